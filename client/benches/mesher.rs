@@ -0,0 +1,73 @@
+//! Benchmarks of subchunk meshing, i.e. the CPU side of re-rendering a subchunk.
+//!
+//! Run with `cargo bench -p wgpu-block-client`.
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use itertools::iproduct;
+use tokio::runtime::Runtime;
+use wgpu_block_client::chunk::ChunkCollection;
+use wgpu_block_client::lod::Lods;
+use wgpu_block_client::mesher::{mesh_subchunk, subchunk_hash, NearbyBlocks};
+use wgpu_block_shared::worldgen::{TerrainGenerator, WorldGenerator};
+
+/// The subchunk that is meshed, at the surface of the generated terrain.
+const SUBCHUNK_START: (i64, i64, i64) = (0, 48, 0);
+
+/// A collection with the chunk at the origin and all of its neighbors loaded, so that every
+/// face and AO sample along the borders is taken.
+fn chunk_collection(runtime: &Runtime) -> ChunkCollection {
+    let mut collection = ChunkCollection::new(0, runtime.handle().clone());
+    let generator = TerrainGenerator::new(0);
+    for (cx, cz) in iproduct!(-1..=1, -1..=1) {
+        collection.insert_chunk((cx, cz), generator.generate((cx, cz)));
+    }
+    collection
+}
+
+fn bench_mesh_subchunk(c: &mut Criterion) {
+    let runtime = Runtime::new().unwrap();
+    let collection = chunk_collection(&runtime);
+    let mut group = c.benchmark_group("mesh_subchunk");
+    for lod in 0..=2 {
+        group.bench_with_input(BenchmarkId::from_parameter(lod), &lod, |b, &lod| {
+            let lods = Lods {
+                own: lod,
+                neighbors: [lod; 4],
+            };
+            b.iter(|| black_box(mesh_subchunk(&collection, SUBCHUNK_START, lods)))
+        });
+    }
+    group.finish();
+}
+
+fn bench_subchunk_hash(c: &mut Criterion) {
+    let runtime = Runtime::new().unwrap();
+    let collection = chunk_collection(&runtime);
+    c.bench_function("subchunk_hash", |b| {
+        b.iter(|| black_box(subchunk_hash(&collection, SUBCHUNK_START, Lods::default())))
+    });
+}
+
+fn bench_nearby_blocks(c: &mut Criterion) {
+    let runtime = Runtime::new().unwrap();
+    let collection = chunk_collection(&runtime);
+    let (x_start, y_start, z_start) = SUBCHUNK_START;
+    c.bench_function("nearby_blocks_subchunk", |b| {
+        b.iter(|| {
+            for (x, y, z) in iproduct!(0..16, 0..16, 0..16) {
+                black_box(NearbyBlocks::new(
+                    (x_start + x, y_start + y, z_start + z),
+                    &collection,
+                ));
+            }
+        })
+    });
+}
+
+criterion_group!(
+    benches,
+    bench_mesh_subchunk,
+    bench_subchunk_hash,
+    bench_nearby_blocks
+);
+criterion_main!(benches);
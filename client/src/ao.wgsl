@@ -0,0 +1,31 @@
+// Bakes per-corner ambient occlusion of a subchunk.
+//
+// The input is the opacity of the 16x16x16 subchunk plus a 1-block halo around it (18x18x18), and
+// the output is the number of opaque blocks around each of the 17x17x17 block corners.
+
+@group(0) @binding(0)
+var opacity: texture_3d<u32>;
+@group(0) @binding(1)
+var corners: texture_storage_3d<r32uint, write>;
+
+@compute @workgroup_size(4, 4, 4)
+fn main_cs(@builtin(global_invocation_id) id: vec3<u32>) {
+    if (any(id >= vec3<u32>(17u, 17u, 17u))) {
+        return;
+    }
+
+    // The 2x2x2 blocks around corner `id` are at `id..=id + 1` in halo coordinates
+    var count = 0u;
+    for (var dx = 0u; dx < 2u; dx = dx + 1u) {
+        for (var dy = 0u; dy < 2u; dy = dy + 1u) {
+            for (var dz = 0u; dz < 2u; dz = dz + 1u) {
+                let pos = vec3<i32>(id + vec3<u32>(dx, dy, dz));
+                count = count + textureLoad(opacity, pos, 0).r;
+            }
+        }
+    }
+
+    textureStore(corners, vec3<i32>(id), vec4<u32>(count, 0u, 0u, 0u));
+}
+
+// vim: set filetype=wgsl:
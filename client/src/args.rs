@@ -0,0 +1,154 @@
+//! Command-line arguments of the client.
+
+use std::path::PathBuf;
+
+use anyhow::{bail, Context, Result};
+
+use crate::quality::{GraphicsOverrides, QualityPreset};
+
+const USAGE: &str = "usage: wgpu-block-client [--seed <seed>] [--quality low|medium|high] \
+                     [--anisotropy 1|2|4|8|16] [--mipmaps on|off] [--ao-strength <0..1>] \
+                     [--msaa 1|4] [--render-distance <1..32>] \
+                     [--vsync fifo|mailbox|immediate] [--max-fps <fps>|off] \
+                     [--record <file> | --replay <file>] [--settings <file>] \
+                     [--bindings <file>] [--assets <dir>]";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Args {
+    /// The seed of the locally generated world.
+    pub seed: u32,
+    pub quality: QualityPreset,
+    /// Graphics settings overriding the ones of the quality preset.
+    pub overrides: GraphicsOverrides,
+    /// The settings file, reloaded by the `reload_settings` action.
+    pub settings: PathBuf,
+    /// The key bindings file, reloaded along with the settings.
+    pub bindings: PathBuf,
+    /// The directory assets are loaded from, and watched for changes.
+    pub assets: PathBuf,
+    /// The file to record input to.
+    pub record: Option<PathBuf>,
+    /// The file to play back recorded input from, instead of handling live input.
+    pub replay: Option<PathBuf>,
+}
+
+impl Args {
+    pub fn parse(mut args: impl Iterator<Item = String>) -> Result<Self> {
+        let mut out = Self {
+            seed: 0,
+            quality: QualityPreset::Medium,
+            overrides: GraphicsOverrides::default(),
+            settings: PathBuf::from("settings.toml"),
+            bindings: PathBuf::from("bindings.toml"),
+            assets: PathBuf::from("assets"),
+            record: None,
+            replay: None,
+        };
+        while let Some(arg) = args.next() {
+            let value = args.next();
+            let value = || value.with_context(|| format!("missing value for {arg}\n{USAGE}"));
+            let bad_value = |value: &str| format!("bad value `{value}` for {arg}");
+            match arg.as_str() {
+                "--seed" => {
+                    let value = value()?;
+                    out.seed = value.parse().with_context(|| bad_value(&value))?;
+                }
+                "--quality" => out.quality = value()?.parse()?,
+                "--anisotropy" => {
+                    let value = value()?;
+                    let anisotropy: u8 = value.parse().with_context(|| bad_value(&value))?;
+                    if !matches!(anisotropy, 1 | 2 | 4 | 8 | 16) {
+                        bail!(bad_value(&value));
+                    }
+                    out.overrides.anisotropy = Some(anisotropy);
+                }
+                "--mipmaps" => {
+                    out.overrides.mipmaps = Some(match value()?.as_str() {
+                        "on" => true,
+                        "off" => false,
+                        value => bail!(bad_value(value)),
+                    })
+                }
+                "--ao-strength" => {
+                    let value = value()?;
+                    let strength: f32 = value.parse().with_context(|| bad_value(&value))?;
+                    if !(0.0..=1.0).contains(&strength) {
+                        bail!(bad_value(&value));
+                    }
+                    out.overrides.ao_strength = Some(strength);
+                }
+                "--msaa" => {
+                    // Only 1 and 4 samples are guaranteed to be supported by every adapter
+                    out.overrides.msaa_samples = Some(match value()?.as_str() {
+                        "1" => 1,
+                        "4" => 4,
+                        value => bail!(bad_value(value)),
+                    })
+                }
+                "--render-distance" => {
+                    let value = value()?;
+                    let distance: u32 = value.parse().with_context(|| bad_value(&value))?;
+                    if !(1..=32).contains(&distance) {
+                        bail!(bad_value(&value));
+                    }
+                    out.overrides.render_distance = Some(distance);
+                }
+                "--vsync" => out.overrides.vsync = Some(value()?.parse()?),
+                "--max-fps" => {
+                    let value = value()?;
+                    out.overrides.max_fps = Some(match value.as_str() {
+                        "off" => None,
+                        _ => match value.parse() {
+                            Ok(fps) if fps > 0 => Some(fps),
+                            _ => bail!(bad_value(&value)),
+                        },
+                    });
+                }
+                "--settings" => out.settings = value()?.into(),
+                "--bindings" => out.bindings = value()?.into(),
+                "--assets" => out.assets = value()?.into(),
+                "--record" => out.record = Some(value()?.into()),
+                "--replay" => out.replay = Some(value()?.into()),
+                _ => bail!("unknown argument `{arg}`\n{USAGE}"),
+            }
+        }
+        if out.record.is_some() && out.replay.is_some() {
+            bail!("--record and --replay can't be used together\n{USAGE}");
+        }
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::quality::VsyncMode;
+
+    fn parse(args: &[&str]) -> Result<Args> {
+        Args::parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn test_parse() {
+        let args = parse(&["--seed", "7", "--quality", "low", "--msaa", "4"]).unwrap();
+        assert_eq!(args.seed, 7);
+        assert_eq!(args.quality, QualityPreset::Low);
+        assert_eq!(args.overrides.msaa_samples, Some(4));
+        assert_eq!(args.overrides.mipmaps, None);
+
+        assert!(parse(&["--msaa", "2"]).is_err());
+        assert!(parse(&["--anisotropy", "3"]).is_err());
+        assert!(parse(&["--ao-strength", "2"]).is_err());
+        assert!(parse(&["--quality"]).is_err());
+
+        let args = parse(&["--vsync", "immediate", "--max-fps", "240"]).unwrap();
+        assert_eq!(args.overrides.vsync, Some(VsyncMode::Immediate));
+        assert_eq!(args.overrides.max_fps, Some(Some(240)));
+        assert_eq!(
+            parse(&["--max-fps", "off"]).unwrap().overrides.max_fps,
+            Some(None)
+        );
+        assert!(parse(&["--max-fps", "0"]).is_err());
+        assert!(parse(&["--record", "a.txt", "--replay", "b.txt"]).is_err());
+    }
+}
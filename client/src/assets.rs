@@ -0,0 +1,175 @@
+//! Assets loaded from a directory at runtime, so that they can be changed without recompiling.
+//!
+//! The asset files are polled for changes, and changed assets are reloaded while the client
+//! runs. Assets missing from the directory, or failing to load, fall back to the copies embedded
+//! in the binary.
+//!
+//! In debug builds, the source of the block shader is watched as well, see [`FileWatcher`].
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+use anyhow::{Context, Result};
+use hashbrown::HashMap;
+use image::RgbaImage;
+use tracing::{info, warn};
+
+/// The source file of the block shader, watched for changes in debug builds. Only exists where
+/// the client was built.
+pub const SHADER_SOURCE_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/src/shader.wgsl");
+
+/// How often the asset files are checked for changes.
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TextureAsset {
+    /// The base texture of all blocks.
+    GrassTop,
+}
+
+impl TextureAsset {
+    pub const ALL: [TextureAsset; 1] = [TextureAsset::GrassTop];
+
+    /// The path of the texture, relative to the assets directory.
+    fn file_name(self) -> &'static str {
+        match self {
+            TextureAsset::GrassTop => "grass-top.png",
+        }
+    }
+
+    fn embedded(self) -> &'static [u8] {
+        match self {
+            TextureAsset::GrassTop => include_bytes!("../assets/grass-top.png"),
+        }
+    }
+}
+
+pub struct Assets {
+    dir: PathBuf,
+    /// The modification times of the texture files when they were last loaded, `None` if the
+    /// file was missing.
+    modified: HashMap<TextureAsset, Option<SystemTime>>,
+    last_poll: Instant,
+}
+
+impl Assets {
+    pub fn new(dir: impl AsRef<Path>) -> Self {
+        Self {
+            dir: dir.as_ref().to_owned(),
+            modified: HashMap::new(),
+            last_poll: Instant::now(),
+        }
+    }
+
+    /// Load a texture from the assets directory, or its embedded copy if that fails.
+    pub fn load_texture(&mut self, asset: TextureAsset) -> RgbaImage {
+        let path = self.dir.join(asset.file_name());
+        let modified = modified_time(&path);
+        self.modified.insert(asset, modified);
+        if modified.is_some() {
+            match load_image(&path) {
+                Ok(img) => {
+                    info!("Loaded texture {}", path.display());
+                    return img;
+                }
+                Err(err) => warn!("{err:#}, using the embedded texture"),
+            }
+        }
+        image::load_from_memory(asset.embedded())
+            .expect("Failed to decode embedded texture")
+            .to_rgba8()
+    }
+
+    /// Get the loaded textures whose files changed since they were loaded. Checks at most once
+    /// every [`POLL_INTERVAL`], and returns nothing in between.
+    pub fn poll_changed(&mut self) -> Vec<TextureAsset> {
+        if self.last_poll.elapsed() < POLL_INTERVAL {
+            return vec![];
+        }
+        self.last_poll = Instant::now();
+        TextureAsset::ALL
+            .into_iter()
+            .filter(|asset| match self.modified.get(asset) {
+                Some(modified) => *modified != modified_time(&self.dir.join(asset.file_name())),
+                None => false,
+            })
+            .collect()
+    }
+}
+
+/// Watches a single file for changes, such as [`SHADER_SOURCE_PATH`].
+pub struct FileWatcher {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+    last_poll: Instant,
+}
+
+impl FileWatcher {
+    pub fn new(path: impl AsRef<Path>) -> Self {
+        let path = path.as_ref().to_owned();
+        Self {
+            modified: modified_time(&path),
+            path,
+            last_poll: Instant::now(),
+        }
+    }
+
+    /// Get the new contents of the file if it changed since the last call. Checks at most once
+    /// every [`POLL_INTERVAL`].
+    pub fn poll_changed(&mut self) -> Option<Result<String>> {
+        if self.last_poll.elapsed() < POLL_INTERVAL {
+            return None;
+        }
+        self.last_poll = Instant::now();
+        let modified = modified_time(&self.path);
+        if modified == self.modified {
+            return None;
+        }
+        self.modified = modified;
+        let contents = fs::read_to_string(&self.path)
+            .with_context(|| format!("Failed to read {}", self.path.display()));
+        Some(contents)
+    }
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|meta| meta.modified()).ok()
+}
+
+fn load_image(path: &Path) -> Result<RgbaImage> {
+    let img = image::open(path).with_context(|| format!("Failed to load {}", path.display()))?;
+    Ok(img.to_rgba8())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_reload_changed() {
+        let dir = std::env::temp_dir().join(format!("wgpu-block-assets-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        // Falls back to the embedded texture
+        let mut assets = Assets::new(&dir);
+        let embedded = assets.load_texture(TextureAsset::GrassTop);
+        assets.last_poll -= POLL_INTERVAL;
+        assert!(assets.poll_changed().is_empty());
+
+        RgbaImage::new(4, 4)
+            .save(dir.join(TextureAsset::GrassTop.file_name()))
+            .unwrap();
+        assert!(assets.poll_changed().is_empty(), "polled too soon");
+        assets.last_poll -= POLL_INTERVAL;
+        assert_eq!(assets.poll_changed(), vec![TextureAsset::GrassTop]);
+        let img = assets.load_texture(TextureAsset::GrassTop);
+        assert_eq!(img.dimensions(), (4, 4));
+        assert_ne!(img.dimensions(), embedded.dimensions());
+        assets.last_poll -= POLL_INTERVAL;
+        assert!(assets.poll_changed().is_empty());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,239 @@
+//! Key bindings, translating pressed keys into logical [`Action`]s.
+//!
+//! Bindings can be changed in a flat TOML file of `action = Key` lines, where keys are named
+//! after [`VirtualKeyCode`] variants:
+//!
+//! ```toml
+//! move_up = Space
+//! toggle_cursor = Tab
+//! ```
+//!
+//! Actions missing from the file keep their default keys.
+
+use std::fmt::Display;
+use std::path::Path;
+use std::str::FromStr;
+
+use anyhow::{bail, Context, Error, Result};
+use hashbrown::HashMap;
+use winit::event::VirtualKeyCode;
+
+use crate::settings::parse_key_values;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Action {
+    MoveForward,
+    MoveBackward,
+    MoveLeft,
+    MoveRight,
+    MoveUp,
+    MoveDown,
+    /// Grab or release the cursor.
+    ToggleCursor,
+    /// Copy the position of the targeted block to the clipboard.
+    CopyTarget,
+    ToggleDebug,
+    ToggleChunkLabels,
+    ToggleWireframe,
+    ToggleSubchunkPanel,
+    CycleSubchunkSort,
+    CycleSubchunkFilter,
+    CycleHeatmap,
+    CycleQuality,
+    CycleVsync,
+    ToggleFullscreen,
+    /// Reload the settings and the bindings from their files.
+    ReloadSettings,
+}
+
+impl Action {
+    pub const ALL: [Action; 19] = [
+        Action::MoveForward,
+        Action::MoveBackward,
+        Action::MoveLeft,
+        Action::MoveRight,
+        Action::MoveUp,
+        Action::MoveDown,
+        Action::ToggleCursor,
+        Action::CopyTarget,
+        Action::ToggleDebug,
+        Action::ToggleChunkLabels,
+        Action::ToggleWireframe,
+        Action::ToggleSubchunkPanel,
+        Action::CycleSubchunkSort,
+        Action::CycleSubchunkFilter,
+        Action::CycleHeatmap,
+        Action::CycleQuality,
+        Action::CycleVsync,
+        Action::ToggleFullscreen,
+        Action::ReloadSettings,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Action::MoveForward => "move_forward",
+            Action::MoveBackward => "move_backward",
+            Action::MoveLeft => "move_left",
+            Action::MoveRight => "move_right",
+            Action::MoveUp => "move_up",
+            Action::MoveDown => "move_down",
+            Action::ToggleCursor => "toggle_cursor",
+            Action::CopyTarget => "copy_target",
+            Action::ToggleDebug => "toggle_debug",
+            Action::ToggleChunkLabels => "toggle_chunk_labels",
+            Action::ToggleWireframe => "toggle_wireframe",
+            Action::ToggleSubchunkPanel => "toggle_subchunk_panel",
+            Action::CycleSubchunkSort => "cycle_subchunk_sort",
+            Action::CycleSubchunkFilter => "cycle_subchunk_filter",
+            Action::CycleHeatmap => "cycle_heatmap",
+            Action::CycleQuality => "cycle_quality",
+            Action::CycleVsync => "cycle_vsync",
+            Action::ToggleFullscreen => "toggle_fullscreen",
+            Action::ReloadSettings => "reload_settings",
+        }
+    }
+
+    fn default_key(self) -> VirtualKeyCode {
+        match self {
+            Action::MoveForward => VirtualKeyCode::W,
+            Action::MoveBackward => VirtualKeyCode::S,
+            Action::MoveLeft => VirtualKeyCode::A,
+            Action::MoveRight => VirtualKeyCode::D,
+            Action::MoveUp => VirtualKeyCode::Space,
+            Action::MoveDown => VirtualKeyCode::LShift,
+            Action::ToggleCursor => VirtualKeyCode::G,
+            Action::CopyTarget => VirtualKeyCode::C,
+            Action::ToggleDebug => VirtualKeyCode::F3,
+            Action::ToggleChunkLabels => VirtualKeyCode::F4,
+            Action::ToggleWireframe => VirtualKeyCode::F5,
+            Action::ToggleSubchunkPanel => VirtualKeyCode::F6,
+            Action::CycleQuality => VirtualKeyCode::F7,
+            Action::CycleSubchunkSort => VirtualKeyCode::F8,
+            Action::CycleSubchunkFilter => VirtualKeyCode::F9,
+            Action::CycleHeatmap => VirtualKeyCode::H,
+            Action::CycleVsync => VirtualKeyCode::F10,
+            Action::ToggleFullscreen => VirtualKeyCode::F11,
+            Action::ReloadSettings => VirtualKeyCode::F12,
+        }
+    }
+}
+
+impl Display for Action {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Action {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match Action::ALL.into_iter().find(|action| action.name() == s) {
+            Some(action) => Ok(action),
+            None => bail!("unknown action `{s}`"),
+        }
+    }
+}
+
+macro_rules! key_names {
+    ($($key:ident),* $(,)?) => {
+        [$((VirtualKeyCode::$key, stringify!($key))),*]
+    };
+}
+
+/// The keys that can be bound, by name.
+const KEY_NAMES: [(VirtualKeyCode, &str); 63] = key_names![
+    A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P, Q, R, S, T, U, V, W, X, Y, Z, Key0, Key1, Key2,
+    Key3, Key4, Key5, Key6, Key7, Key8, Key9, F1, F2, F3, F4, F5, F6, F7, F8, F9, F10, F11, F12,
+    Space, Tab, Return, Back, Escape, LShift, RShift, LControl, RControl, LAlt, RAlt, Up, Down,
+    Left, Right,
+];
+
+fn parse_key(name: &str) -> Result<VirtualKeyCode> {
+    match KEY_NAMES.iter().find(|(_, n)| *n == name) {
+        Some((key, _)) => Ok(*key),
+        None => bail!("unknown key `{name}`"),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Bindings {
+    actions: HashMap<VirtualKeyCode, Action>,
+}
+
+impl Default for Bindings {
+    fn default() -> Self {
+        Self {
+            actions: Action::ALL
+                .into_iter()
+                .map(|action| (action.default_key(), action))
+                .collect(),
+        }
+    }
+}
+
+impl Bindings {
+    pub fn load(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read bindings {}", path.display()))?;
+        Self::parse(&text).with_context(|| format!("Bad bindings {}", path.display()))
+    }
+
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut bindings = Self::default();
+        for (line_no, action, key) in parse_key_values(text)? {
+            let action: Action = action.parse().with_context(|| format!("line {line_no}"))?;
+            // Keys may be quoted, as TOML strings
+            let key =
+                parse_key(key.trim_matches('"')).with_context(|| format!("line {line_no}"))?;
+            bindings.bind(key, action);
+        }
+        Ok(bindings)
+    }
+
+    /// Bind `key` to `action`, replacing any previous binding of either.
+    fn bind(&mut self, key: VirtualKeyCode, action: Action) {
+        self.actions.retain(|_, bound| *bound != action);
+        self.actions.insert(key, action);
+    }
+
+    /// Get the action bound to `key`, if any.
+    pub fn action(&self, key: VirtualKeyCode) -> Option<Action> {
+        self.actions.get(&key).copied()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_default_bindings() {
+        let bindings = Bindings::default();
+        assert_eq!(bindings.actions.len(), Action::ALL.len());
+        for action in Action::ALL {
+            assert_eq!(bindings.action(action.default_key()), Some(action));
+            assert_eq!(action.name().parse::<Action>().unwrap(), action);
+        }
+    }
+
+    #[test]
+    fn test_parse() {
+        let bindings = Bindings::parse("toggle_cursor = Tab # grab\nmove_up = \"F3\"\n").unwrap();
+        assert_eq!(
+            bindings.action(VirtualKeyCode::Tab),
+            Some(Action::ToggleCursor)
+        );
+        assert_eq!(bindings.action(VirtualKeyCode::G), None);
+        // Taking the key of another action unbinds that action
+        assert_eq!(bindings.action(VirtualKeyCode::F3), Some(Action::MoveUp));
+        assert_eq!(bindings.action(VirtualKeyCode::Space), None);
+
+        assert!(Bindings::parse("fly = Space").is_err());
+        assert!(Bindings::parse("move_up = Spacebar").is_err());
+        assert!(Bindings::parse("move_up").is_err());
+    }
+}
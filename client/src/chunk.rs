@@ -1,16 +1,30 @@
 //! Primitives related to chunks and blocks.
 
-use hashbrown::HashMap;
-use itertools::Itertools;
-use noise::{NoiseFn, OpenSimplex};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Arc;
+
+use hashbrown::{HashMap, HashSet};
+use itertools::{iproduct, Itertools};
+use tokio::runtime::Handle;
 use tracing::info;
 
 pub use wgpu_block_shared::chunk::Block;
 use wgpu_block_shared::chunk::Chunk;
+use wgpu_block_shared::worldgen::{TerrainGenerator, WorldGenerator};
+
+/// The maximum number of chunks generated in the background at once, so that moving into
+/// unexplored terrain doesn't take up every blocking thread.
+const MAX_GENERATING: usize = 4;
 
 /// A collection of chunks, indexed by their chunk coordinates `(cx, cz)`.
 pub struct ChunkCollection {
     chunks: HashMap<(i64, i64), ClientChunk>,
+    generator: Arc<TerrainGenerator>,
+    handle: Handle,
+    /// Chunks being generated on blocking threads, which send them back through `generated_tx`.
+    generating: HashSet<(i64, i64)>,
+    generated_tx: Sender<((i64, i64), Chunk)>,
+    generated_rx: Receiver<((i64, i64), Chunk)>,
 }
 
 #[derive(Clone, Copy)]
@@ -20,57 +34,99 @@ pub enum MaybeLoadedBlock {
 }
 
 impl ChunkCollection {
-    pub fn new() -> Self {
-        let mut chunks = HashMap::new();
-        let simplex = OpenSimplex::new(0);
-
-        let mut maxheight = 0;
-        for cx in -3..3_i64 {
-            for cz in -3..3_i64 {
-                info!("Generating chunk ({cx}, {cz})");
-
-                let mut chunk = ClientChunk::default();
-                chunk.dirty = [true; 16];
-                for lx in 0..16 {
-                    for lz in 0..16 {
-                        let height = (simplex
-                            .get([(cx * 16 + lx) as f64 / 16.0, (cz * 16 + lz) as f64 / 16.0])
-                            + 1.0)
-                            * 10.0
-                            + 26.0;
-                        let height = height as usize;
-                        info!("Height at (lx = {lx}, lz = {lz}) is {height}");
-                        maxheight = maxheight.max(height);
-                        for h in 0..height {
-                            chunk.set((lx as usize, h, lz as usize), Block::Grass);
-                        }
-                    }
-                }
-                chunks.insert((cx, cz), chunk);
+    pub fn new(seed: u32, handle: Handle) -> Self {
+        let (generated_tx, generated_rx) = mpsc::channel();
+        Self {
+            chunks: HashMap::new(),
+            generator: Arc::new(TerrainGenerator::new(seed)),
+            handle,
+            generating: HashSet::new(),
+            generated_tx,
+            generated_rx,
+        }
+    }
+
+    /// Load the chunks within `render_distance` chunks of the chunk `center`, nearest first, and
+    /// unload the ones that are too far away. Returns the coordinates of the unloaded chunks.
+    ///
+    /// Chunks are generated in the background, and loaded by the first call after they are done.
+    ///
+    /// Chunks are only unloaded one chunk beyond the render distance, so that moving back and
+    /// forth across a chunk border doesn't load and unload the same chunks over and over.
+    pub fn update_loaded(&mut self, center: (i64, i64), render_distance: u32) -> Vec<(i64, i64)> {
+        let distance_sq = |(cx, cz): (i64, i64)| {
+            let (dx, dz) = (cx - center.0, cz - center.1);
+            dx * dx + dz * dz
+        };
+        let load_radius = render_distance as i64;
+        let unload_radius = load_radius + 1;
+
+        let generated = self.generated_rx.try_iter().collect_vec();
+        for (coords, chunk) in generated {
+            self.generating.remove(&coords);
+            // The camera may have moved away while the chunk was generated
+            if distance_sq(coords) > unload_radius * unload_radius {
+                continue;
             }
+            self.insert_chunk(coords, chunk);
         }
 
-        info!(maxheight);
+        let unloaded = self
+            .chunks
+            .keys()
+            .cloned()
+            .filter(|&coords| distance_sq(coords) > unload_radius * unload_radius)
+            .collect_vec();
+        for coords in &unloaded {
+            self.chunks.remove(coords);
+        }
 
-        Self { chunks }
+        let missing = iproduct!(-load_radius..=load_radius, -load_radius..=load_radius)
+            .map(|(dx, dz)| (center.0 + dx, center.1 + dz))
+            .filter(|&coords| distance_sq(coords) <= load_radius * load_radius)
+            .filter(|coords| !self.chunks.contains_key(coords) && !self.generating.contains(coords))
+            .sorted_by_key(|&coords| distance_sq(coords))
+            .take(MAX_GENERATING.saturating_sub(self.generating.len()))
+            .collect_vec();
+        for (cx, cz) in missing {
+            info!("Generating chunk ({cx}, {cz})");
+            self.generating.insert((cx, cz));
+            let generator = self.generator.clone();
+            let generated_tx = self.generated_tx.clone();
+            self.handle.spawn_blocking(move || {
+                // The collection may be gone by the time the chunk is done
+                let _ = generated_tx.send(((cx, cz), generator.generate((cx, cz))));
+            });
+        }
+
+        unloaded
     }
 
-    /// Get a chunk from its chunk coordinates `(cx, cz)`.
-    ///
-    /// # Panics
-    ///
-    /// Panics if the chunk is nonexistent.
-    pub fn get_chunk(&self, (cx, cz): (i64, i64)) -> &ClientChunk {
-        &self.chunks[&(cx, cz)]
+    /// Load `chunk` at chunk coordinates `(cx, cz)`, marking it and its neighbors dirty.
+    pub fn insert_chunk(&mut self, (cx, cz): (i64, i64), chunk: Chunk) {
+        self.chunks.insert(
+            (cx, cz),
+            ClientChunk {
+                chunk,
+                dirty: [true; 16],
+            },
+        );
+
+        // Faces and AO along the border to the new chunk have changed. The AO halo of a
+        // subchunk includes its corners, so diagonal neighbors are affected as well.
+        for (dx, dz) in iproduct!(-1..=1, -1..=1) {
+            if (dx, dz) == (0, 0) {
+                continue;
+            }
+            if let Some(neighbor) = self.chunks.get_mut(&(cx + dx, cz + dz)) {
+                neighbor.dirty = [true; 16];
+            }
+        }
     }
 
-    /// Get a chunk mutably from its chunk coordinates `(cx, cz)`.
-    ///
-    /// # Panics
-    ///
-    /// Panics if the chunk is nonexistent.
-    pub fn get_chunk_mut(&mut self, (cx, cz): (i64, i64)) -> &mut ClientChunk {
-        self.chunks.get_mut(&(cx, cz)).unwrap()
+    /// Get a chunk mutably from its chunk coordinates `(cx, cz)`, or `None` if it isn't loaded.
+    pub fn get_chunk_mut(&mut self, (cx, cz): (i64, i64)) -> Option<&mut ClientChunk> {
+        self.chunks.get_mut(&(cx, cz))
     }
 
     /// Get a block from its *world* coordinates.
@@ -97,6 +153,51 @@ impl ChunkCollection {
         MaybeLoadedBlock::Loaded(chunk.get((lx, ly, lz)))
     }
 
+    /// Get the y above the highest visible block of the column at *world* coordinates `(x, z)`,
+    /// or `None` if its chunk isn't loaded.
+    pub fn get_height(&self, (x, z): (i64, i64)) -> Option<i64> {
+        let chunk = self.chunks.get(&(x.div_euclid(16), z.div_euclid(16)))?;
+        Some(
+            chunk
+                .chunk
+                .height((x.rem_euclid(16) as usize, z.rem_euclid(16) as usize)) as i64,
+        )
+    }
+
+    pub fn loaded_chunk_count(&self) -> usize {
+        self.chunks.len()
+    }
+
+    /// Get the number of subchunks waiting to be re-rendered.
+    pub fn dirty_subchunk_count(&self) -> usize {
+        self.chunks
+            .values()
+            .map(|chunk| chunk.dirty.iter().filter(|d| **d).count())
+            .sum()
+    }
+
+    /// Get the subchunks waiting to be re-rendered as `(cx, s, cz)`, nearest to the subchunk
+    /// `camera` first.
+    pub fn dirty_subchunks(
+        &self,
+        (camera_x, camera_s, camera_z): (i64, i64, i64),
+    ) -> Vec<(i64, i64, i64)> {
+        let mut dirty = self
+            .chunks
+            .iter()
+            .flat_map(|(&(cx, cz), chunk)| {
+                (0..16)
+                    .filter(|s| chunk.dirty[*s as usize])
+                    .map(move |s| (cx, s, cz))
+            })
+            .collect_vec();
+        dirty.sort_by_key(|&(cx, s, cz)| {
+            let (dx, ds, dz) = (cx - camera_x, s - camera_s, cz - camera_z);
+            dx * dx + ds * ds + dz * dz
+        });
+        dirty
+    }
+
     /// Get chunk coordinates of all the loaded chunks.
     pub fn loaded_chunk_coordinates(&self) -> Vec<(i64, i64)> {
         self.chunks.keys().cloned().collect_vec()
@@ -110,10 +211,6 @@ pub struct ClientChunk {
 }
 
 impl ClientChunk {
-    pub fn set(&mut self, (x, y, z): (usize, usize, usize), block: Block) {
-        self.chunk.set((x, y, z), block)
-    }
-
     pub fn get(&self, (x, y, z): (usize, usize, usize)) -> Block {
         self.chunk.get((x, y, z))
     }
@@ -125,15 +222,103 @@ impl ClientChunk {
     pub fn unmark_subchunk_dirty(&mut self, s: usize) {
         self.dirty[s] = false;
     }
+
+    /// Mark all subchunks dirty, e.g. when they're to be meshed at another level of detail.
+    pub fn mark_dirty(&mut self) {
+        self.dirty = [true; 16];
+    }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
 
+    use std::thread;
+    use std::time::Duration;
+    use tokio::runtime::Runtime;
+
     #[test]
     fn test_chunk_collection_new() {
         tracing_subscriber::fmt::init();
-        ChunkCollection::new();
+        let runtime = Runtime::new().unwrap();
+        ChunkCollection::new(0, runtime.handle().clone());
+    }
+
+    #[test]
+    fn test_update_loaded() {
+        let runtime = Runtime::new().unwrap();
+        let mut collection = ChunkCollection::new(0, runtime.handle().clone());
+        for _ in 0..1000 {
+            collection.update_loaded((0, 0), 1);
+            if collection.loaded_chunk_count() == 5 {
+                break;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert!(collection.generating.is_empty());
+        let mut loaded = collection.loaded_chunk_coordinates();
+        loaded.sort();
+        assert_eq!(loaded, vec![(-1, 0), (0, -1), (0, 0), (0, 1), (1, 0)]);
+
+        // Chunks within one chunk beyond the render distance are kept
+        assert!(collection.update_loaded((1, 0), 1).is_empty());
+        let mut unloaded = collection.update_loaded((2, 0), 1);
+        unloaded.sort();
+        assert_eq!(unloaded, vec![(-1, 0), (0, -1), (0, 1)]);
+    }
+
+    #[test]
+    fn test_diagonal_neighbor_dirty() {
+        let runtime = Runtime::new().unwrap();
+        let mut collection = ChunkCollection::new(0, runtime.handle().clone());
+        let load_around = |collection: &mut ChunkCollection, center, coords: (i64, i64)| {
+            for _ in 0..1000 {
+                collection.update_loaded(center, 1);
+                if collection.generating.is_empty() && collection.chunks.contains_key(&coords) {
+                    break;
+                }
+                thread::sleep(Duration::from_millis(10));
+            }
+        };
+        load_around(&mut collection, (0, 0), (0, 0));
+        for chunk in collection.chunks.values_mut() {
+            chunk.dirty = [false; 16];
+        }
+        assert!(collection.dirty_subchunks((0, 0, 0)).is_empty());
+
+        // (1, 1) only touches (0, 0) at a corner
+        load_around(&mut collection, (1, 1), (1, 1));
+        assert!(collection
+            .get_chunk_mut((0, 0))
+            .unwrap()
+            .is_subchunk_dirty(15));
+
+        // Nearest first
+        let dirty = collection.dirty_subchunks((1, 4, 1));
+        assert_eq!(dirty.len(), collection.dirty_subchunk_count());
+        assert_eq!(dirty[0], (1, 4, 1));
+        assert_eq!(dirty.last(), Some(&(0, 15, 0)));
+    }
+
+    #[test]
+    fn test_get_unloaded_chunk() {
+        let runtime = Runtime::new().unwrap();
+        let mut collection = ChunkCollection::new(0, runtime.handle().clone());
+        for _ in 0..1000 {
+            collection.update_loaded((0, 0), 0);
+            if collection.loaded_chunk_count() == 1 {
+                break;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert!(collection
+            .get_chunk_mut((0, 0))
+            .unwrap()
+            .is_subchunk_dirty(0));
+
+        // The chunk is unloaded while its subchunks are still dirty
+        assert_eq!(collection.update_loaded((5, 0), 0), vec![(0, 0)]);
+        assert!(collection.get_chunk_mut((0, 0)).is_none());
+        assert_eq!(collection.dirty_subchunk_count(), 0);
     }
 }
@@ -0,0 +1,66 @@
+//! Copying text to the system clipboard.
+//!
+//! Rather than talking to each windowing system directly, the text is piped into the clipboard
+//! tool of the platform.
+
+use std::io::Write;
+use std::process::{Child, Command, Stdio};
+
+use anyhow::{bail, Context, Result};
+
+/// Clipboard tools with their arguments, tried in order until one of them succeeds.
+const TOOLS: &[(&str, &[&str])] = if cfg!(target_os = "windows") {
+    &[("clip", &[])]
+} else if cfg!(target_os = "macos") {
+    &[("pbcopy", &[])]
+} else {
+    &[
+        ("wl-copy", &[]),
+        ("xclip", &["-selection", "clipboard"]),
+        ("xsel", &["--clipboard", "--input"]),
+    ]
+};
+
+/// Put `text` on the system clipboard.
+pub fn copy(text: &str) -> Result<()> {
+    let mut last_error = None;
+    for (program, args) in TOOLS {
+        let child = Command::new(program)
+            .args(*args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn();
+        // Try the next tool if this one isn't installed
+        let child = match child {
+            Ok(child) => child,
+            Err(_) => continue,
+        };
+        // Or if it can't reach the clipboard, e.g. `wl-copy` outside of Wayland
+        match pipe_into(program, child, text) {
+            Ok(()) => return Ok(()),
+            Err(e) => last_error = Some(e),
+        }
+    }
+    if let Some(e) = last_error {
+        return Err(e);
+    }
+    let names: Vec<_> = TOOLS.iter().map(|(program, _)| *program).collect();
+    bail!("No clipboard tool found, tried {}", names.join(", "))
+}
+
+/// Write `text` to the stdin of the clipboard tool `program` running as `child`, and wait for
+/// it to succeed.
+fn pipe_into(program: &str, mut child: Child, text: &str) -> Result<()> {
+    child
+        .stdin
+        .take()
+        .context("Clipboard tool has no stdin")?
+        .write_all(text.as_bytes())
+        .with_context(|| format!("Failed to write to {program}"))?;
+    let status = child.wait()?;
+    if !status.success() {
+        bail!("{program} exited with {status}");
+    }
+    Ok(())
+}
@@ -0,0 +1,562 @@
+//! The F3 debug overlay, showing frame timing, camera and chunk statistics, and the other debug
+//! panels.
+
+use std::cmp::Reverse;
+use std::time::{Duration, Instant};
+
+use glam::{vec3, Mat4, Vec3};
+use itertools::iproduct;
+use winit::dpi::PhysicalSize;
+
+use wgpu_block_shared::raycast::RaycastHit;
+
+use crate::chunk::Block;
+use crate::overlay::{text_size, Color, OverlayBuffer, TRANSLUCENT_WHITE, WHITE, YELLOW};
+use crate::render::{RenderStats, SubchunkStats};
+
+/// Smoothing factor of the frame time moving average.
+const FRAME_TIME_WEIGHT: f32 = 0.1;
+
+const ORIGIN: (f32, f32) = (8.0, 8.0);
+const TEXT_SCALE: f32 = 2.0;
+
+/// How many chunks and subchunks away from the camera subchunk get labeled, along each axis.
+const LABEL_RADIUS: i64 = 1;
+
+/// Values shown by the debug overlay, gathered by the main loop once per frame.
+pub struct DebugInfo {
+    pub eye: Vec3,
+    /// Pitch in radians.
+    pub pitch: f32,
+    /// Yaw in radians.
+    pub yaw: f32,
+    pub loaded_chunks: usize,
+    pub render_stats: RenderStats,
+    /// The number of dirty subchunks waiting to be re-rendered.
+    pub pending_meshes: usize,
+    /// In-game hours into the day.
+    pub hours: f32,
+    /// The view-projection matrix, for labeling positions in the world.
+    pub view_proj: Mat4,
+    pub screen_size: PhysicalSize<u32>,
+    /// The block the camera is looking at.
+    pub target: Option<(RaycastHit, Block)>,
+}
+
+pub struct DebugOverlay {
+    visible: bool,
+    labels_visible: bool,
+    last_frame: Instant,
+    /// Moving average of frame times.
+    frame_time: Duration,
+}
+
+impl Default for DebugOverlay {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DebugOverlay {
+    pub fn new() -> Self {
+        Self {
+            visible: false,
+            labels_visible: false,
+            last_frame: Instant::now(),
+            frame_time: Duration::ZERO,
+        }
+    }
+
+    pub fn toggle(&mut self) {
+        self.visible = !self.visible;
+    }
+
+    /// Toggle the in-world chunk coordinate labels.
+    pub fn toggle_labels(&mut self) {
+        self.labels_visible = !self.labels_visible;
+    }
+
+    /// Record the end of a frame for the frame time measurement.
+    pub fn end_frame(&mut self) {
+        let elapsed = self.last_frame.elapsed();
+        self.last_frame = Instant::now();
+        self.frame_time = if self.frame_time.is_zero() {
+            elapsed
+        } else {
+            self.frame_time.mul_f32(1.0 - FRAME_TIME_WEIGHT) + elapsed.mul_f32(FRAME_TIME_WEIGHT)
+        };
+    }
+
+    /// Get the average frame rate.
+    pub fn fps(&self) -> f32 {
+        if self.frame_time.is_zero() {
+            0.0
+        } else {
+            1.0 / self.frame_time.as_secs_f32()
+        }
+    }
+
+    /// Push the overlay to `overlay` if it's visible.
+    pub fn push(&self, overlay: &mut OverlayBuffer, info: &DebugInfo) {
+        if self.labels_visible {
+            push_chunk_labels(overlay, info);
+        }
+        if !self.visible {
+            return;
+        }
+
+        let frame_ms = self.frame_time.as_secs_f32() * 1000.0;
+        let fps = self.fps();
+        let RenderStats {
+            drawn_buffers,
+            culled_buffers,
+            gpu_timings,
+        } = info.render_stats;
+        let gpu = match gpu_timings {
+            Some(timings) => format!(
+                "{:.2} MS (AO {:.2}, TERRAIN {:.2}, OVERLAY {:.2})",
+                timings.total().as_secs_f32() * 1000.0,
+                timings.ao_bake.as_secs_f32() * 1000.0,
+                timings.terrain.as_secs_f32() * 1000.0,
+                timings.overlay.as_secs_f32() * 1000.0,
+            ),
+            None => "N/A".to_owned(),
+        };
+        let target = match info.target {
+            Some((hit, block)) => format!("{} {} {} {block}", hit.pos.0, hit.pos.1, hit.pos.2),
+            None => "NONE".to_owned(),
+        };
+        let text = format!(
+            "{fps:.0} FPS ({frame_ms:.2} MS)\n\
+             GPU: {gpu}\n\
+             XYZ: {:.2} / {:.2} / {:.2}\n\
+             YAW: {:.1}  PITCH: {:.1}\n\
+             CHUNKS: {}\n\
+             SUBCHUNKS: {drawn_buffers} DRAWN, {culled_buffers} CULLED\n\
+             MESHING: {} PENDING\n\
+             TIME: {:02}:{:02}\n\
+             TARGET: {target}",
+            info.eye.x,
+            info.eye.y,
+            info.eye.z,
+            info.yaw.to_degrees(),
+            info.pitch.to_degrees(),
+            info.loaded_chunks,
+            info.pending_meshes,
+            info.hours as u32,
+            (info.hours.fract() * 60.0) as u32,
+        );
+        overlay.push_text_panel(ORIGIN, &text, TEXT_SCALE, WHITE);
+    }
+}
+
+/// Label the subchunk corners around the camera with their `(cx, s, cz)` coordinates, draw the
+/// chunk borders between them, and outline the subchunk the camera is in.
+fn push_chunk_labels(overlay: &mut OverlayBuffer, info: &DebugInfo) {
+    let project = |pos: Vec3| project_to_screen(info.view_proj, info.screen_size, pos);
+    let [cx, s, cz] = (info.eye / 16.0).floor().to_array().map(|v| v as i64);
+
+    for (dx, ds, dz) in iproduct!(
+        -LABEL_RADIUS..=LABEL_RADIUS + 1,
+        -LABEL_RADIUS..=LABEL_RADIUS + 1,
+        -LABEL_RADIUS..=LABEL_RADIUS + 1
+    ) {
+        let (lx, ls, lz) = (cx + dx, s + ds, cz + dz);
+        if !(0..16).contains(&ls) {
+            continue;
+        }
+        let corner = vec3(lx as f32, ls as f32, lz as f32) * 16.0;
+        if let Some((x, y)) = project(corner) {
+            let text = format!("{lx},{ls},{lz}");
+            overlay.push_text_panel((x, y), &text, TEXT_SCALE, WHITE);
+        }
+
+        // The vertical chunk border going up from the corner, skipped if partly behind the camera
+        let top = corner + vec3(0.0, 16.0, 0.0);
+        if let (Some(a), Some(b)) = (project(corner), project(top)) {
+            overlay.push_line(a, b, 1.0, TRANSLUCENT_WHITE);
+        }
+    }
+
+    // Outline the camera subchunk, skipping edges that are partly behind the camera
+    let min = vec3(cx as f32, s as f32, cz as f32) * 16.0;
+    let corner = |(x, y, z): Corner| min + vec3(x as f32, y as f32, z as f32) * 16.0;
+    for (a, b) in CUBE_EDGES {
+        if let (Some(a), Some(b)) = (project(corner(a)), project(corner(b))) {
+            overlay.push_line(a, b, 2.0, YELLOW);
+        }
+    }
+}
+
+/// A corner of a unit cube.
+type Corner = (u8, u8, u8);
+
+/// Edges of a unit cube, as pairs of corners.
+const CUBE_EDGES: [(Corner, Corner); 12] = [
+    ((0, 0, 0), (1, 0, 0)),
+    ((0, 0, 1), (1, 0, 1)),
+    ((0, 1, 0), (1, 1, 0)),
+    ((0, 1, 1), (1, 1, 1)),
+    ((0, 0, 0), (0, 1, 0)),
+    ((1, 0, 0), (1, 1, 0)),
+    ((0, 0, 1), (0, 1, 1)),
+    ((1, 0, 1), (1, 1, 1)),
+    ((0, 0, 0), (0, 0, 1)),
+    ((1, 0, 0), (1, 0, 1)),
+    ((0, 1, 0), (0, 1, 1)),
+    ((1, 1, 0), (1, 1, 1)),
+];
+
+/// Project a world position to overlay coordinates, or `None` if it's behind the camera.
+fn project_to_screen(view_proj: Mat4, size: PhysicalSize<u32>, pos: Vec3) -> Option<(f32, f32)> {
+    let clip = view_proj * pos.extend(1.0);
+    if clip.w <= 0.0 {
+        return None;
+    }
+    let ndc = clip.truncate() / clip.w;
+    Some((
+        (ndc.x + 1.0) / 2.0 * size.width as f32,
+        (1.0 - ndc.y) / 2.0 * size.height as f32,
+    ))
+}
+
+/// The radius of the area around the camera covered by the heatmap, in chunks.
+const HEATMAP_RADIUS: i64 = 4;
+
+/// How long ago a subchunk must have been uploaded to be shown cold by
+/// [`HeatmapMetric::UploadAge`].
+const HEATMAP_MAX_AGE: Duration = Duration::from_secs(5);
+
+/// Faces of a unit cube, as corners in winding order.
+const CUBE_FACES: [[Corner; 4]; 6] = [
+    [(0, 0, 0), (1, 0, 0), (1, 1, 0), (0, 1, 0)],
+    [(0, 0, 1), (1, 0, 1), (1, 1, 1), (0, 1, 1)],
+    [(0, 0, 0), (0, 1, 0), (0, 1, 1), (0, 0, 1)],
+    [(1, 0, 0), (1, 1, 0), (1, 1, 1), (1, 0, 1)],
+    [(0, 0, 0), (1, 0, 0), (1, 0, 1), (0, 0, 1)],
+    [(0, 1, 0), (1, 1, 0), (1, 1, 1), (0, 1, 1)],
+];
+
+/// The metric the heatmap colors subchunks by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HeatmapMetric {
+    /// The number of indices of the mesh.
+    MeshSize,
+    /// How recently the mesh was uploaded.
+    UploadAge,
+    /// How many times the mesh was uploaded, to spot redundant re-meshing.
+    UploadCount,
+}
+
+/// Colors the subchunks around the camera by a [`HeatmapMetric`], from blue (cold) to red (hot).
+pub struct SubchunkHeatmap {
+    metric: Option<HeatmapMetric>,
+}
+
+impl Default for SubchunkHeatmap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SubchunkHeatmap {
+    pub fn new() -> Self {
+        Self { metric: None }
+    }
+
+    /// Cycle through the metrics, and then to hiding the heatmap.
+    pub fn cycle(&mut self) -> Option<HeatmapMetric> {
+        self.metric = match self.metric {
+            None => Some(HeatmapMetric::MeshSize),
+            Some(HeatmapMetric::MeshSize) => Some(HeatmapMetric::UploadAge),
+            Some(HeatmapMetric::UploadAge) => Some(HeatmapMetric::UploadCount),
+            Some(HeatmapMetric::UploadCount) => None,
+        };
+        self.metric
+    }
+
+    pub fn is_visible(&self) -> bool {
+        self.metric.is_some()
+    }
+
+    /// Push the heatmap to `overlay` if it's visible.
+    pub fn push(&self, overlay: &mut OverlayBuffer, stats: &[SubchunkStats], info: &DebugInfo) {
+        let metric = match self.metric {
+            Some(metric) => metric,
+            None => return,
+        };
+        let project = |pos: Vec3| project_to_screen(info.view_proj, info.screen_size, pos);
+        let (cx, cz) = (
+            (info.eye.x / 16.0).floor() as i64,
+            (info.eye.z / 16.0).floor() as i64,
+        );
+        let nearby: Vec<_> = stats
+            .iter()
+            .filter(|stats| {
+                let (x, _, z) = stats.key;
+                (x - cx).abs() <= HEATMAP_RADIUS && (z - cz).abs() <= HEATMAP_RADIUS
+            })
+            .collect();
+
+        let now = Instant::now();
+        let max = nearby
+            .iter()
+            .map(|stats| heat_value(metric, stats, now))
+            .fold(0.0, f32::max);
+        if max <= 0.0 {
+            return;
+        }
+        for stats in nearby {
+            let heat = heat_value(metric, stats, now) / max;
+            if heat <= 0.0 {
+                continue;
+            }
+            let (x, s, z) = stats.key;
+            let min = vec3(x as f32, s as f32, z as f32) * 16.0;
+            let corner = |(x, y, z): Corner| min + vec3(x as f32, y as f32, z as f32) * 16.0;
+            for face in CUBE_FACES {
+                // Skip faces that are partly behind the camera
+                let corners = face.map(|c| project(corner(c)));
+                if let [Some(a), Some(b), Some(c), Some(d)] = corners {
+                    overlay.push_quad([a, b, c, d], heat_color(heat));
+                }
+            }
+        }
+    }
+}
+
+/// Get the unnormalized heat of a subchunk, where 0 is not shown at all.
+fn heat_value(metric: HeatmapMetric, stats: &SubchunkStats, now: Instant) -> f32 {
+    match metric {
+        HeatmapMetric::MeshSize => stats.indices as f32,
+        HeatmapMetric::UploadAge => match stats.last_upload {
+            Some(time) => {
+                let age = now.duration_since(time).as_secs_f32();
+                (1.0 - age / HEATMAP_MAX_AGE.as_secs_f32()).max(0.0)
+            }
+            None => 0.0,
+        },
+        HeatmapMetric::UploadCount => stats.uploads as f32,
+    }
+}
+
+/// Get the translucent color of a normalized heat in `0.0..=1.0`.
+fn heat_color(heat: f32) -> Color {
+    [heat, 0.2, 1.0 - heat, 0.1 + 0.15 * heat]
+}
+
+/// The maximum number of rows in the subchunk panel.
+const PANEL_ROWS: usize = 24;
+
+/// How recently a subchunk must have been uploaded to pass [`SubchunkFilter::RecentlyUploaded`].
+const RECENT_UPLOAD: Duration = Duration::from_secs(1);
+
+/// The order of the rows in the subchunk panel, from the top.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SubchunkSort {
+    MostIndices,
+    LargestBuffers,
+    LatestUpload,
+}
+
+impl SubchunkSort {
+    fn next(self) -> Self {
+        match self {
+            SubchunkSort::MostIndices => SubchunkSort::LargestBuffers,
+            SubchunkSort::LargestBuffers => SubchunkSort::LatestUpload,
+            SubchunkSort::LatestUpload => SubchunkSort::MostIndices,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SubchunkFilter {
+    All,
+    /// Only subchunks with faces to draw.
+    NonEmpty,
+    /// Only subchunks uploaded within [`RECENT_UPLOAD`], to spot upload churn.
+    RecentlyUploaded,
+}
+
+impl SubchunkFilter {
+    fn next(self) -> Self {
+        match self {
+            SubchunkFilter::All => SubchunkFilter::NonEmpty,
+            SubchunkFilter::NonEmpty => SubchunkFilter::RecentlyUploaded,
+            SubchunkFilter::RecentlyUploaded => SubchunkFilter::All,
+        }
+    }
+
+    fn accepts(self, stats: &SubchunkStats, now: Instant) -> bool {
+        match self {
+            SubchunkFilter::All => true,
+            SubchunkFilter::NonEmpty => stats.indices > 0,
+            SubchunkFilter::RecentlyUploaded => {
+                matches!(stats.last_upload, Some(time) if now.duration_since(time) < RECENT_UPLOAD)
+            }
+        }
+    }
+}
+
+/// A panel listing the buffers of each subchunk, sortable and filterable.
+pub struct SubchunkPanel {
+    visible: bool,
+    sort: SubchunkSort,
+    filter: SubchunkFilter,
+}
+
+impl Default for SubchunkPanel {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SubchunkPanel {
+    pub fn new() -> Self {
+        Self {
+            visible: false,
+            sort: SubchunkSort::MostIndices,
+            filter: SubchunkFilter::NonEmpty,
+        }
+    }
+
+    pub fn toggle(&mut self) {
+        self.visible = !self.visible;
+    }
+
+    pub fn is_visible(&self) -> bool {
+        self.visible
+    }
+
+    pub fn cycle_sort(&mut self) {
+        self.sort = self.sort.next();
+    }
+
+    pub fn cycle_filter(&mut self) {
+        self.filter = self.filter.next();
+    }
+
+    /// Push the panel to the top-right corner of `overlay` if it's visible.
+    pub fn push(
+        &self,
+        overlay: &mut OverlayBuffer,
+        stats: Vec<SubchunkStats>,
+        screen_size: PhysicalSize<u32>,
+    ) {
+        if !self.visible {
+            return;
+        }
+
+        let total = stats.len();
+        let rows = select_rows(stats, self.sort, self.filter, Instant::now());
+        let mut text = format!(
+            "SUBCHUNKS: {} OF {total} ({:?}, {:?})\n\
+             CX   S   CZ  VERTS  INDICES   BYTES D UPLOADS UPLOADED",
+            rows.len(),
+            self.sort,
+            self.filter,
+        );
+        for stats in rows.iter().take(PANEL_ROWS) {
+            let (cx, s, cz) = stats.key;
+            let uploaded = match stats.last_upload {
+                Some(time) => format!("{:.1}S AGO", time.elapsed().as_secs_f32()),
+                None => "NEVER".to_owned(),
+            };
+            text += &format!(
+                "\n{cx:>4} {s:>2} {cz:>4} {:>6} {:>8} {:>7} {} {:>7} {uploaded}",
+                stats.vertices,
+                stats.indices,
+                stats.buffer_bytes,
+                if stats.dirty { "*" } else { " " },
+                stats.uploads,
+            );
+        }
+
+        let (width, _) = text_size(&text, TEXT_SCALE);
+        let x = screen_size.width as f32 - width - ORIGIN.0 - 4.0 * TEXT_SCALE;
+        overlay.push_text_panel((x.max(0.0), ORIGIN.1), &text, TEXT_SCALE, WHITE);
+    }
+}
+
+/// Filter and sort subchunk statistics into the rows of the subchunk panel.
+fn select_rows(
+    mut stats: Vec<SubchunkStats>,
+    sort: SubchunkSort,
+    filter: SubchunkFilter,
+    now: Instant,
+) -> Vec<SubchunkStats> {
+    stats.retain(|stats| filter.accepts(stats, now));
+    match sort {
+        SubchunkSort::MostIndices => stats.sort_by_key(|stats| Reverse(stats.indices)),
+        SubchunkSort::LargestBuffers => stats.sort_by_key(|stats| Reverse(stats.buffer_bytes)),
+        SubchunkSort::LatestUpload => stats.sort_by_key(|stats| Reverse(stats.last_upload)),
+    }
+    stats
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn stats(key: (i64, i64, i64), indices: usize, last_upload: Option<Instant>) -> SubchunkStats {
+        SubchunkStats {
+            key,
+            vertices: indices / 6 * 4,
+            indices,
+            buffer_bytes: indices * 2,
+            dirty: false,
+            last_upload,
+            uploads: 1,
+        }
+    }
+
+    #[test]
+    fn test_heat_value() {
+        let now = Instant::now();
+        let fresh = stats((0, 0, 0), 6, Some(now));
+        let stale = stats((0, 1, 0), 60, Some(now - HEATMAP_MAX_AGE * 2));
+        assert_eq!(heat_value(HeatmapMetric::UploadAge, &fresh, now), 1.0);
+        assert_eq!(heat_value(HeatmapMetric::UploadAge, &stale, now), 0.0);
+        assert_eq!(heat_value(HeatmapMetric::MeshSize, &stale, now), 60.0);
+
+        let mut heatmap = SubchunkHeatmap::new();
+        assert!(!heatmap.is_visible());
+        let cycled: Vec<_> = (0..4).map(|_| heatmap.cycle()).collect();
+        assert_eq!(cycled[0], Some(HeatmapMetric::MeshSize));
+        assert_eq!(cycled[3], None);
+    }
+
+    #[test]
+    fn test_select_rows() {
+        let now = Instant::now();
+        let old = now - Duration::from_secs(5);
+        let all = vec![
+            stats((0, 0, 0), 6, Some(old)),
+            stats((0, 1, 0), 0, Some(now)),
+            stats((0, 2, 0), 60, None),
+        ];
+        let keys = |rows: Vec<SubchunkStats>| rows.iter().map(|s| s.key.1).collect::<Vec<_>>();
+
+        let rows = select_rows(
+            all.clone(),
+            SubchunkSort::MostIndices,
+            SubchunkFilter::All,
+            now,
+        );
+        assert_eq!(keys(rows), vec![2, 0, 1]);
+        let rows = select_rows(
+            all.clone(),
+            SubchunkSort::LatestUpload,
+            SubchunkFilter::NonEmpty,
+            now,
+        );
+        assert_eq!(keys(rows), vec![0, 2]);
+        let rows = select_rows(
+            all,
+            SubchunkSort::MostIndices,
+            SubchunkFilter::RecentlyUploaded,
+            now,
+        );
+        assert_eq!(keys(rows), vec![1]);
+    }
+}
@@ -0,0 +1,168 @@
+//! GPU timing of the passes of a frame, measured with timestamp queries.
+//!
+//! Timestamps are only read back from one frame at a time, so while a readback is in flight
+//! the following frames go unmeasured. The timings shown are thus a few frames old.
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use wgpu::*;
+
+/// The points of a frame a timestamp is written at, in order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Timestamp {
+    FrameStart,
+    AoBakeEnd,
+    TerrainEnd,
+    OverlayEnd,
+}
+
+const TIMESTAMP_COUNT: u32 = 4;
+
+const BUFFER_SIZE: BufferAddress = TIMESTAMP_COUNT as BufferAddress * 8;
+
+/// GPU durations of the passes of a frame.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct GpuTimings {
+    pub ao_bake: Duration,
+    pub terrain: Duration,
+    pub overlay: Duration,
+}
+
+impl GpuTimings {
+    pub fn total(&self) -> Duration {
+        self.ao_bake + self.terrain + self.overlay
+    }
+
+    /// Compute the timings from raw timestamps, ticking every `period` nanoseconds.
+    fn from_timestamps(timestamps: [u64; TIMESTAMP_COUNT as usize], period: f32) -> Self {
+        let between = |start: Timestamp, end: Timestamp| {
+            let ticks = timestamps[end as usize].saturating_sub(timestamps[start as usize]);
+            Duration::from_nanos((ticks as f64 * period as f64) as u64)
+        };
+        Self {
+            ao_bake: between(Timestamp::FrameStart, Timestamp::AoBakeEnd),
+            terrain: between(Timestamp::AoBakeEnd, Timestamp::TerrainEnd),
+            overlay: between(Timestamp::TerrainEnd, Timestamp::OverlayEnd),
+        }
+    }
+}
+
+pub struct GpuTimer {
+    query_set: QuerySet,
+    readback_buffer: Buffer,
+    /// Nanoseconds per timestamp tick.
+    period: f32,
+    /// Whether the current frame writes timestamps, which it does if no readback is in flight.
+    measuring: bool,
+    /// Whether `readback_buffer` is mapped or being mapped.
+    reading_back: bool,
+    /// Set by the map callback to whether `readback_buffer` was mapped successfully.
+    map_result: Arc<Mutex<Option<bool>>>,
+    latest: Option<GpuTimings>,
+}
+
+impl GpuTimer {
+    /// Create a timer, or `None` if the device doesn't support timestamp queries.
+    pub fn new(device: &Device, queue: &Queue) -> Option<Self> {
+        if !device.features().contains(Features::TIMESTAMP_QUERY) {
+            return None;
+        }
+        let query_set = device.create_query_set(&QuerySetDescriptor {
+            label: Some("GPU Timer Query Set"),
+            ty: QueryType::Timestamp,
+            count: TIMESTAMP_COUNT,
+        });
+        let readback_buffer = device.create_buffer(&BufferDescriptor {
+            label: Some("GPU Timer Readback Buffer"),
+            size: BUFFER_SIZE,
+            usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        Some(Self {
+            query_set,
+            readback_buffer,
+            period: queue.get_timestamp_period(),
+            measuring: false,
+            reading_back: false,
+            map_result: Arc::new(Mutex::new(None)),
+            latest: None,
+        })
+    }
+
+    /// Start measuring a frame, collecting the timings of an earlier frame if they're ready.
+    pub fn begin_frame(&mut self, device: &Device) {
+        device.poll(Maintain::Poll);
+        let map_result = self.map_result.lock().unwrap().take();
+        match map_result {
+            Some(true) => {
+                let data = self.readback_buffer.slice(..).get_mapped_range();
+                let mut timestamps = [0; TIMESTAMP_COUNT as usize];
+                for (timestamp, bytes) in timestamps.iter_mut().zip(data.chunks_exact(8)) {
+                    *timestamp = u64::from_le_bytes(bytes.try_into().unwrap());
+                }
+                self.latest = Some(GpuTimings::from_timestamps(timestamps, self.period));
+                drop(data);
+                self.readback_buffer.unmap();
+                self.reading_back = false;
+            }
+            Some(false) => self.reading_back = false,
+            None => {}
+        }
+        self.measuring = !self.reading_back;
+    }
+
+    pub fn write(&self, encoder: &mut CommandEncoder, timestamp: Timestamp) {
+        if self.measuring {
+            encoder.write_timestamp(&self.query_set, timestamp as u32);
+        }
+    }
+
+    /// Copy the timestamps of the frame to the readback buffer. Must be the last command of
+    /// the frame.
+    pub fn resolve(&self, encoder: &mut CommandEncoder) {
+        if self.measuring {
+            encoder.resolve_query_set(
+                &self.query_set,
+                0..TIMESTAMP_COUNT,
+                &self.readback_buffer,
+                0,
+            );
+        }
+    }
+
+    /// Start reading back the timestamps, after the frame was submitted.
+    pub fn end_frame(&mut self) {
+        if !self.measuring {
+            return;
+        }
+        self.measuring = false;
+        self.reading_back = true;
+        let map_result = self.map_result.clone();
+        self.readback_buffer
+            .slice(..)
+            .map_async(MapMode::Read, move |result| {
+                *map_result.lock().unwrap() = Some(result.is_ok());
+            });
+    }
+
+    /// The timings of the most recently measured frame.
+    pub fn latest(&self) -> Option<GpuTimings> {
+        self.latest
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_from_timestamps() {
+        let timings = GpuTimings::from_timestamps([100, 300, 1300, 1200], 2.0);
+        assert_eq!(timings.ao_bake, Duration::from_nanos(400));
+        assert_eq!(timings.terrain, Duration::from_nanos(2000));
+        // Timestamps may be out of order on some hardware
+        assert_eq!(timings.overlay, Duration::ZERO);
+        assert_eq!(timings.total(), Duration::from_nanos(2400));
+    }
+}
@@ -0,0 +1,247 @@
+//! Recording and playback of input events, for replaying camera-dependent bugs exactly.
+//!
+//! A recording is a text file with one event per line, tagged with the frame it was handled in
+//! and the time since the recording started in milliseconds. Every frame starts with its frame
+//! time in microseconds, followed by its events:
+//!
+//! ```text
+//! 120 2003 frame_time 16667
+//! 120 2003 action toggle_debug
+//! 121 2019 frame_time 16102
+//! 121 2019 mouse 4.5 -1
+//! ```
+//!
+//! Playback feeds the events back by frame number rather than by time, along with the recorded
+//! frame times, so that the replayed frames see the same input regardless of how fast they are
+//! rendered.
+
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Context, Result};
+
+use crate::bindings::Action;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum InputEvent {
+    Action(Action),
+    /// Raw mouse motion, in unspecified units.
+    MouseMotion(f64, f64),
+}
+
+impl InputEvent {
+    fn to_line(self) -> String {
+        match self {
+            InputEvent::Action(action) => format!("action {action}"),
+            InputEvent::MouseMotion(x, y) => format!("mouse {x} {y}"),
+        }
+    }
+
+    fn parse(words: &[&str]) -> Result<Self> {
+        Ok(match words {
+            ["action", name] => InputEvent::Action(name.parse()?),
+            ["mouse", x, y] => InputEvent::MouseMotion(x.parse()?, y.parse()?),
+            _ => bail!("unknown event `{}`", words.join(" ")),
+        })
+    }
+}
+
+/// Writes the input events of each frame to a recording.
+pub struct InputRecorder {
+    writer: BufWriter<File>,
+    start: Instant,
+}
+
+impl InputRecorder {
+    pub fn create(path: &Path) -> Result<Self> {
+        let file = File::create(path)
+            .with_context(|| format!("Failed to create input recording {}", path.display()))?;
+        Ok(Self {
+            writer: BufWriter::new(file),
+            start: Instant::now(),
+        })
+    }
+
+    /// Record the events of `frame`, which took `frame_time`.
+    pub fn record(
+        &mut self,
+        frame: u64,
+        frame_time: Duration,
+        events: &[InputEvent],
+    ) -> Result<()> {
+        let millis = self.start.elapsed().as_millis();
+        writeln!(
+            self.writer,
+            "{frame} {millis} frame_time {}",
+            frame_time.as_micros()
+        )?;
+        for event in events {
+            writeln!(self.writer, "{frame} {millis} {}", event.to_line())?;
+        }
+        // Flush every frame, so that the recording is complete even if the client crashes
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+/// A line of a recording.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Entry {
+    FrameTime(Duration),
+    Event(InputEvent),
+}
+
+/// Reads back a recording, handing out its events frame by frame.
+pub struct InputPlayback {
+    entries: VecDeque<(u64, Entry)>,
+}
+
+impl InputPlayback {
+    pub fn open(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read input recording {}", path.display()))?;
+        Self::parse(&text)
+    }
+
+    fn parse(text: &str) -> Result<Self> {
+        let entries = text
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(index, line)| {
+                let words: Vec<_> = line.split_whitespace().collect();
+                let parse = || -> Result<_> {
+                    match words.as_slice() {
+                        [frame, _millis, "frame_time", micros] => Ok((
+                            frame.parse()?,
+                            Entry::FrameTime(Duration::from_micros(micros.parse()?)),
+                        )),
+                        [frame, _millis, event @ ..] => {
+                            Ok((frame.parse()?, Entry::Event(InputEvent::parse(event)?)))
+                        }
+                        _ => bail!("missing frame number"),
+                    }
+                };
+                parse().with_context(|| format!("Bad input event on line {}", index + 1))
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self { entries })
+    }
+
+    /// Take the frame time and events of `frame`, skipping those of any earlier frames. Frames
+    /// recorded without a frame time take `live_frame_time`.
+    pub fn take_frame(
+        &mut self,
+        frame: u64,
+        live_frame_time: Duration,
+    ) -> (Duration, Vec<InputEvent>) {
+        let mut frame_time = live_frame_time;
+        let mut events = vec![];
+        while let Some(&(entry_frame, entry)) = self.entries.front() {
+            if entry_frame > frame {
+                break;
+            }
+            self.entries.pop_front();
+            match entry {
+                _ if entry_frame < frame => {}
+                Entry::FrameTime(time) => frame_time = time,
+                Entry::Event(event) => events.push(event),
+            }
+        }
+        (frame_time, events)
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::look::LookSmoother;
+    use crate::settings::Settings;
+
+    #[test]
+    fn test_playback() {
+        let text = "0 0 action toggle_debug\n2 30 frame_time 15000\n2 30 mouse 1.5 -2\n\
+                    2 30 action move_up\n";
+        let mut playback = InputPlayback::parse(text).unwrap();
+        let live = Duration::from_millis(5);
+        assert_eq!(
+            playback.take_frame(0, live),
+            (live, vec![InputEvent::Action(Action::ToggleDebug)])
+        );
+        assert_eq!(playback.take_frame(1, live), (live, vec![]));
+        assert_eq!(
+            playback.take_frame(2, live),
+            (
+                Duration::from_millis(15),
+                vec![
+                    InputEvent::MouseMotion(1.5, -2.0),
+                    InputEvent::Action(Action::MoveUp)
+                ]
+            )
+        );
+        assert!(playback.is_finished());
+
+        assert!(InputPlayback::parse("0 0 action fly").is_err());
+        assert!(InputPlayback::parse("x 0 action move_up").is_err());
+    }
+
+    #[test]
+    fn test_replay_at_any_frame_rate() {
+        let path = std::env::temp_dir().join(format!("wgpu-block-replay-{}", std::process::id()));
+        let mut recorder = InputRecorder::create(&path).unwrap();
+        for frame in 0..30 {
+            let events = match frame % 3 {
+                0 => vec![InputEvent::MouseMotion(12.0, -5.0)],
+                _ => vec![],
+            };
+            let frame_time = Duration::from_millis(10 + frame % 7);
+            recorder.record(frame, frame_time, &events).unwrap();
+        }
+        drop(recorder);
+
+        let settings = Settings::parse("mouse_acceleration = 0.01\nlook_smoothing = 0.5").unwrap();
+        let replay = |live_frame_time: Duration| {
+            let mut playback = InputPlayback::open(&path).unwrap();
+            let mut smoother = LookSmoother::default();
+            let (mut yaw, mut pitch) = (0.0, 0.0);
+            for frame in 0..30 {
+                let (frame_time, events) = playback.take_frame(frame, live_frame_time);
+                let mut motion = (0.0, 0.0);
+                for event in events {
+                    if let InputEvent::MouseMotion(x, y) = event {
+                        motion = (motion.0 + x, motion.1 + y);
+                    }
+                }
+                let look = settings.look_delta(motion, frame_time);
+                let (dyaw, dpitch) = smoother.update(look, settings.look_smoothing, frame_time);
+                yaw += dyaw;
+                pitch += dpitch;
+            }
+            (yaw, pitch)
+        };
+        assert_eq!(
+            replay(Duration::from_millis(1)),
+            replay(Duration::from_millis(100))
+        );
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_event_lines() {
+        for event in [
+            InputEvent::Action(Action::CycleVsync),
+            InputEvent::MouseMotion(-0.25, 3.0),
+        ] {
+            let line = event.to_line();
+            let words: Vec<_> = line.split_whitespace().collect();
+            assert_eq!(InputEvent::parse(&words).unwrap(), event);
+        }
+    }
+}
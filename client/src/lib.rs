@@ -0,0 +1,22 @@
+//! The client of the block engine, split out of the binary so that benchmarks can reach the
+//! mesher.
+
+pub mod args;
+pub mod assets;
+pub mod bindings;
+pub mod chunk;
+pub mod clipboard;
+pub mod debug;
+pub mod font;
+pub mod gpu_timer;
+pub mod input;
+pub mod limiter;
+pub mod lod;
+pub mod look;
+pub mod mesh_cache;
+pub mod mesher;
+pub mod overlay;
+pub mod quality;
+pub mod render;
+pub mod settings;
+pub mod sky;
@@ -0,0 +1,87 @@
+//! Frame rate limiting, for when the present mode doesn't cap the frame rate itself.
+
+use std::time::{Duration, Instant};
+
+/// The frame rate cap while the window is unfocused, to save power in the background.
+const BACKGROUND_MAX_FPS: u32 = 15;
+
+pub struct FrameLimiter {
+    /// The minimum duration of a frame, or `None` if unlimited.
+    frame_time: Option<Duration>,
+    /// The earliest time the next frame may start.
+    next_frame: Instant,
+}
+
+impl FrameLimiter {
+    pub fn new(max_fps: Option<u32>) -> Self {
+        Self {
+            frame_time: max_fps.map(frame_time),
+            next_frame: Instant::now(),
+        }
+    }
+
+    pub fn set_max_fps(&mut self, max_fps: Option<u32>) {
+        self.frame_time = max_fps.map(frame_time);
+    }
+
+    /// Sleep until the next frame may start.
+    pub fn wait(&mut self) {
+        let frame_time = match self.frame_time {
+            Some(frame_time) => frame_time,
+            None => return,
+        };
+        let now = Instant::now();
+        if let Some(remaining) = self.next_frame.checked_duration_since(now) {
+            std::thread::sleep(remaining);
+        }
+        self.next_frame = next_frame(self.next_frame, now, frame_time);
+    }
+}
+
+/// Get the frame rate cap for the window, lowered to [`BACKGROUND_MAX_FPS`] while unfocused.
+pub fn max_fps(max_fps: Option<u32>, is_focused: bool) -> Option<u32> {
+    if is_focused {
+        max_fps
+    } else {
+        Some(max_fps.map_or(BACKGROUND_MAX_FPS, |fps| fps.min(BACKGROUND_MAX_FPS)))
+    }
+}
+
+fn frame_time(max_fps: u32) -> Duration {
+    Duration::from_secs_f64(1.0 / max_fps as f64)
+}
+
+/// Get the start of the frame after the one scheduled at `scheduled`. Frames are scheduled at a
+/// fixed pace, so that sleeping a bit too long is made up for, but a frame that runs late doesn't
+/// cause a burst of unlimited frames after it.
+fn next_frame(scheduled: Instant, now: Instant, frame_time: Duration) -> Instant {
+    (scheduled + frame_time).max(now)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_next_frame() {
+        let start = Instant::now();
+        let frame_time = frame_time(100);
+        assert_eq!(frame_time, Duration::from_millis(10));
+
+        // On schedule
+        let next = next_frame(start, start, frame_time);
+        assert_eq!(next, start + frame_time);
+        // Late by more than a frame
+        let late = start + frame_time * 3;
+        assert_eq!(next_frame(next, late, frame_time), late);
+    }
+
+    #[test]
+    fn test_max_fps() {
+        assert_eq!(max_fps(None, true), None);
+        assert_eq!(max_fps(Some(144), true), Some(144));
+        assert_eq!(max_fps(None, false), Some(BACKGROUND_MAX_FPS));
+        assert_eq!(max_fps(Some(144), false), Some(BACKGROUND_MAX_FPS));
+        assert_eq!(max_fps(Some(10), false), Some(10));
+    }
+}
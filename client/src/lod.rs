@@ -0,0 +1,216 @@
+//! Level of detail of subchunk meshes. Far away subchunks are meshed with their blocks merged
+//! into larger cells, which cuts down the faces drawn at long render distances.
+
+use itertools::iproduct;
+
+use crate::chunk::{Block, ChunkCollection, MaybeLoadedBlock};
+use crate::render::{self, RenderedBuffer, Vertex};
+
+/// The distances from the camera, in chunks, beyond which the level of detail drops by one.
+const LOD_DISTANCES: [i64; 2] = [8, 16];
+
+/// The faces of a cell, with the direction of the neighboring cell each one faces.
+const FACES: [([Vertex; 4], (i64, i64, i64)); 6] = [
+    (render::TOP_FACE, (0, 1, 0)),
+    (render::BOTTOM_FACE, (0, -1, 0)),
+    (render::RIGHT_FACE, (1, 0, 0)),
+    (render::LEFT_FACE, (-1, 0, 0)),
+    (render::FRONT_FACE, (0, 0, 1)),
+    (render::REAR_FACE, (0, 0, -1)),
+];
+
+/// The offsets of the chunks next to a chunk, along +x, -x, +z and -z.
+pub const NEIGHBORS: [(i64, i64); 4] = [(1, 0), (-1, 0), (0, 1), (0, -1)];
+
+/// The level of detail of a chunk along with those of its neighbors, which decide how faces
+/// along the borders between them are culled.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Lods {
+    pub own: u32,
+    /// The levels of detail of the neighbors, in the order of [`NEIGHBORS`].
+    pub neighbors: [u32; 4],
+}
+
+impl Lods {
+    /// Get the levels of detail around the chunk at `(cx, cz)` as seen from the camera chunk.
+    pub fn new((cx, cz): (i64, i64), camera_chunk: (i64, i64)) -> Self {
+        Self {
+            own: lod_level((cx, cz), camera_chunk),
+            neighbors: NEIGHBORS.map(|(dx, dz)| lod_level((cx + dx, cz + dz), camera_chunk)),
+        }
+    }
+
+    /// Get the level of detail of the chunk at offset `(dx, dz)` from the chunk, which must be
+    /// the chunk itself or one of its [`NEIGHBORS`].
+    pub fn at(&self, (dx, dz): (i64, i64)) -> u32 {
+        match NEIGHBORS.iter().position(|offset| *offset == (dx, dz)) {
+            Some(i) => self.neighbors[i],
+            None => self.own,
+        }
+    }
+
+    /// Get the highest level of detail of the chunk and its neighbors.
+    pub fn max(&self) -> u32 {
+        self.neighbors.into_iter().fold(self.own, u32::max)
+    }
+}
+
+/// Get the level of detail of the chunk at `(cx, cz)` as seen from the camera chunk. At level
+/// `n`, blocks are merged into cells of `2^n` blocks along each axis.
+pub fn lod_level((cx, cz): (i64, i64), (camera_x, camera_z): (i64, i64)) -> u32 {
+    let (dx, dz) = (cx - camera_x, cz - camera_z);
+    let distance_sq = dx * dx + dz * dz;
+    LOD_DISTANCES
+        .iter()
+        .filter(|distance| distance_sq > *distance * *distance)
+        .count() as u32
+}
+
+/// Mesh the subchunk starting at world coordinates `start` at level of detail `lod`, see
+/// [`lod_level`].
+pub fn mesh_cells(
+    chunk_collection: &ChunkCollection,
+    (x_start, y_start, z_start): (i64, i64, i64),
+    lod: u32,
+    buffer: &mut RenderedBuffer,
+) {
+    let size = 1 << lod;
+    let cells = 16 / size;
+    let cell_index = |(cx, cy, cz): (i64, i64, i64)| ((cy * cells + cz) * cells + cx) as usize;
+
+    let merged = iproduct!(0..cells, 0..cells, 0..cells)
+        .map(|(cy, cz, cx)| {
+            let origin = (
+                x_start + cx * size,
+                y_start + cy * size,
+                z_start + cz * size,
+            );
+            merged_block(chunk_collection, origin, size)
+        })
+        .collect::<Vec<_>>();
+
+    for (cx, cy, cz) in iproduct!(0..cells, 0..cells, 0..cells) {
+        let block = match merged[cell_index((cx, cy, cz))] {
+            Some(block) => block,
+            None => continue,
+        };
+        let layer = render::texture_layer(block);
+        let local = (cx * size, cy * size, cz * size);
+
+        for (face, (dx, dy, dz)) in FACES {
+            let neighbor = (cx + dx, cy + dy, cz + dz);
+            let is_inside = [neighbor.0, neighbor.1, neighbor.2]
+                .iter()
+                .all(|n| (0..cells).contains(n));
+            let is_hidden = if is_inside {
+                matches!(
+                    merged[cell_index(neighbor)],
+                    Some(neighbor) if neighbor.is_opaque() || neighbor == block
+                )
+            } else {
+                // Neighboring subchunks may be meshed at another level of detail, so only cover
+                // faces with blocks that are opaque at any level, to not leave holes along seams
+                let origin = (
+                    x_start + neighbor.0 * size,
+                    y_start + neighbor.1 * size,
+                    z_start + neighbor.2 * size,
+                );
+                is_covered(chunk_collection, origin, size, block)
+            };
+            if !is_hidden {
+                buffer.push_scaled_face(face, local, size, layer);
+            }
+        }
+    }
+}
+
+/// Get the block that the cell of `size` blocks along each axis starting at `origin` is drawn
+/// as, i.e. its most common visible block, or `None` if less than half of its blocks are visible.
+fn merged_block(
+    chunk_collection: &ChunkCollection,
+    (x, y, z): (i64, i64, i64),
+    size: i64,
+) -> Option<Block> {
+    let mut counts = [0; Block::ALL.len()];
+    for (dx, dy, dz) in iproduct!(0..size, 0..size, 0..size) {
+        if let MaybeLoadedBlock::Loaded(block) =
+            chunk_collection.get_block((x + dx, y + dy, z + dz))
+        {
+            if block.is_visible() {
+                counts[block.id() as usize] += 1;
+            }
+        }
+    }
+    if counts.iter().sum::<i64>() * 2 < size * size * size {
+        return None;
+    }
+    let id = (0..counts.len()).max_by_key(|id| counts[*id])?;
+    Block::from_id(id as u8)
+}
+
+/// Check whether the face of a full-detail `block` toward the block at `pos` is hidden, where
+/// `pos` is meshed at level of detail `lod`. It's hidden by the cell `pos` is drawn as rather
+/// than by the block itself, which may not be drawn at all.
+pub fn hides_face(
+    chunk_collection: &ChunkCollection,
+    (x, y, z): (i64, i64, i64),
+    lod: u32,
+    block: Block,
+) -> bool {
+    let size = 1 << lod;
+    let origin = (
+        x.div_euclid(size) * size,
+        y.div_euclid(size) * size,
+        z.div_euclid(size) * size,
+    );
+    matches!(
+        merged_block(chunk_collection, origin, size),
+        Some(neighbor) if neighbor.is_opaque() || neighbor == block
+    )
+}
+
+/// Check whether the face of a cell drawn as `block` toward the cell starting at `origin` is
+/// hidden, i.e. all of its blocks are opaque or of the same kind. Faces toward unloaded blocks are
+/// skipped, as in full-detail meshes.
+fn is_covered(
+    chunk_collection: &ChunkCollection,
+    (x, y, z): (i64, i64, i64),
+    size: i64,
+    block: Block,
+) -> bool {
+    iproduct!(0..size, 0..size, 0..size).all(|(dx, dy, dz)| {
+        match chunk_collection.get_block((x + dx, y + dy, z + dz)) {
+            MaybeLoadedBlock::Loaded(neighbor) => neighbor.is_opaque() || neighbor == block,
+            MaybeLoadedBlock::Unloaded => true,
+        }
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_lod_level() {
+        assert_eq!(lod_level((3, -4), (3, -4)), 0);
+        assert_eq!(lod_level((8, 0), (0, 0)), 0);
+        assert_eq!(lod_level((6, 6), (0, 0)), 1);
+        assert_eq!(lod_level((0, -16), (0, 0)), 1);
+        assert_eq!(lod_level((-20, 5), (0, 0)), 2);
+    }
+
+    #[test]
+    fn test_faces_point_outward() {
+        for (face, (dx, dy, dz)) in FACES {
+            for vertex in face {
+                for (pos, d) in vertex.pos.into_iter().zip([dx, dy, dz]) {
+                    match d {
+                        1 => assert_eq!(pos, 1.0),
+                        -1 => assert_eq!(pos, 0.0),
+                        _ => {}
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,62 @@
+//! Smoothing of the camera look motion, see [`Settings::look_smoothing`].
+//!
+//! [`Settings::look_smoothing`]: crate::settings::Settings::look_smoothing
+
+use std::time::Duration;
+
+/// The frame time the smoothing factor is given for, i.e. that of 60 FPS.
+const SMOOTHING_FRAME_TIME: Duration = Duration::from_nanos(16_666_667);
+
+#[derive(Debug, Default)]
+pub struct LookSmoother {
+    /// The yaw and pitch not turned yet, in radians.
+    pending: (f32, f32),
+}
+
+impl LookSmoother {
+    /// Get the yaw and pitch to turn in a frame that took `frame_time`, given the unsmoothed turn
+    /// `(yaw, pitch)` of the frame. The rest of the turn decays over the following frames, with
+    /// the fraction `smoothing` of it left after every 60 FPS frame's worth of time, so that
+    /// the total turn is the same as without smoothing at any frame rate.
+    pub fn update(
+        &mut self,
+        (yaw, pitch): (f32, f32),
+        smoothing: f32,
+        frame_time: Duration,
+    ) -> (f32, f32) {
+        let carried = smoothing.powf(frame_time.as_secs_f32() / SMOOTHING_FRAME_TIME.as_secs_f32());
+        let (pending_yaw, pending_pitch) = (self.pending.0 + yaw, self.pending.1 + pitch);
+        self.pending = (pending_yaw * carried, pending_pitch * carried);
+        (pending_yaw - self.pending.0, pending_pitch - self.pending.1)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_smoothing() {
+        let frame = SMOOTHING_FRAME_TIME;
+        let mut smoother = LookSmoother::default();
+        assert_eq!(smoother.update((1.0, -2.0), 0.0, frame), (1.0, -2.0));
+        assert_eq!(smoother.update((0.0, 0.0), 0.0, frame), (0.0, 0.0));
+
+        // A single motion is spread over the following frames
+        let (yaw, pitch) = smoother.update((1.0, -2.0), 0.5, frame);
+        assert_eq!((yaw, pitch), (0.5, -1.0));
+        let total_yaw: f32 = yaw
+            + (0..40)
+                .map(|_| smoother.update((0.0, 0.0), 0.5, frame).0)
+                .sum::<f32>();
+        assert!((total_yaw - 1.0).abs() < 1e-6);
+
+        // Two frames turn as far as a single one twice as long
+        let mut fast = LookSmoother::default();
+        let fast_yaw =
+            fast.update((1.0, 0.0), 0.5, frame / 2).0 + fast.update((0.0, 0.0), 0.5, frame / 2).0;
+        let mut slow = LookSmoother::default();
+        let slow_yaw = slow.update((1.0, 0.0), 0.5, frame).0;
+        assert!((fast_yaw - slow_yaw).abs() < 1e-6);
+    }
+}
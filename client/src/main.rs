@@ -1,44 +1,122 @@
+use std::time::{Duration, Instant};
+
 use anyhow::Result;
 use glam::{vec3, Mat4, Vec3};
-use itertools::iproduct;
-use render::Render;
 use tokio::runtime::Handle;
-use tracing::{info, warn};
+use tracing::{error, info, warn};
 use wgpu::SurfaceError;
+use wgpu_block_client::{
+    args,
+    assets::{Assets, FileWatcher, TextureAsset, SHADER_SOURCE_PATH},
+    bindings::{Action, Bindings},
+    chunk::{self, Block, MaybeLoadedBlock},
+    clipboard,
+    debug::{DebugInfo, DebugOverlay, SubchunkHeatmap, SubchunkPanel},
+    input::{InputEvent, InputPlayback, InputRecorder},
+    limiter::{self, FrameLimiter},
+    lod,
+    look::LookSmoother,
+    mesh_cache::MeshCache,
+    mesher::{mesh_subchunk, subchunk_hash},
+    render::{self, Render},
+    settings::Settings,
+    sky,
+};
+use wgpu_block_shared::raycast::{raycast, RaycastHit};
 use winit::{
-    event::{ElementState, VirtualKeyCode, WindowEvent},
+    event::{ElementState, WindowEvent},
     event_loop::ControlFlow,
+    window::{Fullscreen, Icon, WindowBuilder},
 };
 
-use crate::{chunk::MaybeLoadedBlock, render::Vertex};
+/// The distance the spectator moves per movement key press, in blocks.
+const MOVE_STEP: f32 = 0.05;
+
+/// The maximum distance of the block the camera is looking at.
+const TARGET_DISTANCE: f32 = 64.0;
 
-mod chunk;
-mod render;
+/// How often the frame rate in the window title is updated.
+const TITLE_UPDATE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The maximum number of subchunk meshes kept around for reuse.
+const MESH_CACHE_CAPACITY: usize = 256;
+
+/// The time spent re-rendering dirty subchunks per frame, at most. Re-rendering the subchunk
+/// that goes over it is not interrupted.
+const MESHING_BUDGET: Duration = Duration::from_millis(4);
 
 fn main() -> Result<()> {
     init_tracing();
+    let args = args::Args::parse(std::env::args().skip(1))?;
 
     let runtime = tokio::runtime::Builder::new_multi_thread()
         .enable_all()
         .build()
         .unwrap();
 
-    run(runtime.handle().clone());
-
-    Ok(())
+    run(runtime.handle().clone(), args)
 }
 
-fn run(handle: Handle) {
+fn run(handle: Handle, args: args::Args) -> Result<()> {
     use winit::event::Event;
 
-    let mut chunk_collection = chunk::ChunkCollection::new();
+    let mut chunk_collection = chunk::ChunkCollection::new(args.seed, handle.clone());
+    let mut mesh_cache = MeshCache::new(MESH_CACHE_CAPACITY);
+    let mut recorder = args
+        .record
+        .as_deref()
+        .map(InputRecorder::create)
+        .transpose()?;
+    let mut playback = args
+        .replay
+        .as_deref()
+        .map(InputPlayback::open)
+        .transpose()?;
+
+    let (mut settings, mut bindings) = reload(&args)?;
+
+    let mut assets = Assets::new(&args.assets);
+    // Editing the block shader takes effect without restarting during development
+    let mut shader_watcher = cfg!(debug_assertions).then(|| FileWatcher::new(SHADER_SOURCE_PATH));
+    let block_texture = assets.load_texture(TextureAsset::GrassTop);
 
     let event_loop = winit::event_loop::EventLoop::new();
-    let window = winit::window::Window::new(&event_loop).expect("Failed to create window");
-
-    let mut render = handle.block_on(Render::new(&window));
+    let icon = render::icon_image(&block_texture);
+    let (icon_width, icon_height) = icon.dimensions();
+    let window = WindowBuilder::new()
+        .with_title(&settings.window_title)
+        .with_window_icon(Icon::from_rgba(icon.into_raw(), icon_width, icon_height).ok())
+        .build(&event_loop)
+        .expect("Failed to create window");
+    let mut last_title_update = Instant::now();
+
+    let mut quality = args.quality;
+    let mut overrides = args.overrides;
+    // Command-line arguments take precedence over the settings file
+    overrides.render_distance = args.overrides.render_distance.or(settings.render_distance);
+    let mut render = handle.block_on(Render::new(
+        &window,
+        overrides.apply(quality.settings()),
+        &block_texture,
+    ));
+    render.set_fov(settings.fov.to_radians());
+    let mut limiter = FrameLimiter::new(render.graphics().max_fps);
     let mut spec = Spectator::new((40.0, 40.0, 40.0), 0.4, 0.4);
     let mut is_cursor_grabbed = false;
+    let mut is_focused = true;
+    let mut debug_overlay = DebugOverlay::new();
+    let mut subchunk_panel = SubchunkPanel::new();
+    let mut heatmap = SubchunkHeatmap::new();
+    let mut time_of_day = sky::TimeOfDay::new(8.0);
+    // Input received since the last frame, handled at the start of the next one
+    let mut frame_inputs = vec![];
+    let mut frame: u64 = 0;
+    let mut look_smoother = LookSmoother::default();
+    let mut last_frame = Instant::now();
+    // The chunk the camera was in, which the level of detail of meshes is relative to
+    let mut last_camera_chunk = None;
+    // The block the camera looked at in the last frame
+    let mut target: Option<(RaycastHit, Block)> = None;
     event_loop.run(move |event, _, control_flow| match event {
         Event::WindowEvent { event, .. } => match event {
             WindowEvent::CloseRequested => *control_flow = ControlFlow::Exit,
@@ -46,40 +124,247 @@ fn run(handle: Handle) {
             WindowEvent::ScaleFactorChanged { new_inner_size, .. } => {
                 render.resize(*new_inner_size)
             }
+            WindowEvent::Focused(focused) => {
+                info!(focused);
+                is_focused = focused;
+                // Give the cursor back to whatever the user switched to
+                if !focused && is_cursor_grabbed {
+                    window.set_cursor_visible(true);
+                    if let Err(err) = window.set_cursor_grab(false) {
+                        warn!("Failed to release the cursor: {err}");
+                    }
+                    is_cursor_grabbed = false;
+                }
+            }
             WindowEvent::KeyboardInput { input, .. } => {
                 if input.state != ElementState::Pressed {
                     return;
                 }
-                if input.virtual_keycode.is_none() {
-                    return;
+                let action = input.virtual_keycode.and_then(|key| bindings.action(key));
+                if let Some(action) = action {
+                    info!(?input, %action);
+                    frame_inputs.push(InputEvent::Action(action));
                 }
-
-                info!(?input);
-                let keycode = input.virtual_keycode.unwrap();
-                match keycode {
-                    VirtualKeyCode::Space => {
-                        spec.update_eye((0.0, 0.05, 0.0));
+            }
+            _ => {}
+        },
+        Event::MainEventsCleared => {
+            let mut frame_time = last_frame.elapsed();
+            last_frame = Instant::now();
+            // Handle the input of this frame, replacing it with the recorded one during playback
+            if let Some(playback) = &mut playback {
+                (frame_time, frame_inputs) = playback.take_frame(frame, frame_time);
+            }
+            if matches!(&playback, Some(playback) if playback.is_finished()) {
+                info!("Input playback finished at frame {frame}, handing back control");
+                playback = None;
+            }
+            let record =
+                |recorder: &mut InputRecorder| recorder.record(frame, frame_time, &frame_inputs);
+            if let Some(Err(err)) = recorder.as_mut().map(record) {
+                error!("Stopped input recording: {err:#}");
+                recorder = None;
+            }
+            let mut mouse_motion = (0.0, 0.0);
+            for input in frame_inputs.drain(..) {
+                match input {
+                    InputEvent::Action(action) => match action {
+                        Action::MoveForward => spec.update_eye(spec.forward() * MOVE_STEP),
+                        Action::MoveBackward => spec.update_eye(-spec.forward() * MOVE_STEP),
+                        Action::MoveLeft => spec.update_eye(-spec.right() * MOVE_STEP),
+                        Action::MoveRight => spec.update_eye(spec.right() * MOVE_STEP),
+                        Action::MoveUp => spec.update_eye(Vec3::Y * MOVE_STEP),
+                        Action::MoveDown => spec.update_eye(-Vec3::Y * MOVE_STEP),
+                        Action::ToggleCursor => {
+                            window.set_cursor_visible(is_cursor_grabbed);
+                            window.set_cursor_grab(!is_cursor_grabbed).unwrap();
+                            is_cursor_grabbed = !is_cursor_grabbed;
+                        }
+                        Action::CopyTarget => match target {
+                            Some((hit, _)) => {
+                                let (x, y, z) = hit.pos;
+                                match clipboard::copy(&format!("{x} {y} {z}")) {
+                                    Ok(()) => info!("Copied {x} {y} {z} to the clipboard"),
+                                    Err(err) => warn!("Failed to copy to the clipboard: {err:#}"),
+                                }
+                            }
+                            None => info!("No targeted block to copy"),
+                        },
+                        Action::ToggleDebug => debug_overlay.toggle(),
+                        Action::ToggleChunkLabels => debug_overlay.toggle_labels(),
+                        Action::ToggleWireframe => {
+                            let wireframe = render.toggle_wireframe();
+                            info!(wireframe);
+                        }
+                        Action::ToggleSubchunkPanel => subchunk_panel.toggle(),
+                        Action::CycleSubchunkSort => subchunk_panel.cycle_sort(),
+                        Action::CycleSubchunkFilter => subchunk_panel.cycle_filter(),
+                        Action::CycleHeatmap => {
+                            let heatmap = heatmap.cycle();
+                            info!(?heatmap);
+                        }
+                        Action::CycleQuality => {
+                            quality = quality.next();
+                            info!("Graphics quality set to {quality}");
+                            render.set_graphics(overrides.apply(quality.settings()));
+                        }
+                        Action::CycleVsync => {
+                            // Kept as an override so that it survives changing the quality preset
+                            overrides.vsync = Some(render.graphics().vsync.next());
+                            render.set_graphics(overrides.apply(quality.settings()));
+                            let present_mode = render.present_mode();
+                            info!(vsync = %render.graphics().vsync, ?present_mode);
+                        }
+                        Action::ToggleFullscreen => {
+                            let fullscreen = match window.fullscreen() {
+                                Some(_) => None,
+                                None => Some(Fullscreen::Borderless(None)),
+                            };
+                            info!(fullscreen = fullscreen.is_some());
+                            window.set_fullscreen(fullscreen);
+                            // Some platforms release the cursor when changing modes
+                            if is_cursor_grabbed {
+                                if let Err(err) = window.set_cursor_grab(true) {
+                                    warn!("Failed to grab the cursor again: {err}");
+                                }
+                            }
+                        }
+                        Action::ReloadSettings => match reload(&args) {
+                            Ok((new_settings, new_bindings)) => {
+                                info!(settings = ?new_settings, "Reloaded settings and bindings");
+                                settings = new_settings;
+                                bindings = new_bindings;
+                                overrides.render_distance =
+                                    args.overrides.render_distance.or(settings.render_distance);
+                                render.set_graphics(overrides.apply(quality.settings()));
+                                render.set_fov(settings.fov.to_radians());
+                            }
+                            Err(err) => error!("Failed to reload settings: {err:#}"),
+                        },
+                    },
+                    InputEvent::MouseMotion(x, y) => {
+                        mouse_motion.0 += x;
+                        mouse_motion.1 += y;
                     }
-                    VirtualKeyCode::LShift => {
-                        spec.update_eye((0.0, -0.05, 0.0));
+                }
+            }
+            let look = settings.look_delta(mouse_motion, frame_time);
+            let (yaw, pitch) = look_smoother.update(look, settings.look_smoothing, frame_time);
+            spec.update_yaw(yaw);
+            spec.update_pitch(pitch);
+            frame += 1;
+
+            // Load chunks around the camera, and drop the meshes of unloaded ones
+            let camera_chunk = (
+                (spec.eye.x / 16.0).floor() as i64,
+                (spec.eye.z / 16.0).floor() as i64,
+            );
+            let camera_subchunk = (
+                camera_chunk.0,
+                (spec.eye.y / 16.0).floor() as i64,
+                camera_chunk.1,
+            );
+            let render_distance = render.graphics().render_distance;
+            for (cx, cz) in chunk_collection.update_loaded(camera_chunk, render_distance) {
+                for s in 0..16 {
+                    render.remove_rendered((cx, s, cz));
+                    mesh_cache.forget_rendered((cx, s, cz));
+                }
+            }
+
+            // Re-mesh the chunks whose level of detail changed as the camera moved, along with
+            // their neighbors, whose faces along the borders are culled against them
+            if let Some(last) = last_camera_chunk.filter(|last| *last != camera_chunk) {
+                for (cx, cz) in chunk_collection.loaded_chunk_coordinates() {
+                    if lod::lod_level((cx, cz), last) == lod::lod_level((cx, cz), camera_chunk) {
+                        continue;
                     }
-                    VirtualKeyCode::G => {
-                        window.set_cursor_visible(is_cursor_grabbed);
-                        window.set_cursor_grab(!is_cursor_grabbed).unwrap();
-                        is_cursor_grabbed = !is_cursor_grabbed;
+                    for (dx, dz) in [(0, 0)].into_iter().chain(lod::NEIGHBORS) {
+                        if let Some(chunk) = chunk_collection.get_chunk_mut((cx + dx, cz + dz)) {
+                            chunk.mark_dirty();
+                        }
                     }
-                    _ => {}
                 }
             }
-            _ => {}
-        },
-        Event::MainEventsCleared => {
+            last_camera_chunk = Some(camera_chunk);
+
+            let pending_meshes = chunk_collection.dirty_subchunk_count();
+
             // re-render dirty subchunks
-            re_render_chunks(&mut chunk_collection, &mut render);
+            re_render_chunks(
+                &mut chunk_collection,
+                &mut render,
+                &mut mesh_cache,
+                camera_subchunk,
+            );
+
+            for asset in assets.poll_changed() {
+                info!(?asset, "Reloading changed asset");
+                match asset {
+                    TextureAsset::GrassTop => {
+                        render.set_block_texture(&assets.load_texture(asset));
+                    }
+                }
+            }
+
+            if let Some(source) = shader_watcher.as_mut().and_then(FileWatcher::poll_changed) {
+                match source {
+                    Ok(source) => match handle.block_on(render.reload_shader(&source)) {
+                        Ok(()) => info!("Reloaded {SHADER_SOURCE_PATH}"),
+                        Err(err) => {
+                            error!("Failed to reload the shader, keeping the old one: {err}")
+                        }
+                    },
+                    Err(err) => error!("{err:#}"),
+                }
+            }
 
+            time_of_day.update();
+            render.set_sky(sky::sky_colors(time_of_day.hours()));
             render.set_view_matrix(spec.view_matrix());
             render.update();
 
+            let size = render.size();
+            let overlay = render.overlay_mut();
+            overlay.clear();
+            overlay.push_crosshair(size);
+
+            target = raycast(
+                spec.eye.to_array(),
+                spec.look_direction().to_array(),
+                TARGET_DISTANCE,
+                |pos| {
+                    matches!(chunk_collection.get_block(pos),
+                        MaybeLoadedBlock::Loaded(block) if block.is_solid())
+                },
+            )
+            .and_then(|hit| match chunk_collection.get_block(hit.pos) {
+                MaybeLoadedBlock::Loaded(block) => Some((hit, block)),
+                MaybeLoadedBlock::Unloaded => None,
+            });
+            let debug_info = DebugInfo {
+                eye: spec.eye,
+                pitch: spec.pitch,
+                yaw: spec.yaw,
+                loaded_chunks: chunk_collection.loaded_chunk_count(),
+                render_stats: render.stats(),
+                pending_meshes,
+                hours: time_of_day.hours(),
+                view_proj: render.view_proj(),
+                screen_size: size,
+                target,
+            };
+            // The heatmap goes first, so that it doesn't cover the text panels
+            if subchunk_panel.is_visible() || heatmap.is_visible() {
+                let stats = render.subchunk_stats();
+                heatmap.push(render.overlay_mut(), &stats, &debug_info);
+                subchunk_panel.push(render.overlay_mut(), stats, size);
+            }
+            debug_overlay.push(render.overlay_mut(), &debug_info);
+
+            limiter.set_max_fps(limiter::max_fps(render.graphics().max_fps, is_focused));
+            limiter.wait();
+
             info!("Rendering frame");
             let render_result = handle.block_on(render.render());
             match render_result {
@@ -88,11 +373,17 @@ fn run(handle: Handle) {
                 Err(SurfaceError::OutOfMemory) => *control_flow = ControlFlow::Exit,
                 Err(SurfaceError::Timeout) => warn!("Surface timeout"),
             }
+            debug_overlay.end_frame();
+            if last_title_update.elapsed() >= TITLE_UPDATE_INTERVAL {
+                let fps = debug_overlay.fps();
+                window.set_title(&format!("{} - {fps:.0} FPS", settings.window_title));
+                last_title_update = Instant::now();
+            }
         }
         Event::DeviceEvent { event, .. } => match event {
-            winit::event::DeviceEvent::MouseMotion { delta: (x, y) } => {
-                spec.update_yaw(x as f32 * 0.01);
-                spec.update_pitch(y as f32 * -0.01);
+            // Device events keep coming while unfocused, but aren't meant for this window
+            winit::event::DeviceEvent::MouseMotion { delta: (x, y) } if is_focused => {
+                frame_inputs.push(InputEvent::MouseMotion(x, y));
             }
             _ => {}
         },
@@ -100,6 +391,14 @@ fn run(handle: Handle) {
     });
 }
 
+/// Load the settings and the key bindings from their files.
+fn reload(args: &args::Args) -> Result<(Settings, Bindings)> {
+    Ok((
+        Settings::load(&args.settings)?,
+        Bindings::load(&args.bindings)?,
+    ))
+}
+
 fn init_tracing() {
     use std::str::FromStr;
     use tracing_subscriber::*;
@@ -114,153 +413,70 @@ fn init_tracing() {
         .init();
 }
 
-fn re_render_chunks(chunk_collection: &mut chunk::ChunkCollection, render: &mut render::Render) {
-    let coords = chunk_collection.loaded_chunk_coordinates();
-    for (cx, cz) in coords {
-        for s in 0..16 {
-            re_render_subchunk(chunk_collection, render, (cx, cz), s);
+/// Re-render dirty subchunks nearest to the subchunk `camera` first, until [`MESHING_BUDGET`]
+/// is used up. The rest are left dirty for the following frames.
+fn re_render_chunks(
+    chunk_collection: &mut chunk::ChunkCollection,
+    render: &mut render::Render,
+    mesh_cache: &mut MeshCache,
+    (camera_x, camera_s, camera_z): (i64, i64, i64),
+) {
+    let start = Instant::now();
+    for (cx, s, cz) in chunk_collection.dirty_subchunks((camera_x, camera_s, camera_z)) {
+        if start.elapsed() > MESHING_BUDGET {
+            break;
         }
+        let lods = lod::Lods::new((cx, cz), (camera_x, camera_z));
+        re_render_subchunk(
+            chunk_collection,
+            render,
+            mesh_cache,
+            (cx, cz),
+            s as usize,
+            lods,
+        );
     }
 }
 
 fn re_render_subchunk(
     chunk_collection: &mut chunk::ChunkCollection,
     render: &mut render::Render,
+    mesh_cache: &mut MeshCache,
     (cx, cz): (i64, i64),
     s: usize,
+    lods: lod::Lods,
 ) {
-    let is_dirty = chunk_collection.get_chunk((cx, cz)).is_subchunk_dirty(s);
-    if is_dirty == false {
+    // The chunk may have been unloaded since its coordinates were taken
+    let chunk = match chunk_collection.get_chunk_mut((cx, cz)) {
+        Some(chunk) if chunk.is_subchunk_dirty(s) => chunk,
+        _ => return,
+    };
+    chunk.unmark_subchunk_dirty(s);
+
+    let key = (cx, s as i64, cz);
+    let start = (cx * 16, s as i64 * 16, cz * 16);
+    let hash = subchunk_hash(chunk_collection, start, lods);
+    if mesh_cache.is_rendered(key, hash) {
         return;
     }
-    chunk_collection
-        .get_chunk_mut((cx, cz))
-        .unmark_subchunk_dirty(s);
-    info!("Re-rendering chunk at (cx = {cx}, cz = {cz})");
-
-    // redraw the subchunk at (cx, s, cz)
-    let mut buffer = render::RenderedBuffer::new();
-
-    let x_start = cx * 16;
-    let y_start = s as i64 * 16;
-    let z_start = cz * 16;
-
-    let x_end = x_start + 16;
-    let y_end = y_start + 16;
-    let z_end = z_start + 16;
-
-    for (x, y, z) in iproduct!(x_start..x_end, y_start..y_end, z_start..z_end) {
-        let block = match chunk_collection.get_block((x, y, z)) {
-            MaybeLoadedBlock::Loaded(block) => block,
-            MaybeLoadedBlock::Unloaded => continue,
-        };
-        if block.is_opaque() == false {
-            continue;
+    let (buffer, opacity) = match mesh_cache.get(hash) {
+        Some(mesh) => mesh.clone(),
+        None => {
+            info!("Re-rendering chunk at (cx = {cx}, cz = {cz})");
+            let mesh = mesh_subchunk(chunk_collection, start, lods);
+            mesh_cache.insert(hash, mesh.clone());
+            mesh
         }
-
-        let sx = x.rem_euclid(16);
-        let sy = y.rem_euclid(16);
-        let sz = z.rem_euclid(16);
-
-        // Storage for the blocks nearby
-        let nearbys = NearbyBlocks::new((x, y, z), chunk_collection);
-        let opaque_count_of_face = |face: [Vertex; 4]| {
-            face.map(Vertex::pos_i64)
-                .map(|(vx, vy, vz)| nearbys.opaque_count((vx, vy, vz)))
-        };
-
-        if let MaybeLoadedBlock::Loaded(block) = nearbys.at((0, 1, 0)) {
-            if block.is_opaque() == false {
-                let opaque_counts = opaque_count_of_face(render::TOP_FACE);
-                buffer._push_face(render::TOP_FACE, opaque_counts, (sx, sy, sz));
-            }
-        }
-
-        if let MaybeLoadedBlock::Loaded(below_block) = nearbys.at((0, -1, 0)) {
-            if below_block.is_opaque() == false {
-                let opaque_counts = opaque_count_of_face(render::BOTTOM_FACE);
-                buffer._push_face(render::BOTTOM_FACE, opaque_counts, (sx, sy, sz));
-            }
-        }
-
-        if let MaybeLoadedBlock::Loaded(right_block) = nearbys.at((1, 0, 0)) {
-            if right_block.is_opaque() == false {
-                let opaque_counts = opaque_count_of_face(render::RIGHT_FACE);
-                buffer._push_face(render::RIGHT_FACE, opaque_counts, (sx, sy, sz));
-            }
-        }
-
-        if let MaybeLoadedBlock::Loaded(left_block) = nearbys.at((-1, 0, 0)) {
-            if left_block.is_opaque() == false {
-                let opaque_counts = opaque_count_of_face(render::LEFT_FACE);
-                buffer._push_face(render::LEFT_FACE, opaque_counts, (sx, sy, sz));
-            }
-        }
-
-        if let MaybeLoadedBlock::Loaded(front_block) = nearbys.at((0, 0, 1)) {
-            if front_block.is_opaque() == false {
-                let opaque_counts = opaque_count_of_face(render::FRONT_FACE);
-                buffer._push_face(render::FRONT_FACE, opaque_counts, (sx, sy, sz));
-            }
-        }
-
-        if let MaybeLoadedBlock::Loaded(rear_block) = nearbys.at((0, 0, -1)) {
-            if rear_block.is_opaque() == false {
-                let opaque_counts = opaque_count_of_face(render::REAR_FACE);
-                buffer._push_face(render::REAR_FACE, opaque_counts, (sx, sy, sz));
-            }
-        }
-    }
-
-    render.insert_rendered((cx, s as i64, cz), buffer);
-}
-
-/// Blocks within a 3x3x3 region around a center block.
-struct NearbyBlocks {
-    blocks: [[[MaybeLoadedBlock; 3]; 3]; 3],
-    opaques: [[[bool; 3]; 3]; 3],
-}
-
-impl NearbyBlocks {
-    fn new((x, y, z): (i64, i64, i64), chunk_collection: &chunk::ChunkCollection) -> Self {
-        let mut blocks = [[[MaybeLoadedBlock::Unloaded; 3]; 3]; 3];
-        for (dx, dy, dz) in iproduct!(-1..=1, -1..=1, -1..=1) {
-            blocks[(dx + 1) as usize][(dy + 1) as usize][(dz + 1) as usize] =
-                chunk_collection.get_block((x + dx, y + dy, z + dz));
-        }
-
-        let opaques = blocks.map(|b| {
-            b.map(|c| {
-                c.map(|block| match block {
-                    MaybeLoadedBlock::Loaded(block) => block.is_opaque(),
-                    MaybeLoadedBlock::Unloaded => false,
-                })
-            })
-        });
-        Self { blocks, opaques }
-    }
-
-    fn at(&self, (dx, dy, dz): (i64, i64, i64)) -> MaybeLoadedBlock {
-        self.blocks[(dx + 1) as usize][(dy + 1) as usize][(dz + 1) as usize]
-    }
-
-    /// Get the number of opaque blocks at the corner `(vx, vy, vz)`, specified in vertex
-    /// coordinates on the centeral unit block.
-    fn opaque_count(&self, (vx, vy, vz): (i64, i64, i64)) -> u8 {
-        // The filter (i.e. the unit block) is 2x2x2, while the input (i.e. the nearbys) is 3x3x3.
-        // This is like a 3d convolution with a 2x2x2 filter of all 1's.
-        iproduct!(vx..=(vx + 1), vy..=(vy + 1), vz..=(vz + 1))
-            .map(|(dx, dy, dz)| self.opaques[dx as usize][dy as usize][dz as usize])
-            .filter(|b| *b)
-            .count() as u8
-    }
+    };
+    render.insert_rendered(key, buffer, &opacity);
+    mesh_cache.set_rendered(key, hash);
 }
 
 #[derive(Debug)]
 struct Spectator {
     /// The view position.
     eye: Vec3,
-    /// Pitch (up-down rotation axis of head), `0` at the eye level, positive down, in radians.
+    /// Pitch (up-down rotation axis of head), `0` at the eye level, positive up, in radians.
     pitch: f32,
     /// Yaw (horizontal rotation axis of head), `0` towards east, clockwise.
     yaw: f32,
@@ -276,10 +492,11 @@ impl Spectator {
     }
 
     fn update_pitch(&mut self, delta: f32) {
+        // Looking straight up or down would make the look direction parallel to the up vector of
+        // the view matrix, leaving the yaw undefined
+        const MAX_PITCH: f32 = std::f32::consts::FRAC_PI_2 - 0.01;
         self.pitch += delta;
-        self.pitch = self
-            .pitch
-            .clamp(-std::f32::consts::FRAC_PI_2, std::f32::consts::FRAC_PI_2);
+        self.pitch = self.pitch.clamp(-MAX_PITCH, MAX_PITCH);
     }
 
     fn update_yaw(&mut self, delta: f32) {
@@ -291,13 +508,67 @@ impl Spectator {
         self.eye += delta.into();
     }
 
+    /// Get the unit vector the spectator is looking along.
+    fn look_direction(&self) -> Vec3 {
+        let (sin_pitch, cos_pitch) = self.pitch.sin_cos();
+        let (sin_yaw, cos_yaw) = self.yaw.sin_cos();
+        vec3(cos_pitch * cos_yaw, sin_pitch, cos_pitch * sin_yaw)
+    }
+
+    /// Get the horizontal unit vector the spectator is facing, for moving forward.
+    fn forward(&self) -> Vec3 {
+        let (sin_yaw, cos_yaw) = self.yaw.sin_cos();
+        vec3(cos_yaw, 0.0, sin_yaw)
+    }
+
+    /// Get the horizontal unit vector to the right of the spectator, for strafing.
+    fn right(&self) -> Vec3 {
+        self.forward().cross(Vec3::Y)
+    }
+
     fn view_matrix(&self) -> Mat4 {
         info!(?self);
 
-        let look_direction = vec3(f32::cos(self.yaw), f32::sin(self.pitch), f32::sin(self.yaw));
-        let look_point = self.eye + look_direction;
+        let look_point = self.eye + self.look_direction();
 
         const UP: Vec3 = vec3(0.0, 1.0, 0.0);
         Mat4::look_at_rh(self.eye, look_point, UP)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::f32::consts::{FRAC_PI_2, FRAC_PI_4, PI};
+
+    fn look(pitch: f32, yaw: f32) -> Vec3 {
+        Spectator::new(Vec3::ZERO, pitch, yaw).look_direction()
+    }
+
+    #[test]
+    fn test_look_direction_cardinal() {
+        assert!(look(0.0, 0.0).abs_diff_eq(Vec3::X, 1e-6));
+        assert!(look(0.0, FRAC_PI_2).abs_diff_eq(Vec3::Z, 1e-6));
+        assert!(look(0.0, PI).abs_diff_eq(-Vec3::X, 1e-6));
+        assert!(look(0.0, 3.0 * FRAC_PI_2).abs_diff_eq(-Vec3::Z, 1e-6));
+        assert!(look(FRAC_PI_2, 0.0).abs_diff_eq(Vec3::Y, 1e-6));
+        assert!(look(-FRAC_PI_2, 1.0).abs_diff_eq(-Vec3::Y, 1e-6));
+    }
+
+    #[test]
+    fn test_look_direction_keeps_yaw_when_pitched() {
+        let direction = look(FRAC_PI_4, FRAC_PI_4);
+        assert!((direction.length() - 1.0).abs() < 1e-6);
+        // Looking up doesn't turn the horizontal direction
+        let horizontal = vec3(direction.x, 0.0, direction.z).normalize();
+        assert!(horizontal.abs_diff_eq(look(0.0, FRAC_PI_4), 1e-6));
+    }
+
+    #[test]
+    fn test_forward_right() {
+        let spec = Spectator::new(Vec3::ZERO, 0.7, FRAC_PI_2);
+        assert!(spec.forward().abs_diff_eq(Vec3::Z, 1e-6));
+        assert!(spec.right().abs_diff_eq(-Vec3::X, 1e-6));
+        assert!(spec.forward().dot(spec.right()).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,102 @@
+//! Caching of subchunk meshes by a hash of the blocks they are built from, so that subchunks
+//! marked dirty without their blocks changing aren't meshed and uploaded again.
+
+use std::collections::VecDeque;
+
+use hashbrown::HashMap;
+
+use crate::render::{OpacityVolume, RenderedBuffer, RenderedBufferKey};
+
+/// A subchunk mesh, and the opacity its AO is baked from.
+pub type Mesh = (RenderedBuffer, OpacityVolume);
+
+pub struct MeshCache {
+    meshes: HashMap<u64, Mesh>,
+    /// Hashes of the cached meshes, least recently used first.
+    lru: VecDeque<u64>,
+    capacity: usize,
+    /// The hash of the mesh currently rendered for each subchunk.
+    rendered: HashMap<RenderedBufferKey, u64>,
+}
+
+impl MeshCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            meshes: HashMap::new(),
+            lru: VecDeque::new(),
+            capacity,
+            rendered: HashMap::new(),
+        }
+    }
+
+    /// Check whether the mesh rendered for `key` was built from blocks with `hash`.
+    pub fn is_rendered(&self, key: RenderedBufferKey, hash: u64) -> bool {
+        self.rendered.get(&key) == Some(&hash)
+    }
+
+    pub fn set_rendered(&mut self, key: RenderedBufferKey, hash: u64) {
+        self.rendered.insert(key, hash);
+    }
+
+    /// Forget the mesh rendered for `key`, e.g. after its buffers were removed.
+    pub fn forget_rendered(&mut self, key: RenderedBufferKey) {
+        self.rendered.remove(&key);
+    }
+
+    /// Get the mesh built from blocks with `hash`, marking it as recently used.
+    pub fn get(&mut self, hash: u64) -> Option<&Mesh> {
+        let mesh = self.meshes.get(&hash)?;
+        if let Some(index) = self.lru.iter().position(|h| *h == hash) {
+            self.lru.remove(index);
+        }
+        self.lru.push_back(hash);
+        Some(mesh)
+    }
+
+    /// Cache the mesh built from blocks with `hash`, evicting the least recently used mesh if
+    /// the cache is full.
+    pub fn insert(&mut self, hash: u64, mesh: Mesh) {
+        if self.meshes.insert(hash, mesh).is_some() {
+            return;
+        }
+        self.lru.push_back(hash);
+        if self.lru.len() > self.capacity {
+            if let Some(evicted) = self.lru.pop_front() {
+                self.meshes.remove(&evicted);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn mesh() -> Mesh {
+        (RenderedBuffer::new(), OpacityVolume::new())
+    }
+
+    #[test]
+    fn test_lru() {
+        let mut cache = MeshCache::new(2);
+        cache.insert(1, mesh());
+        cache.insert(2, mesh());
+        assert!(cache.get(1).is_some());
+        // 2 is the least recently used
+        cache.insert(3, mesh());
+        assert!(cache.get(2).is_none());
+        assert!(cache.get(1).is_some());
+        assert!(cache.get(3).is_some());
+        assert_eq!(cache.meshes.len(), 2);
+    }
+
+    #[test]
+    fn test_rendered() {
+        let mut cache = MeshCache::new(2);
+        cache.set_rendered((0, 1, 2), 7);
+        assert!(cache.is_rendered((0, 1, 2), 7));
+        assert!(!cache.is_rendered((0, 1, 2), 8));
+        cache.forget_rendered((0, 1, 2));
+        assert!(!cache.is_rendered((0, 1, 2), 7));
+    }
+}
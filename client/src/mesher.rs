@@ -0,0 +1,251 @@
+//! Building the meshes of subchunks from the blocks around them.
+
+use std::collections::hash_map::DefaultHasher;
+use std::hash::Hasher;
+
+use itertools::iproduct;
+
+use crate::chunk::{Block, ChunkCollection, MaybeLoadedBlock};
+use crate::lod::{self, Lods};
+use crate::mesh_cache::Mesh;
+use crate::render::{self, RenderedBuffer, AO_HALO_SIZE};
+
+/// Hash the blocks the mesh of the subchunk starting at `start` is built from, along with the
+/// levels of detail it's meshed at. These are the blocks of the subchunk and a halo around it,
+/// which is 1 block thick at full detail and a cell thick at lower levels of detail.
+pub fn subchunk_hash(
+    chunk_collection: &ChunkCollection,
+    (x_start, y_start, z_start): (i64, i64, i64),
+    lods: Lods,
+) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    hasher.write_u32(lods.own);
+    for lod in lods.neighbors {
+        hasher.write_u32(lod);
+    }
+    // Faces are culled against whole cells, which reach up to a cell deep into the neighbors
+    let halo = 1 << lods.max();
+    let range = || -halo..16 + halo;
+    for (dx, dy, dz) in iproduct!(range(), range(), range()) {
+        let pos = (x_start + dx, y_start + dy, z_start + dz);
+        hasher.write_u8(match chunk_collection.get_block(pos) {
+            MaybeLoadedBlock::Loaded(block) => block.id(),
+            MaybeLoadedBlock::Unloaded => u8::MAX,
+        });
+    }
+    hasher.finish()
+}
+
+/// Build the mesh of the subchunk starting at world coordinates `start`, at the levels of detail
+/// `lods` of its chunk and the chunks next to it.
+pub fn mesh_subchunk(
+    chunk_collection: &ChunkCollection,
+    (x_start, y_start, z_start): (i64, i64, i64),
+    lods: Lods,
+) -> Mesh {
+    let mut buffer = RenderedBuffer::new();
+    if lods.own > 0 {
+        lod::mesh_cells(
+            chunk_collection,
+            (x_start, y_start, z_start),
+            lods.own,
+            &mut buffer,
+        );
+    } else {
+        let x_end = x_start + 16;
+        let y_end = y_start + 16;
+        let z_end = z_start + 16;
+        let (cx, cz) = (x_start.div_euclid(16), z_start.div_euclid(16));
+
+        // Only visible blocks are meshed, so columns end at their height
+        for (x, z) in iproduct!(x_start..x_end, z_start..z_end) {
+            let height = match chunk_collection.get_height((x, z)) {
+                Some(height) => height,
+                None => continue,
+            };
+            for y in y_start..y_end.min(height) {
+                let block = match chunk_collection.get_block((x, y, z)) {
+                    MaybeLoadedBlock::Loaded(block) => block,
+                    MaybeLoadedBlock::Unloaded => continue,
+                };
+                if !block.is_visible() {
+                    continue;
+                }
+                let hides_face = |(dx, dy, dz): (i64, i64, i64), neighbor: Block| {
+                    let pos = (x + dx, y + dy, z + dz);
+                    let offset = (pos.0.div_euclid(16) - cx, pos.2.div_euclid(16) - cz);
+                    match lods.at(offset) {
+                        // Transparent neighbors only hide the faces between blocks of their own
+                        // kind, e.g. within a body of water
+                        0 => neighbor.is_opaque() || neighbor == block,
+                        // Neighbors in chunks meshed at a lower level of detail are drawn as
+                        // cells, which may leave cracks where single blocks would cover faces
+                        lod => lod::hides_face(chunk_collection, pos, lod, block),
+                    }
+                };
+
+                let layer = render::texture_layer(block);
+
+                let sx = x.rem_euclid(16);
+                let sy = y.rem_euclid(16);
+                let sz = z.rem_euclid(16);
+
+                // Storage for the blocks nearby
+                let nearbys = NearbyBlocks::new((x, y, z), chunk_collection);
+
+                if let MaybeLoadedBlock::Loaded(block) = nearbys.at((0, 1, 0)) {
+                    if !hides_face((0, 1, 0), block) {
+                        buffer._push_face(render::TOP_FACE, (sx, sy, sz), layer);
+                    }
+                }
+
+                if let MaybeLoadedBlock::Loaded(below_block) = nearbys.at((0, -1, 0)) {
+                    if !hides_face((0, -1, 0), below_block) {
+                        buffer._push_face(render::BOTTOM_FACE, (sx, sy, sz), layer);
+                    }
+                }
+
+                if let MaybeLoadedBlock::Loaded(right_block) = nearbys.at((1, 0, 0)) {
+                    if !hides_face((1, 0, 0), right_block) {
+                        buffer._push_face(render::RIGHT_FACE, (sx, sy, sz), layer);
+                    }
+                }
+
+                if let MaybeLoadedBlock::Loaded(left_block) = nearbys.at((-1, 0, 0)) {
+                    if !hides_face((-1, 0, 0), left_block) {
+                        buffer._push_face(render::LEFT_FACE, (sx, sy, sz), layer);
+                    }
+                }
+
+                if let MaybeLoadedBlock::Loaded(front_block) = nearbys.at((0, 0, 1)) {
+                    if !hides_face((0, 0, 1), front_block) {
+                        buffer._push_face(render::FRONT_FACE, (sx, sy, sz), layer);
+                    }
+                }
+
+                if let MaybeLoadedBlock::Loaded(rear_block) = nearbys.at((0, 0, -1)) {
+                    if !hides_face((0, 0, -1), rear_block) {
+                        buffer._push_face(render::REAR_FACE, (sx, sy, sz), layer);
+                    }
+                }
+            }
+        }
+    }
+
+    // Opacity of the subchunk and a 1-block halo around it, for baking ambient occlusion
+    let mut opacity = render::OpacityVolume::new();
+    for (hx, hy, hz) in iproduct!(0..AO_HALO_SIZE, 0..AO_HALO_SIZE, 0..AO_HALO_SIZE) {
+        let pos = (
+            x_start + hx as i64 - 1,
+            y_start + hy as i64 - 1,
+            z_start + hz as i64 - 1,
+        );
+        if let MaybeLoadedBlock::Loaded(block) = chunk_collection.get_block(pos) {
+            opacity.set((hx, hy, hz), block.is_opaque());
+        }
+    }
+
+    (buffer, opacity)
+}
+
+/// Blocks within a 3x3x3 region around a center block.
+pub struct NearbyBlocks {
+    blocks: [[[MaybeLoadedBlock; 3]; 3]; 3],
+}
+
+impl NearbyBlocks {
+    pub fn new((x, y, z): (i64, i64, i64), chunk_collection: &ChunkCollection) -> Self {
+        let mut blocks = [[[MaybeLoadedBlock::Unloaded; 3]; 3]; 3];
+        for (dx, dy, dz) in iproduct!(-1..=1, -1..=1, -1..=1) {
+            blocks[(dx + 1) as usize][(dy + 1) as usize][(dz + 1) as usize] =
+                chunk_collection.get_block((x + dx, y + dy, z + dz));
+        }
+        Self { blocks }
+    }
+
+    pub fn at(&self, (dx, dy, dz): (i64, i64, i64)) -> MaybeLoadedBlock {
+        self.blocks[(dx + 1) as usize][(dy + 1) as usize][(dz + 1) as usize]
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use tokio::runtime::Runtime;
+    use wgpu_block_shared::chunk::Chunk;
+
+    /// Check whether `mesh` has a face of `block` whose vertices all lie on the plane `x = plane`.
+    fn has_face_at_x(mesh: &RenderedBuffer, block: Block, plane: f32) -> bool {
+        mesh.vertices().chunks(4).any(|face| {
+            face.iter()
+                .all(|v| v.layer == render::texture_layer(block) && v.pos[0] == plane)
+        })
+    }
+
+    #[test]
+    fn test_face_toward_water() {
+        let runtime = Runtime::new().unwrap();
+        let mut collection = ChunkCollection::new(0, runtime.handle().clone());
+        let mut chunk = Chunk::default();
+        chunk.set((5, 3, 5), Block::Stone);
+        chunk.set((6, 3, 5), Block::Water);
+        chunk.set((7, 3, 5), Block::Water);
+        collection.insert_chunk((0, 0), chunk);
+
+        let (mesh, _) = mesh_subchunk(&collection, (0, 0, 0), Lods::default());
+        // The stone is drawn behind the water, which hides no faces of its own kind
+        assert!(has_face_at_x(&mesh, Block::Stone, 6.0));
+        assert!(!has_face_at_x(&mesh, Block::Water, 6.0));
+        assert!(!has_face_at_x(&mesh, Block::Water, 7.0));
+        assert!(has_face_at_x(&mesh, Block::Water, 8.0));
+    }
+
+    #[test]
+    fn test_hash_halo() {
+        let runtime = Runtime::new().unwrap();
+        let mut collection = ChunkCollection::new(0, runtime.handle().clone());
+        collection.insert_chunk((0, 0), Chunk::default());
+        collection.insert_chunk((1, 0), Chunk::default());
+        let coarse = Lods {
+            own: 2,
+            neighbors: [2; 4],
+        };
+        let hashes = |collection: &ChunkCollection| {
+            (
+                subchunk_hash(collection, (0, 0, 0), Lods::default()),
+                subchunk_hash(collection, (0, 0, 0), coarse),
+            )
+        };
+
+        let (fine, before) = hashes(&collection);
+        let mut neighbor = Chunk::default();
+        neighbor.set((3, 3, 5), Block::Stone);
+        collection.insert_chunk((1, 0), neighbor);
+        let (fine_after, after) = hashes(&collection);
+        assert_eq!(fine, fine_after);
+        assert_ne!(before, after);
+    }
+
+    #[test]
+    fn test_face_toward_lower_detail() {
+        let runtime = Runtime::new().unwrap();
+        let mut collection = ChunkCollection::new(0, runtime.handle().clone());
+        let mut chunk = Chunk::default();
+        chunk.set((15, 3, 5), Block::Stone);
+        collection.insert_chunk((0, 0), chunk);
+        // Too sparse for its cell to be drawn at a lower level of detail
+        let mut neighbor = Chunk::default();
+        neighbor.set((0, 3, 5), Block::Stone);
+        collection.insert_chunk((1, 0), neighbor);
+
+        let (mesh, _) = mesh_subchunk(&collection, (0, 0, 0), Lods::default());
+        assert!(!has_face_at_x(&mesh, Block::Stone, 16.0));
+        let lods = Lods {
+            own: 0,
+            neighbors: [1, 0, 0, 0],
+        };
+        let (mesh, _) = mesh_subchunk(&collection, (0, 0, 0), lods);
+        assert!(has_face_at_x(&mesh, Block::Stone, 16.0));
+    }
+}
@@ -0,0 +1,212 @@
+//! Screen-space overlay primitives, drawn on top of the world without the view/projection
+//! transform.
+//!
+//! Overlay coordinates are in physical pixels, with the origin at the top-left corner of the
+//! window and `+y` pointing down.
+
+use bytemuck::{Pod, Zeroable};
+use winit::dpi::PhysicalSize;
+
+use crate::font::{self, GLYPH_HEIGHT, GLYPH_WIDTH};
+
+pub type Color = [f32; 4];
+
+pub const WHITE: Color = [1.0, 1.0, 1.0, 1.0];
+pub const YELLOW: Color = [1.0, 0.9, 0.2, 1.0];
+pub const TRANSLUCENT_WHITE: Color = [1.0, 1.0, 1.0, 0.5];
+pub const TRANSLUCENT_BLACK: Color = [0.0, 0.0, 0.0, 0.5];
+
+/// Spacing between characters and lines, in font pixels.
+const TEXT_SPACING: usize = 1;
+
+/// A host-side buffer of overlay quads, rebuilt every frame.
+#[derive(Default)]
+pub struct OverlayBuffer {
+    vertices: Vec<OverlayVertex>,
+    indices: Vec<u32>,
+}
+
+impl OverlayBuffer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn clear(&mut self) {
+        self.vertices.clear();
+        self.indices.clear();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+
+    pub fn vertices(&self) -> &[OverlayVertex] {
+        &self.vertices
+    }
+
+    pub fn indices(&self) -> &[u32] {
+        &self.indices
+    }
+
+    /// Push an axis-aligned rectangle with its top-left corner at `(x, y)`.
+    pub fn push_rect(&mut self, (x, y): (f32, f32), (w, h): (f32, f32), color: Color) {
+        self.push_quad([(x, y), (x, y + h), (x + w, y + h), (x + w, y)], color);
+    }
+
+    /// Push a line segment from `a` to `b` with the given thickness.
+    pub fn push_line(&mut self, a: (f32, f32), b: (f32, f32), thickness: f32, color: Color) {
+        let (dx, dy) = (b.0 - a.0, b.1 - a.1);
+        let length = (dx * dx + dy * dy).sqrt();
+        if length == 0.0 {
+            return;
+        }
+        // Offset perpendicular to the line by half the thickness
+        let (nx, ny) = (
+            -dy / length * thickness / 2.0,
+            dx / length * thickness / 2.0,
+        );
+        self.push_quad(
+            [
+                (a.0 + nx, a.1 + ny),
+                (a.0 - nx, a.1 - ny),
+                (b.0 - nx, b.1 - ny),
+                (b.0 + nx, b.1 + ny),
+            ],
+            color,
+        );
+    }
+
+    /// Push an arbitrary quad, with its corners in order around the edge.
+    pub fn push_quad(&mut self, corners: [(f32, f32); 4], color: Color) {
+        let index_start = self.vertices.len() as u32;
+        self.vertices
+            .extend(corners.map(|corner| OverlayVertex::new(corner, color)));
+        self.indices
+            .extend_from_slice(&[0, 1, 2, 2, 3, 0].map(|i| i + index_start));
+    }
+
+    /// Push text with its top-left corner at `(x, y)`, where every font pixel is `scale` physical
+    /// pixels large. Lines are separated by `\n`.
+    pub fn push_text(&mut self, (x, y): (f32, f32), text: &str, scale: f32, color: Color) {
+        let advance_x = (GLYPH_WIDTH + TEXT_SPACING) as f32 * scale;
+        let advance_y = (GLYPH_HEIGHT + TEXT_SPACING) as f32 * scale;
+
+        for (line_index, line) in text.lines().enumerate() {
+            let line_y = y + line_index as f32 * advance_y;
+            for (char_index, c) in line.chars().enumerate() {
+                let char_x = x + char_index as f32 * advance_x;
+                for (row_index, row) in font::glyph(c).into_iter().enumerate() {
+                    let row_y = line_y + row_index as f32 * scale;
+                    // Merge horizontal runs of lit pixels into single quads
+                    let mut column = 0;
+                    while column < GLYPH_WIDTH {
+                        let is_lit = |column: usize| row & (1 << (GLYPH_WIDTH - 1 - column)) != 0;
+                        if !is_lit(column) {
+                            column += 1;
+                            continue;
+                        }
+                        let run_start = column;
+                        while column < GLYPH_WIDTH && is_lit(column) {
+                            column += 1;
+                        }
+                        self.push_rect(
+                            (char_x + run_start as f32 * scale, row_y),
+                            ((column - run_start) as f32 * scale, scale),
+                            color,
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    /// Push text on top of a translucent background panel.
+    pub fn push_text_panel(&mut self, (x, y): (f32, f32), text: &str, scale: f32, color: Color) {
+        let padding = 2.0 * scale;
+        let (w, h) = text_size(text, scale);
+        self.push_rect(
+            (x, y),
+            (w + padding * 2.0, h + padding * 2.0),
+            TRANSLUCENT_BLACK,
+        );
+        self.push_text((x + padding, y + padding), text, scale, color);
+    }
+
+    /// Push a crosshair at the center of a screen of the given size.
+    pub fn push_crosshair(&mut self, size: PhysicalSize<u32>) {
+        const ARM_LENGTH: f32 = 10.0;
+        const THICKNESS: f32 = 2.0;
+
+        let cx = (size.width / 2) as f32;
+        let cy = (size.height / 2) as f32;
+        self.push_rect(
+            (cx - ARM_LENGTH, cy - THICKNESS / 2.0),
+            (ARM_LENGTH * 2.0, THICKNESS),
+            WHITE,
+        );
+        self.push_rect(
+            (cx - THICKNESS / 2.0, cy - ARM_LENGTH),
+            (THICKNESS, ARM_LENGTH * 2.0),
+            WHITE,
+        );
+    }
+}
+
+/// Get the size of `text` as drawn by [`OverlayBuffer::push_text`], in physical pixels.
+pub fn text_size(text: &str, scale: f32) -> (f32, f32) {
+    let columns = text.lines().map(|l| l.chars().count()).max().unwrap_or(0);
+    let rows = text.lines().count();
+    let size = |count: usize, glyph_size: usize| {
+        (count * (glyph_size + TEXT_SPACING)).saturating_sub(TEXT_SPACING) as f32 * scale
+    };
+    (size(columns, GLYPH_WIDTH), size(rows, GLYPH_HEIGHT))
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+pub struct OverlayVertex {
+    pub pos: [f32; 2],
+    pub color: Color,
+}
+
+impl OverlayVertex {
+    fn new((x, y): (f32, f32), color: Color) -> Self {
+        Self { pos: [x, y], color }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_push_text_merges_runs() {
+        let mut overlay = OverlayBuffer::new();
+        // A single horizontal bar of 5 pixels should become one quad
+        overlay.push_text((0.0, 0.0), "-", 1.0, WHITE);
+        assert_eq!(overlay.indices().len(), 6);
+
+        overlay.clear();
+        overlay.push_text((0.0, 0.0), " \n ", 1.0, WHITE);
+        assert!(overlay.is_empty());
+    }
+
+    #[test]
+    fn test_text_size() {
+        assert_eq!(text_size("", 1.0), (0.0, 0.0));
+        assert_eq!(text_size("A", 2.0), (10.0, 14.0));
+        assert_eq!(text_size("AB\nC", 1.0), (11.0, 15.0));
+    }
+
+    #[test]
+    fn test_push_line() {
+        let mut overlay = OverlayBuffer::new();
+        overlay.push_line((0.0, 0.0), (10.0, 0.0), 2.0, WHITE);
+        let ys: Vec<f32> = overlay.vertices().iter().map(|v| v.pos[1]).collect();
+        assert_eq!(ys, [1.0, -1.0, -1.0, 1.0]);
+
+        overlay.clear();
+        overlay.push_line((5.0, 5.0), (5.0, 5.0), 2.0, WHITE);
+        assert!(overlay.is_empty());
+    }
+}
@@ -0,0 +1,32 @@
+struct VertexOutput {
+    @location(0) color: vec4<f32>,
+    @builtin(position) pos: vec4<f32>,
+};
+
+struct PushConstantsData {
+    screen_size: vec2<f32>,
+};
+
+var<push_constant> pc: PushConstantsData;
+
+@vertex
+fn main_vs(
+    @location(0) pos: vec2<f32>,
+    @location(1) color: vec4<f32>
+) -> VertexOutput {
+    var out: VertexOutput;
+
+    // Pixel coordinates with the origin at the top-left corner, to NDC
+    let ndc = pos / pc.screen_size * 2.0 - vec2<f32>(1.0, 1.0);
+    out.pos = vec4<f32>(ndc.x, -ndc.y, 0.0, 1.0);
+    out.color = color;
+
+    return out;
+}
+
+@fragment
+fn main_fs(vertex: VertexOutput) -> @location(0) vec4<f32> {
+    return vertex.color;
+}
+
+// vim: set filetype=wgsl:
@@ -0,0 +1,210 @@
+//! Graphics quality presets, each bundling a set of [`GraphicsSettings`].
+
+use std::fmt::Display;
+use std::str::FromStr;
+
+use anyhow::{bail, Error};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QualityPreset {
+    Low,
+    Medium,
+    High,
+}
+
+impl QualityPreset {
+    pub const ALL: [QualityPreset; 3] = [
+        QualityPreset::Low,
+        QualityPreset::Medium,
+        QualityPreset::High,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            QualityPreset::Low => "low",
+            QualityPreset::Medium => "medium",
+            QualityPreset::High => "high",
+        }
+    }
+
+    /// Get the next preset, wrapping around from the highest to the lowest.
+    pub fn next(self) -> Self {
+        match self {
+            QualityPreset::Low => QualityPreset::Medium,
+            QualityPreset::Medium => QualityPreset::High,
+            QualityPreset::High => QualityPreset::Low,
+        }
+    }
+
+    pub fn settings(self) -> GraphicsSettings {
+        match self {
+            QualityPreset::Low => GraphicsSettings {
+                anisotropy: 1,
+                mipmaps: false,
+                ao_strength: 0.5,
+                msaa_samples: 1,
+                render_distance: 4,
+                vsync: VsyncMode::Fifo,
+                max_fps: None,
+            },
+            QualityPreset::Medium => GraphicsSettings {
+                anisotropy: 1,
+                mipmaps: true,
+                ao_strength: 1.0,
+                msaa_samples: 1,
+                render_distance: 8,
+                vsync: VsyncMode::Fifo,
+                max_fps: None,
+            },
+            QualityPreset::High => GraphicsSettings {
+                anisotropy: 16,
+                mipmaps: true,
+                ao_strength: 1.0,
+                msaa_samples: 4,
+                render_distance: 12,
+                vsync: VsyncMode::Fifo,
+                max_fps: None,
+            },
+        }
+    }
+}
+
+impl Display for QualityPreset {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for QualityPreset {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match QualityPreset::ALL
+            .into_iter()
+            .find(|preset| preset.name() == s)
+        {
+            Some(preset) => Ok(preset),
+            None => bail!("unknown quality preset `{s}`, expected low, medium or high"),
+        }
+    }
+}
+
+/// How frames are presented, in the terms of [`wgpu::PresentMode`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VsyncMode {
+    /// Wait for vertical blank, capping the frame rate at the refresh rate.
+    Fifo,
+    /// Render uncapped, presenting the latest frame at vertical blank without tearing.
+    Mailbox,
+    /// Render uncapped and present immediately, possibly tearing.
+    Immediate,
+}
+
+impl VsyncMode {
+    pub const ALL: [VsyncMode; 3] = [VsyncMode::Fifo, VsyncMode::Mailbox, VsyncMode::Immediate];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            VsyncMode::Fifo => "fifo",
+            VsyncMode::Mailbox => "mailbox",
+            VsyncMode::Immediate => "immediate",
+        }
+    }
+
+    /// Get the next mode, wrapping around from the last to the first.
+    pub fn next(self) -> Self {
+        match self {
+            VsyncMode::Fifo => VsyncMode::Mailbox,
+            VsyncMode::Mailbox => VsyncMode::Immediate,
+            VsyncMode::Immediate => VsyncMode::Fifo,
+        }
+    }
+}
+
+impl Display for VsyncMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for VsyncMode {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match VsyncMode::ALL.into_iter().find(|mode| mode.name() == s) {
+            Some(mode) => Ok(mode),
+            None => bail!("unknown vsync mode `{s}`, expected fifo, mailbox or immediate"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GraphicsSettings {
+    /// Maximum anisotropy of texture filtering, where 1 disables anisotropic filtering.
+    pub anisotropy: u8,
+    /// Whether minified textures are sampled from mipmaps, instead of the nearest texel.
+    pub mipmaps: bool,
+    /// How much ambient occlusion darkens block corners, from 0 (not at all) to 1.
+    pub ao_strength: f32,
+    /// Number of samples per pixel, where 1 disables MSAA.
+    pub msaa_samples: u32,
+    /// Radius of the loaded area around the camera, in chunks.
+    pub render_distance: u32,
+    /// The preferred present mode, used if the surface supports it.
+    pub vsync: VsyncMode,
+    /// Cap of the frame rate, or `None` to render as fast as the present mode allows.
+    pub max_fps: Option<u32>,
+}
+
+/// Per-option overrides of the settings of a preset. `None` keeps the preset's value.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct GraphicsOverrides {
+    pub anisotropy: Option<u8>,
+    pub mipmaps: Option<bool>,
+    pub ao_strength: Option<f32>,
+    pub msaa_samples: Option<u32>,
+    pub render_distance: Option<u32>,
+    pub vsync: Option<VsyncMode>,
+    pub max_fps: Option<Option<u32>>,
+}
+
+impl GraphicsOverrides {
+    pub fn apply(&self, settings: GraphicsSettings) -> GraphicsSettings {
+        GraphicsSettings {
+            anisotropy: self.anisotropy.unwrap_or(settings.anisotropy),
+            mipmaps: self.mipmaps.unwrap_or(settings.mipmaps),
+            ao_strength: self.ao_strength.unwrap_or(settings.ao_strength),
+            msaa_samples: self.msaa_samples.unwrap_or(settings.msaa_samples),
+            render_distance: self.render_distance.unwrap_or(settings.render_distance),
+            vsync: self.vsync.unwrap_or(settings.vsync),
+            max_fps: self.max_fps.unwrap_or(settings.max_fps),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_overrides() {
+        let overrides = GraphicsOverrides {
+            msaa_samples: Some(4),
+            ..Default::default()
+        };
+        let settings = overrides.apply(QualityPreset::Low.settings());
+        assert_eq!(settings.msaa_samples, 4);
+        assert_eq!(settings.mipmaps, QualityPreset::Low.settings().mipmaps);
+    }
+
+    #[test]
+    fn test_preset_names() {
+        for preset in QualityPreset::ALL {
+            assert_eq!(preset.name().parse::<QualityPreset>().unwrap(), preset);
+        }
+        assert_eq!(QualityPreset::High.next(), QualityPreset::Low);
+        for mode in VsyncMode::ALL {
+            assert_eq!(mode.name().parse::<VsyncMode>().unwrap(), mode);
+        }
+    }
+}
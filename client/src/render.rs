@@ -2,7 +2,7 @@
 //!
 //! # Coordinate system
 //!
-//! ```
+//! ```text
 //!    (0, 1, 0)______ (1, 1, 0)
 //!            /     /|              ^ +y
 //!           /     / |              |
@@ -13,43 +13,110 @@
 //! ```
 
 use std::mem::size_of;
-use std::num::NonZeroU32;
+use std::num::{NonZeroU32, NonZeroU8};
 
 use bytemuck::{Pod, Zeroable};
-use glam::{vec4, Mat4, Vec3, Vec4};
+use glam::{vec2, vec4, Mat4, Vec2, Vec3, Vec4};
 use hashbrown::HashMap;
+use image::{imageops, RgbaImage};
 use tokio::time::Instant;
-use tracing::error;
-use wgpu::util::{BufferInitDescriptor, DeviceExt};
+use tracing::{error, warn};
+use wgpu::util::{BufferInitDescriptor, DeviceExt, StagingBelt};
 use wgpu::*;
 use winit::{dpi::PhysicalSize, window::Window};
 
+use crate::chunk::Block;
+use crate::gpu_timer::{GpuTimer, GpuTimings, Timestamp};
+use crate::overlay::{OverlayBuffer, OverlayVertex};
+use crate::quality::{GraphicsSettings, VsyncMode};
+use crate::sky::SkyColors;
+
 /// A collection of objects needed for rendering and presenting.
 pub struct Render {
     surface: Surface,
     device: Device,
     queue: Queue,
+    pipeline_layout: PipelineLayout,
+    /// The shader of the block pipelines, which may have been reloaded since startup.
+    shader: ShaderModule,
     pipeline: RenderPipeline,
+    /// The block pipeline drawing edges only, if the adapter supports it.
+    wireframe_pipeline: Option<RenderPipeline>,
+    wireframe: bool,
+    sky_pipeline: RenderPipeline,
+    overlay_pipeline: RenderPipeline,
+    ao_pipeline: ComputePipeline,
+    ao_bake_layout: BindGroupLayout,
+    ao_corners_layout: BindGroupLayout,
     size: PhysicalSize<u32>,
     config: SurfaceConfiguration,
+    /// The present modes supported by the surface.
+    present_modes: Vec<PresentMode>,
+    graphics: GraphicsSettings,
 
     view_matrix: Mat4,
+    /// Vertical field of view, in radians.
+    fov: f32,
+    sky: SkyColors,
 
     uniforms: Uniforms,
+    uniform_data_layout: BindGroupLayout,
     uniform_buffer: Buffer,
     uniform_bind_group: BindGroup,
 
-    grass_bind_group: BindGroup,
+    block_texture_layout: BindGroupLayout,
+    block_texture_view: TextureView,
+    block_texture_bind_group: BindGroup,
 
     depth_texture_view: TextureView,
+    /// The multisampled color target resolved into the surface, if MSAA is enabled.
+    msaa_texture_view: Option<TextureView>,
 
     last_update: tokio::time::Instant,
 
     rendered: RenderedBufferCollection,
+    overlay: OverlayBuffer,
+
+    stats: RenderStats,
+    /// Timing of the passes on the GPU, if the device supports timestamp queries.
+    gpu_timer: Option<GpuTimer>,
+    /// Staging buffers the uniforms and meshes are uploaded through, reused across frames.
+    staging_belt: StagingBelt,
+}
+
+/// Statistics of the buffers of one subchunk.
+#[derive(Debug, Clone, Copy)]
+pub struct SubchunkStats {
+    pub key: RenderedBufferKey,
+    pub vertices: usize,
+    pub indices: usize,
+    /// Size of the vertex and index buffers, in bytes.
+    pub buffer_bytes: usize,
+    /// Whether the buffers are waiting to be uploaded.
+    pub dirty: bool,
+    pub last_upload: Option<std::time::Instant>,
+    /// The number of times the buffers were uploaded.
+    pub uploads: u32,
+}
+
+/// Statistics of the most recently rendered frame.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RenderStats {
+    /// The number of subchunk buffers drawn.
+    pub drawn_buffers: usize,
+    /// The number of subchunk buffers skipped, e.g. because they contain no faces.
+    pub culled_buffers: usize,
+    /// GPU durations of the passes of a recent frame, if GPU timing is supported.
+    pub gpu_timings: Option<GpuTimings>,
 }
 
 impl Render {
-    pub async fn new(window: &Window) -> Self {
+    /// Create the renderer, texturing blocks with `block_texture`.
+    pub async fn new(
+        window: &Window,
+        graphics: GraphicsSettings,
+        block_texture: &RgbaImage,
+    ) -> Self {
         let inst = wgpu::Instance::new(Backends::all());
         let surface = unsafe { inst.create_surface(window) };
         let adapter = inst
@@ -70,7 +137,11 @@ impl Render {
                     },
                     features: Features::default()
                         .union(Features::TEXTURE_BINDING_ARRAY)
-                        .union(Features::PUSH_CONSTANTS),
+                        .union(Features::PUSH_CONSTANTS)
+                        .union(
+                            adapter.features()
+                                & (Features::POLYGON_MODE_LINE | Features::TIMESTAMP_QUERY),
+                        ),
                 },
                 None,
             )
@@ -78,26 +149,27 @@ impl Render {
             .expect("Failed to request device");
 
         let size = window.inner_size();
+        let present_modes = surface.get_supported_modes(&adapter);
         let config = SurfaceConfiguration {
             usage: TextureUsages::RENDER_ATTACHMENT,
             format: surface.get_supported_formats(&adapter)[0],
             width: size.width,
             height: size.height,
-            present_mode: PresentMode::Fifo,
+            present_mode: choose_present_mode(graphics.vsync, &present_modes),
         };
         surface.configure(&device, &config);
 
-        // Create depth buffer
+        // Create depth buffer and multisampled color target
         let (_depth_texture, depth_texture_view, _depth_texture_sampler) =
-            create_depth_texture(&device, &config);
+            create_depth_texture(&device, &config, graphics.msaa_samples);
+        let msaa_texture_view = create_msaa_texture(&device, &config, graphics.msaa_samples);
 
-        // Create shader and layouts
-        let shader = device.create_shader_module(include_wgsl!("./shader.wgsl"));
+        // Create layouts
         let uniform_data_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
             label: Some("Uniform Data Bind Group Layout"),
             entries: &[BindGroupLayoutEntry {
                 binding: 0,
-                visibility: ShaderStages::VERTEX,
+                visibility: ShaderStages::VERTEX_FRAGMENT,
                 ty: BindingType::Buffer {
                     ty: BufferBindingType::Uniform,
                     has_dynamic_offset: false,
@@ -106,15 +178,15 @@ impl Render {
                 count: None,
             }],
         });
-        let grass_bind_group_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
-            label: Some("Grass Texture Bind Group Layout"),
+        let block_texture_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("Block Texture Bind Group Layout"),
             entries: &[
                 BindGroupLayoutEntry {
                     binding: 0,
                     visibility: ShaderStages::FRAGMENT,
                     ty: BindingType::Texture {
                         sample_type: TextureSampleType::Float { filterable: true },
-                        view_dimension: TextureViewDimension::D2,
+                        view_dimension: TextureViewDimension::D2Array,
                         multisampled: false,
                     },
                     count: None,
@@ -128,65 +200,76 @@ impl Render {
             ],
         });
 
+        let ao_corners_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("AO Corners Bind Group Layout"),
+            entries: &[BindGroupLayoutEntry {
+                binding: 0,
+                visibility: ShaderStages::VERTEX,
+                ty: BindingType::Texture {
+                    sample_type: TextureSampleType::Uint,
+                    view_dimension: TextureViewDimension::D3,
+                    multisampled: false,
+                },
+                count: None,
+            }],
+        });
+
         let layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
             label: Some("PipelineLayout"),
-            bind_group_layouts: &[&uniform_data_layout, &grass_bind_group_layout],
+            bind_group_layouts: &[
+                &uniform_data_layout,
+                &block_texture_layout,
+                &ao_corners_layout,
+            ],
             push_constant_ranges: &[PushConstantRange {
                 range: 0..16,
                 stages: ShaderStages::VERTEX,
             }],
         });
 
-        let pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
-            label: Some("RenderPipeline"),
-            layout: Some(&layout),
-            vertex: VertexState {
-                module: &shader,
-                entry_point: "main_vs",
-                buffers: &[VertexBufferLayout {
-                    step_mode: wgpu::VertexStepMode::Vertex,
-                    attributes: &vertex_attr_array![0 => Float32x3, 1 => Float32x2, 2 => Float32],
-                    array_stride: size_of::<Vertex>() as BufferAddress,
-                }],
-            },
-            fragment: Some(FragmentState {
-                module: &shader,
-                entry_point: "main_fs",
-                targets: &[Some(ColorTargetState {
-                    format: config.format,
-                    blend: Some(BlendState::REPLACE),
-                    write_mask: ColorWrites::ALL,
-                })],
-            }),
-            primitive: PrimitiveState {
-                topology: PrimitiveTopology::TriangleList,
-                strip_index_format: None,
-                front_face: FrontFace::Ccw,
-                cull_mode: Some(Face::Back),
-                unclipped_depth: false,
-                polygon_mode: PolygonMode::Fill,
-                conservative: false,
-            },
-            depth_stencil: Some(DepthStencilState {
-                format: TextureFormat::Depth32Float,
-                depth_write_enabled: true,
-                depth_compare: CompareFunction::Less,
-                stencil: StencilState::default(),
-                bias: DepthBiasState::default(),
-            }),
-            multisample: MultisampleState {
-                count: 1,
-                mask: !0,
-                alpha_to_coverage_enabled: false,
-            },
-            multiview: None,
-        });
+        let shader = device.create_shader_module(include_wgsl!("./shader.wgsl"));
+        let pipeline = create_main_pipeline(
+            &device,
+            config.format,
+            &layout,
+            &shader,
+            graphics.msaa_samples,
+            PolygonMode::Fill,
+        );
+        let wireframe_pipeline = create_wireframe_pipeline(
+            &device,
+            config.format,
+            &layout,
+            &shader,
+            graphics.msaa_samples,
+        );
+        let sky_pipeline = create_sky_pipeline(
+            &device,
+            config.format,
+            &uniform_data_layout,
+            graphics.msaa_samples,
+        );
+        let overlay_pipeline = create_overlay_pipeline(&device, config.format);
+        let (ao_pipeline, ao_bake_layout) = create_ao_pipeline(&device);
+
+        let gpu_timer = GpuTimer::new(&device, &queue);
+        if gpu_timer.is_none() {
+            warn!("Timestamp queries are unsupported, GPU timings won't be available");
+        }
 
         // Create uniform buffer
         let view_matrix = Mat4::look_at_lh(Vec3::X, Vec3::ZERO, Vec3::Y);
+        let fov = std::f32::consts::FRAC_PI_4;
+        let sky = crate::sky::sky_colors(12.0);
         let uniforms = Uniforms::new(
             view_matrix,
-            Self::compute_proj_matrix(config.width as f32 / config.height as f32),
+            Self::compute_proj_matrix(
+                config.width as f32 / config.height as f32,
+                fov,
+                graphics.render_distance,
+            ),
+            &sky,
+            &graphics,
         );
         let uniform_buffer = device.create_buffer_init(&BufferInitDescriptor {
             label: Some("Uniform Buffer"),
@@ -202,102 +285,229 @@ impl Render {
             }],
         });
 
-        // Load texture
-        let grass_top_img = image::load_from_memory(assets::GRASSTOP)
-            .unwrap()
-            .to_rgba8();
-        let grass_top_size = Extent3d {
-            width: grass_top_img.width(),
-            height: grass_top_img.height(),
-            depth_or_array_layers: 1,
-        };
-        let grass_texture = device.create_texture(&TextureDescriptor {
-            label: Some("Grass Texture"),
-            size: grass_top_size,
-            mip_level_count: 1,
-            sample_count: 1,
-            dimension: TextureDimension::D2,
-            format: TextureFormat::Rgba8UnormSrgb,
-            usage: TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
-        });
-        queue.write_texture(
-            ImageCopyTexture {
-                texture: &grass_texture,
-                mip_level: 0,
-                origin: Origin3d::ZERO,
-                aspect: TextureAspect::All,
-            },
-            &grass_top_img,
-            ImageDataLayout {
-                offset: 0,
-                bytes_per_row: NonZeroU32::new(4 * grass_top_img.width()),
-                rows_per_image: NonZeroU32::new(grass_top_img.height()),
-            },
-            grass_top_size,
+        let block_texture_view = create_block_texture(&device, &queue, block_texture);
+        let block_texture_bind_group = create_block_texture_bind_group(
+            &device,
+            &block_texture_layout,
+            &block_texture_view,
+            &graphics,
         );
 
-        let grass_texture_view = grass_texture.create_view(&TextureViewDescriptor::default());
-        let grass_texture_sampler = device.create_sampler(&SamplerDescriptor {
-            label: Some("Grass Texture Sampler"),
-            address_mode_u: AddressMode::ClampToEdge,
-            address_mode_v: AddressMode::ClampToEdge,
-            address_mode_w: AddressMode::ClampToEdge,
-            mag_filter: FilterMode::Nearest,
-            min_filter: FilterMode::Linear,
-            mipmap_filter: FilterMode::Linear,
-            ..Default::default()
-        });
-        let grass_bind_group = device.create_bind_group(&BindGroupDescriptor {
-            label: Some("Grass Texture Bind Group"),
-            layout: &grass_bind_group_layout,
-            entries: &[
-                BindGroupEntry {
-                    binding: 0,
-                    resource: BindingResource::TextureView(&grass_texture_view),
-                },
-                BindGroupEntry {
-                    binding: 1,
-                    resource: BindingResource::Sampler(&grass_texture_sampler),
-                },
-            ],
-        });
-
         Self {
             surface,
             device,
             queue,
+            pipeline_layout: layout,
+            shader,
             pipeline,
+            wireframe_pipeline,
+            wireframe: false,
+            sky_pipeline,
+            overlay_pipeline,
+            ao_pipeline,
+            ao_bake_layout,
+            ao_corners_layout,
             size,
             config,
+            present_modes,
+            graphics,
 
             view_matrix,
+            fov,
+            sky,
 
             uniforms,
+            uniform_data_layout,
             uniform_buffer,
             uniform_bind_group,
 
-            grass_bind_group,
+            block_texture_layout,
+            block_texture_view,
+            block_texture_bind_group,
 
             depth_texture_view,
+            msaa_texture_view,
 
             last_update: Instant::now(),
 
             rendered: RenderedBufferCollection::new(),
+            overlay: OverlayBuffer::new(),
+
+            stats: RenderStats::default(),
+            gpu_timer,
+            staging_belt: StagingBelt::new(STAGING_CHUNK_SIZE),
         }
     }
 
+    pub fn stats(&self) -> RenderStats {
+        self.stats
+    }
+
+    /// Get statistics of the buffers of every subchunk.
+    pub fn subchunk_stats(&self) -> Vec<SubchunkStats> {
+        self.rendered
+            .buffers
+            .iter()
+            .map(|(&key, entry)| {
+                let vertices = entry.host_buffer.vertices.len();
+                let indices = entry.host_buffer.indices.len();
+                SubchunkStats {
+                    key,
+                    vertices,
+                    indices,
+                    buffer_bytes: vertices * size_of::<Vertex>() + indices * size_of::<u16>(),
+                    dirty: entry.dirty,
+                    last_upload: entry.last_upload,
+                    uploads: entry.uploads,
+                }
+            })
+            .collect()
+    }
+
+    /// Get the overlay buffer drawn on top of the world in the next frame.
+    pub fn overlay_mut(&mut self) -> &mut OverlayBuffer {
+        &mut self.overlay
+    }
+
     pub fn set_view_matrix(&mut self, mat: Mat4) {
         self.view_matrix = mat;
         self.update_uniforms();
     }
 
+    /// Get the view-projection matrix of the current frame.
+    pub fn view_proj(&self) -> Mat4 {
+        self.uniforms.trans
+    }
+
+    /// Set the vertical field of view, in radians.
+    pub fn set_fov(&mut self, fov: f32) {
+        self.fov = fov;
+        self.update_uniforms();
+    }
+
+    pub fn set_sky(&mut self, sky: SkyColors) {
+        self.sky = sky;
+        self.update_uniforms();
+    }
+
+    /// Toggle drawing blocks as wireframes, returning whether wireframes are now drawn. Does
+    /// nothing if the adapter doesn't support wireframes.
+    pub fn toggle_wireframe(&mut self) -> bool {
+        if self.wireframe_pipeline.is_some() {
+            self.wireframe = !self.wireframe;
+        } else {
+            warn!("Wireframes are not supported by the adapter");
+        }
+        self.wireframe
+    }
+
+    pub fn graphics(&self) -> GraphicsSettings {
+        self.graphics
+    }
+
+    /// The present mode in use, which may differ from the preferred one in the graphics settings
+    /// if the surface doesn't support it.
+    pub fn present_mode(&self) -> PresentMode {
+        self.config.present_mode
+    }
+
+    /// Apply new graphics settings, recreating whatever depends on them.
+    pub fn set_graphics(&mut self, graphics: GraphicsSettings) {
+        let present_mode = choose_present_mode(graphics.vsync, &self.present_modes);
+        if present_mode != self.config.present_mode {
+            self.config.present_mode = present_mode;
+            self.surface.configure(&self.device, &self.config);
+        }
+        if graphics.msaa_samples != self.graphics.msaa_samples {
+            let samples = graphics.msaa_samples;
+            self.pipeline = create_main_pipeline(
+                &self.device,
+                self.config.format,
+                &self.pipeline_layout,
+                &self.shader,
+                samples,
+                PolygonMode::Fill,
+            );
+            self.wireframe_pipeline = create_wireframe_pipeline(
+                &self.device,
+                self.config.format,
+                &self.pipeline_layout,
+                &self.shader,
+                samples,
+            );
+            self.sky_pipeline = create_sky_pipeline(
+                &self.device,
+                self.config.format,
+                &self.uniform_data_layout,
+                samples,
+            );
+            let (_depth_texture, depth_texture_view, _depth_texture_sampler) =
+                create_depth_texture(&self.device, &self.config, samples);
+            self.depth_texture_view = depth_texture_view;
+            self.msaa_texture_view = create_msaa_texture(&self.device, &self.config, samples);
+        }
+        self.block_texture_bind_group = create_block_texture_bind_group(
+            &self.device,
+            &self.block_texture_layout,
+            &self.block_texture_view,
+            &graphics,
+        );
+        self.graphics = graphics;
+        self.update_uniforms();
+    }
+
+    /// Replace the base texture of blocks, e.g. after its asset file changed.
+    pub fn set_block_texture(&mut self, block_texture: &RgbaImage) {
+        self.block_texture_view = create_block_texture(&self.device, &self.queue, block_texture);
+        self.block_texture_bind_group = create_block_texture_bind_group(
+            &self.device,
+            &self.block_texture_layout,
+            &self.block_texture_view,
+            &self.graphics,
+        );
+    }
+
+    /// Recreate the block pipelines from new WGSL source of `shader.wgsl`. If the shader fails to
+    /// compile or doesn't match the pipeline layout, the current pipelines are kept.
+    pub async fn reload_shader(&mut self, source: &str) -> Result<(), wgpu::Error> {
+        self.device.push_error_scope(ErrorFilter::Validation);
+        let shader = self.device.create_shader_module(ShaderModuleDescriptor {
+            label: Some("shader.wgsl"),
+            source: ShaderSource::Wgsl(source.into()),
+        });
+        let samples = self.graphics.msaa_samples;
+        let pipeline = create_main_pipeline(
+            &self.device,
+            self.config.format,
+            &self.pipeline_layout,
+            &shader,
+            samples,
+            PolygonMode::Fill,
+        );
+        let wireframe_pipeline = create_wireframe_pipeline(
+            &self.device,
+            self.config.format,
+            &self.pipeline_layout,
+            &shader,
+            samples,
+        );
+        if let Some(err) = self.device.pop_error_scope().await {
+            return Err(err);
+        }
+        self.shader = shader;
+        self.pipeline = pipeline;
+        self.wireframe_pipeline = wireframe_pipeline;
+        Ok(())
+    }
+
     fn update_uniforms(&mut self) {
-        let proj = Self::compute_proj_matrix(self.config.width as f32 / self.config.height as f32);
-        self.uniforms = Uniforms::new(self.view_matrix, proj);
+        let aspect = self.config.width as f32 / self.config.height as f32;
+        let proj = Self::compute_proj_matrix(aspect, self.fov, self.graphics.render_distance);
+        self.uniforms = Uniforms::new(self.view_matrix, proj, &self.sky, &self.graphics);
     }
 
-    fn compute_proj_matrix(aspect: f32) -> Mat4 {
-        Mat4::perspective_rh(std::f32::consts::FRAC_PI_4, aspect, 0.1, 100.0)
+    fn compute_proj_matrix(aspect: f32, fov: f32, render_distance: u32) -> Mat4 {
+        Mat4::perspective_rh(fov, aspect, NEAR_PLANE, far_plane(render_distance))
     }
 
     pub fn size(&self) -> PhysicalSize<u32> {
@@ -313,9 +523,11 @@ impl Render {
         self.config.height = size.height;
 
         self.surface.configure(&self.device, &self.config);
+        let samples = self.graphics.msaa_samples;
         let (_depth_texture, depth_texture_view, _depth_texture_sampler) =
-            create_depth_texture(&self.device, &self.config);
+            create_depth_texture(&self.device, &self.config, samples);
         self.depth_texture_view = depth_texture_view;
+        self.msaa_texture_view = create_msaa_texture(&self.device, &self.config, samples);
 
         self.update_uniforms();
     }
@@ -327,9 +539,6 @@ impl Render {
     }
 
     pub async fn render(&mut self) -> Result<(), SurfaceError> {
-        self.queue
-            .write_buffer(&self.uniform_buffer, 0, self.uniforms.as_u8_slice());
-
         self.device.push_error_scope(ErrorFilter::Validation);
 
         let output = self.surface.get_current_texture()?;
@@ -342,16 +551,74 @@ impl Render {
                 label: Some("Render Command Encoder"),
             });
 
+        if let Some(gpu_timer) = &mut self.gpu_timer {
+            gpu_timer.begin_frame(&self.device);
+            gpu_timer.write(&mut encoder, Timestamp::FrameStart);
+        }
+
+        // Upload the uniforms and the meshes changed since the last frame
+        let uniforms = self.uniforms.as_u8_slice();
+        self.staging_belt
+            .write_buffer(
+                &mut encoder,
+                &self.uniform_buffer,
+                0,
+                nonzero_size(uniforms),
+                &self.device,
+            )
+            .copy_from_slice(uniforms);
+        for entry in self.rendered.buffers.values_mut() {
+            if !entry.dirty {
+                continue;
+            }
+            entry.dirty = false;
+            // Empty meshes aren't drawn, so there's nothing to upload
+            if entry.host_buffer.indices.is_empty() {
+                continue;
+            }
+            let vertices = entry.host_buffer.vertices.as_u8_slice();
+            let indices = entry.host_buffer.indices.as_u8_slice();
+            for (buffer, data) in [
+                (&entry.vertex_buffer, vertices),
+                (&entry.index_buffer, indices),
+            ] {
+                self.staging_belt
+                    .write_buffer(&mut encoder, buffer, 0, nonzero_size(data), &self.device)
+                    .copy_from_slice(data);
+            }
+            entry.last_upload = Some(std::time::Instant::now());
+            entry.uploads += 1;
+        }
+        self.staging_belt.finish();
+
+        // Bake ambient occlusion of newly inserted subchunks
+        let mut ao_pass = encoder.begin_compute_pass(&ComputePassDescriptor {
+            label: Some("AO Bake Pass"),
+        });
+        ao_pass.set_pipeline(&self.ao_pipeline);
+        for entry in self.rendered.buffers.values_mut() {
+            if entry.needs_ao_bake {
+                let workgroups = (AO_CORNERS as u32 + 3) / 4;
+                ao_pass.set_bind_group(0, &entry.ao_bake_bind_group, &[]);
+                ao_pass.dispatch_workgroups(workgroups, workgroups, workgroups);
+                entry.needs_ao_bake = false;
+            }
+        }
+        drop(ao_pass);
+        if let Some(gpu_timer) = &self.gpu_timer {
+            gpu_timer.write(&mut encoder, Timestamp::AoBakeEnd);
+        }
+
         let mut render_pass = encoder.begin_render_pass(&RenderPassDescriptor {
             label: Some("Render Pass"),
             color_attachments: &[Some(RenderPassColorAttachment {
-                view: &view,
-                resolve_target: None,
+                view: self.msaa_texture_view.as_ref().unwrap_or(&view),
+                resolve_target: self.msaa_texture_view.as_ref().map(|_| &view),
                 ops: Operations {
                     load: LoadOp::Clear(Color {
-                        r: 0.1,
-                        g: 0.2,
-                        b: 0.3,
+                        r: self.sky.horizon.x as f64,
+                        g: self.sky.horizon.y as f64,
+                        b: self.sky.horizon.z as f64,
                         a: 1.0,
                     }),
                     store: true,
@@ -366,33 +633,51 @@ impl Render {
                 stencil_ops: None,
             }),
         });
-        for (&(cx, cy, cz), buffer) in self.rendered.buffers.iter_mut() {
+        render_pass.set_pipeline(&self.sky_pipeline);
+        render_pass.set_bind_group(0, &self.uniform_bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+
+        // State shared by all subchunks is set once, leaving only their buffers, AO and
+        // position to be set per draw
+        match (&self.wireframe_pipeline, self.wireframe) {
+            (Some(wireframe_pipeline), true) => render_pass.set_pipeline(wireframe_pipeline),
+            _ => render_pass.set_pipeline(&self.pipeline),
+        }
+        render_pass.set_bind_group(0, &self.uniform_bind_group, &[]);
+        render_pass.set_bind_group(1, &self.block_texture_bind_group, &[]);
+
+        // Draw front to back, so that hidden fragments fail the depth test early
+        let eye = self.view_matrix.inverse().w_axis.truncate();
+        let mut buffers = self.rendered.buffers.iter().collect::<Vec<_>>();
+        buffers.sort_by(|(a, _), (b, _)| {
+            let distance = |&(cx, cy, cz): &RenderedBufferKey| {
+                let center = Vec3::new(cx as f32, cy as f32, cz as f32) * 16.0 + 8.0;
+                center.distance_squared(eye)
+            };
+            distance(a).total_cmp(&distance(b))
+        });
+
+        let mut stats = RenderStats::default();
+        for (&(cx, cy, cz), buffer) in buffers {
             let RenderedBufferEntry {
                 host_buffer,
-                dirty,
                 vertex_buffer,
                 index_buffer,
+                ao_corners_bind_group,
+                ..
             } = buffer;
 
             if host_buffer.indices.is_empty() {
+                stats.culled_buffers += 1;
                 continue;
             }
-
-            if *dirty {
-                self.queue
-                    .write_buffer(vertex_buffer, 0, host_buffer.vertices.as_u8_slice());
-                self.queue
-                    .write_buffer(index_buffer, 0, host_buffer.indices.as_u8_slice());
-                *dirty = false;
-            }
+            stats.drawn_buffers += 1;
 
             let push_constants = PushConstants::new((cx, cy, cz));
 
-            render_pass.set_pipeline(&self.pipeline);
             render_pass.set_vertex_buffer(0, vertex_buffer.slice(..));
             render_pass.set_index_buffer(index_buffer.slice(..), IndexFormat::Uint16);
-            render_pass.set_bind_group(0, &self.uniform_bind_group, &[]);
-            render_pass.set_bind_group(1, &self.grass_bind_group, &[]);
+            render_pass.set_bind_group(2, ao_corners_bind_group, &[]);
             render_pass.set_push_constants(ShaderStages::VERTEX, 0, push_constants.as_u8_slice());
 
             let num_indices = host_buffer.indices.len() as u32;
@@ -400,7 +685,56 @@ impl Render {
         }
 
         drop(render_pass);
+        if let Some(gpu_timer) = &self.gpu_timer {
+            gpu_timer.write(&mut encoder, Timestamp::TerrainEnd);
+            stats.gpu_timings = gpu_timer.latest();
+        }
+        self.stats = stats;
+
+        if !self.overlay.is_empty() {
+            let vertex_buffer = self.device.create_buffer_init(&BufferInitDescriptor {
+                label: Some("Overlay Vertex Buffer"),
+                contents: self.overlay.vertices().as_u8_slice(),
+                usage: BufferUsages::VERTEX,
+            });
+            let index_buffer = self.device.create_buffer_init(&BufferInitDescriptor {
+                label: Some("Overlay Index Buffer"),
+                contents: self.overlay.indices().as_u8_slice(),
+                usage: BufferUsages::INDEX,
+            });
+            let push_constants = OverlayPushConstants {
+                screen_size: vec2(self.config.width as f32, self.config.height as f32),
+            };
+
+            let mut overlay_pass = encoder.begin_render_pass(&RenderPassDescriptor {
+                label: Some("Overlay Render Pass"),
+                color_attachments: &[Some(RenderPassColorAttachment {
+                    view: &view,
+                    resolve_target: None,
+                    ops: Operations {
+                        load: LoadOp::Load,
+                        store: true,
+                    },
+                })],
+                depth_stencil_attachment: None,
+            });
+            overlay_pass.set_pipeline(&self.overlay_pipeline);
+            overlay_pass.set_vertex_buffer(0, vertex_buffer.slice(..));
+            overlay_pass.set_index_buffer(index_buffer.slice(..), IndexFormat::Uint32);
+            overlay_pass.set_push_constants(ShaderStages::VERTEX, 0, push_constants.as_u8_slice());
+            overlay_pass.draw_indexed(0..self.overlay.indices().len() as u32, 0, 0..1);
+        }
+
+        if let Some(gpu_timer) = &self.gpu_timer {
+            gpu_timer.write(&mut encoder, Timestamp::OverlayEnd);
+            gpu_timer.resolve(&mut encoder);
+        }
+
         self.queue.submit([encoder.finish()]);
+        self.staging_belt.recall();
+        if let Some(gpu_timer) = &mut self.gpu_timer {
+            gpu_timer.end_frame();
+        }
 
         // report on error
         let err_scope = self.device.pop_error_scope();
@@ -416,7 +750,17 @@ impl Render {
         Ok(())
     }
 
-    pub fn insert_rendered(&mut self, key: RenderedBufferKey, host_buffer: RenderedBuffer) {
+    /// Remove the buffers of a subchunk, e.g. after its chunk was unloaded.
+    pub fn remove_rendered(&mut self, key: RenderedBufferKey) {
+        self.rendered.buffers.remove(&key);
+    }
+
+    pub fn insert_rendered(
+        &mut self,
+        key: RenderedBufferKey,
+        host_buffer: RenderedBuffer,
+        opacity: &OpacityVolume,
+    ) {
         let vertex_data: &[u8] = bytemuck::cast_slice(&host_buffer.vertices);
         let index_data: &[u8] = bytemuck::cast_slice(&host_buffer.indices);
 
@@ -433,6 +777,80 @@ impl Render {
             mapped_at_creation: false,
         });
 
+        let opacity_size = Extent3d {
+            width: AO_HALO_SIZE as u32,
+            height: AO_HALO_SIZE as u32,
+            depth_or_array_layers: AO_HALO_SIZE as u32,
+        };
+        let opacity_texture = self.device.create_texture(&TextureDescriptor {
+            label: Some("Opacity Texture"),
+            size: opacity_size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: TextureDimension::D3,
+            format: TextureFormat::R8Uint,
+            usage: TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
+        });
+        self.queue.write_texture(
+            ImageCopyTexture {
+                texture: &opacity_texture,
+                mip_level: 0,
+                origin: Origin3d::ZERO,
+                aspect: TextureAspect::All,
+            },
+            &opacity.0,
+            ImageDataLayout {
+                offset: 0,
+                bytes_per_row: NonZeroU32::new(AO_HALO_SIZE as u32),
+                rows_per_image: NonZeroU32::new(AO_HALO_SIZE as u32),
+            },
+            opacity_size,
+        );
+        let ao_corners_texture = self.device.create_texture(&TextureDescriptor {
+            label: Some("AO Corners Texture"),
+            size: Extent3d {
+                width: AO_CORNERS as u32,
+                height: AO_CORNERS as u32,
+                depth_or_array_layers: AO_CORNERS as u32,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: TextureDimension::D3,
+            format: TextureFormat::R32Uint,
+            usage: TextureUsages::STORAGE_BINDING | TextureUsages::TEXTURE_BINDING,
+        });
+
+        let opacity_view = opacity_texture.create_view(&TextureViewDescriptor::default());
+        let ao_corners_view = ao_corners_texture.create_view(&TextureViewDescriptor::default());
+        let ao_bake_bind_group = self.device.create_bind_group(&BindGroupDescriptor {
+            label: Some("AO Bake Bind Group"),
+            layout: &self.ao_bake_layout,
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: BindingResource::TextureView(&opacity_view),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: BindingResource::TextureView(&ao_corners_view),
+                },
+            ],
+        });
+        let ao_corners_bind_group = self.device.create_bind_group(&BindGroupDescriptor {
+            label: Some("AO Corners Bind Group"),
+            layout: &self.ao_corners_layout,
+            entries: &[BindGroupEntry {
+                binding: 0,
+                resource: BindingResource::TextureView(&ao_corners_view),
+            }],
+        });
+
+        // Keep counting uploads across re-meshes, to spot subchunks that are re-meshed too often
+        let uploads = self
+            .rendered
+            .buffers
+            .get(&key)
+            .map_or(0, |entry| entry.uploads);
         self.rendered.buffers.insert(
             key,
             RenderedBufferEntry {
@@ -440,6 +858,11 @@ impl Render {
                 vertex_buffer,
                 index_buffer,
                 dirty: true,
+                last_upload: None,
+                uploads,
+                ao_bake_bind_group,
+                ao_corners_bind_group,
+                needs_ao_bake: true,
             },
         );
     }
@@ -448,6 +871,7 @@ impl Render {
 fn create_depth_texture(
     device: &Device,
     config: &SurfaceConfiguration,
+    sample_count: u32,
 ) -> (Texture, TextureView, Sampler) {
     const DEPTH_FORMAT: TextureFormat = TextureFormat::Depth32Float;
 
@@ -460,7 +884,7 @@ fn create_depth_texture(
         label: Some("Depth Texture"),
         size,
         mip_level_count: 1,
-        sample_count: 1,
+        sample_count,
         dimension: TextureDimension::D2,
         format: DEPTH_FORMAT,
         usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING,
@@ -485,15 +909,430 @@ fn create_depth_texture(
     (texture, view, sampler)
 }
 
+/// Get the supported present mode closest to the preferred one. Uncapped modes fall back to each
+/// other before falling back to [`PresentMode::Fifo`], which every surface supports.
+fn choose_present_mode(vsync: VsyncMode, supported: &[PresentMode]) -> PresentMode {
+    let candidates: &[PresentMode] = match vsync {
+        VsyncMode::Fifo => &[],
+        VsyncMode::Mailbox => &[PresentMode::Mailbox, PresentMode::Immediate],
+        VsyncMode::Immediate => &[PresentMode::Immediate, PresentMode::Mailbox],
+    };
+    candidates
+        .iter()
+        .copied()
+        .find(|mode| supported.contains(mode))
+        .unwrap_or(PresentMode::Fifo)
+}
+
+/// Create the multisampled color target, or `None` if MSAA is disabled.
+fn create_msaa_texture(
+    device: &Device,
+    config: &SurfaceConfiguration,
+    sample_count: u32,
+) -> Option<TextureView> {
+    if sample_count == 1 {
+        return None;
+    }
+    let texture = device.create_texture(&TextureDescriptor {
+        label: Some("MSAA Color Texture"),
+        size: Extent3d {
+            width: config.width,
+            height: config.height,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count,
+        dimension: TextureDimension::D2,
+        format: config.format,
+        usage: TextureUsages::RENDER_ATTACHMENT,
+    });
+    Some(texture.create_view(&TextureViewDescriptor::default()))
+}
+
+/// Create a 2D array texture with one layer per image, which must all have the same size. Each
+/// layer gets a full chain of mipmaps downscaled on the CPU.
+fn create_mipmapped_texture(
+    device: &Device,
+    queue: &Queue,
+    label: &str,
+    layers: &[RgbaImage],
+) -> TextureView {
+    let (width, height) = layers[0].dimensions();
+    let mip_level_count = 32 - width.max(height).leading_zeros();
+    let texture = device.create_texture(&TextureDescriptor {
+        label: Some(label),
+        size: Extent3d {
+            width,
+            height,
+            depth_or_array_layers: layers.len() as u32,
+        },
+        mip_level_count,
+        sample_count: 1,
+        dimension: TextureDimension::D2,
+        format: TextureFormat::Rgba8UnormSrgb,
+        usage: TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
+    });
+
+    for (layer, img) in layers.iter().enumerate() {
+        let mut level_img = img.clone();
+        for mip_level in 0..mip_level_count {
+            if mip_level > 0 {
+                let (width, height) = (
+                    (level_img.width() / 2).max(1),
+                    (level_img.height() / 2).max(1),
+                );
+                level_img =
+                    imageops::resize(&level_img, width, height, imageops::FilterType::Triangle);
+            }
+            queue.write_texture(
+                ImageCopyTexture {
+                    texture: &texture,
+                    mip_level,
+                    origin: Origin3d {
+                        x: 0,
+                        y: 0,
+                        z: layer as u32,
+                    },
+                    aspect: TextureAspect::All,
+                },
+                &level_img,
+                ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: NonZeroU32::new(4 * level_img.width()),
+                    rows_per_image: NonZeroU32::new(level_img.height()),
+                },
+                Extent3d {
+                    width: level_img.width(),
+                    height: level_img.height(),
+                    depth_or_array_layers: 1,
+                },
+            );
+        }
+    }
+
+    texture.create_view(&TextureViewDescriptor {
+        dimension: Some(TextureViewDimension::D2Array),
+        ..Default::default()
+    })
+}
+
+/// Create the block texture array, with a layer of `base` tinted for each textured block.
+fn create_block_texture(device: &Device, queue: &Queue, base: &RgbaImage) -> TextureView {
+    let layers = TEXTURED_BLOCKS.map(|block| tinted(base, block_tint(block)));
+    create_mipmapped_texture(device, queue, "Block Textures", &layers)
+}
+
+/// Create the bind group of the block textures, with a sampler configured by `graphics`.
+fn create_block_texture_bind_group(
+    device: &Device,
+    layout: &BindGroupLayout,
+    texture_view: &TextureView,
+    graphics: &GraphicsSettings,
+) -> BindGroup {
+    // Without mipmaps, sample the nearest texel of the full-size texture
+    let (min_filter, lod_max_clamp) = match graphics.mipmaps {
+        true => (FilterMode::Linear, f32::MAX),
+        false => (FilterMode::Nearest, 0.0),
+    };
+    let sampler = device.create_sampler(&SamplerDescriptor {
+        label: Some("Block Texture Sampler"),
+        address_mode_u: AddressMode::ClampToEdge,
+        address_mode_v: AddressMode::ClampToEdge,
+        address_mode_w: AddressMode::ClampToEdge,
+        mag_filter: FilterMode::Nearest,
+        min_filter,
+        mipmap_filter: FilterMode::Linear,
+        lod_max_clamp,
+        anisotropy_clamp: NonZeroU8::new(graphics.anisotropy).filter(|a| a.get() > 1),
+        ..Default::default()
+    });
+    device.create_bind_group(&BindGroupDescriptor {
+        label: Some("Block Texture Bind Group"),
+        layout,
+        entries: &[
+            BindGroupEntry {
+                binding: 0,
+                resource: BindingResource::TextureView(texture_view),
+            },
+            BindGroupEntry {
+                binding: 1,
+                resource: BindingResource::Sampler(&sampler),
+            },
+        ],
+    })
+}
+
+/// Create the block pipeline drawing edges only, or `None` if the device doesn't support it.
+fn create_wireframe_pipeline(
+    device: &Device,
+    format: TextureFormat,
+    layout: &PipelineLayout,
+    shader: &ShaderModule,
+    sample_count: u32,
+) -> Option<RenderPipeline> {
+    if !device.features().contains(Features::POLYGON_MODE_LINE) {
+        return None;
+    }
+    Some(create_main_pipeline(
+        device,
+        format,
+        layout,
+        shader,
+        sample_count,
+        PolygonMode::Line,
+    ))
+}
+
+fn create_main_pipeline(
+    device: &Device,
+    format: TextureFormat,
+    layout: &PipelineLayout,
+    shader: &ShaderModule,
+    sample_count: u32,
+    polygon_mode: PolygonMode,
+) -> RenderPipeline {
+    device.create_render_pipeline(&RenderPipelineDescriptor {
+        label: Some("RenderPipeline"),
+        layout: Some(layout),
+        vertex: VertexState {
+            module: shader,
+            entry_point: "main_vs",
+            buffers: &[VertexBufferLayout {
+                step_mode: wgpu::VertexStepMode::Vertex,
+                attributes: &vertex_attr_array![0 => Float32x3, 1 => Float32x2, 2 => Uint32],
+                array_stride: size_of::<Vertex>() as BufferAddress,
+            }],
+        },
+        fragment: Some(FragmentState {
+            module: shader,
+            entry_point: "main_fs",
+            targets: &[Some(ColorTargetState {
+                format,
+                blend: Some(BlendState::REPLACE),
+                write_mask: ColorWrites::ALL,
+            })],
+        }),
+        primitive: PrimitiveState {
+            topology: PrimitiveTopology::TriangleList,
+            strip_index_format: None,
+            front_face: FrontFace::Ccw,
+            cull_mode: Some(Face::Back),
+            unclipped_depth: false,
+            polygon_mode,
+            conservative: false,
+        },
+        depth_stencil: Some(DepthStencilState {
+            format: TextureFormat::Depth32Float,
+            depth_write_enabled: true,
+            depth_compare: CompareFunction::Less,
+            stencil: StencilState::default(),
+            bias: DepthBiasState::default(),
+        }),
+        multisample: MultisampleState {
+            count: sample_count,
+            mask: !0,
+            alpha_to_coverage_enabled: false,
+        },
+        multiview: None,
+    })
+}
+
+/// Create the compute pipeline baking per-corner AO, and the layout of its bind group.
+fn create_ao_pipeline(device: &Device) -> (ComputePipeline, BindGroupLayout) {
+    let shader = device.create_shader_module(include_wgsl!("./ao.wgsl"));
+    let bind_group_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+        label: Some("AO Bake Bind Group Layout"),
+        entries: &[
+            BindGroupLayoutEntry {
+                binding: 0,
+                visibility: ShaderStages::COMPUTE,
+                ty: BindingType::Texture {
+                    sample_type: TextureSampleType::Uint,
+                    view_dimension: TextureViewDimension::D3,
+                    multisampled: false,
+                },
+                count: None,
+            },
+            BindGroupLayoutEntry {
+                binding: 1,
+                visibility: ShaderStages::COMPUTE,
+                ty: BindingType::StorageTexture {
+                    access: StorageTextureAccess::WriteOnly,
+                    format: TextureFormat::R32Uint,
+                    view_dimension: TextureViewDimension::D3,
+                },
+                count: None,
+            },
+        ],
+    });
+    let layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+        label: Some("AO Bake Pipeline Layout"),
+        bind_group_layouts: &[&bind_group_layout],
+        push_constant_ranges: &[],
+    });
+    let pipeline = device.create_compute_pipeline(&ComputePipelineDescriptor {
+        label: Some("AO Bake Pipeline"),
+        layout: Some(&layout),
+        module: &shader,
+        entry_point: "main_cs",
+    });
+
+    (pipeline, bind_group_layout)
+}
+
+fn create_sky_pipeline(
+    device: &Device,
+    format: TextureFormat,
+    uniform_data_layout: &BindGroupLayout,
+    sample_count: u32,
+) -> RenderPipeline {
+    let shader = device.create_shader_module(include_wgsl!("./sky.wgsl"));
+    let layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+        label: Some("Sky Pipeline Layout"),
+        bind_group_layouts: &[uniform_data_layout],
+        push_constant_ranges: &[],
+    });
+
+    device.create_render_pipeline(&RenderPipelineDescriptor {
+        label: Some("Sky Render Pipeline"),
+        layout: Some(&layout),
+        vertex: VertexState {
+            module: &shader,
+            entry_point: "main_vs",
+            buffers: &[],
+        },
+        fragment: Some(FragmentState {
+            module: &shader,
+            entry_point: "main_fs",
+            targets: &[Some(ColorTargetState {
+                format,
+                blend: Some(BlendState::REPLACE),
+                write_mask: ColorWrites::ALL,
+            })],
+        }),
+        primitive: PrimitiveState {
+            topology: PrimitiveTopology::TriangleList,
+            cull_mode: None,
+            ..Default::default()
+        },
+        // Drawn first without touching the depth buffer, so that everything covers it
+        depth_stencil: Some(DepthStencilState {
+            format: TextureFormat::Depth32Float,
+            depth_write_enabled: false,
+            depth_compare: CompareFunction::Always,
+            stencil: StencilState::default(),
+            bias: DepthBiasState::default(),
+        }),
+        multisample: MultisampleState {
+            count: sample_count,
+            ..Default::default()
+        },
+        multiview: None,
+    })
+}
+
+fn create_overlay_pipeline(device: &Device, format: TextureFormat) -> RenderPipeline {
+    let shader = device.create_shader_module(include_wgsl!("./overlay.wgsl"));
+    let layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+        label: Some("Overlay Pipeline Layout"),
+        bind_group_layouts: &[],
+        push_constant_ranges: &[PushConstantRange {
+            range: 0..size_of::<OverlayPushConstants>() as u32,
+            stages: ShaderStages::VERTEX,
+        }],
+    });
+
+    device.create_render_pipeline(&RenderPipelineDescriptor {
+        label: Some("Overlay Render Pipeline"),
+        layout: Some(&layout),
+        vertex: VertexState {
+            module: &shader,
+            entry_point: "main_vs",
+            buffers: &[VertexBufferLayout {
+                step_mode: wgpu::VertexStepMode::Vertex,
+                attributes: &vertex_attr_array![0 => Float32x2, 1 => Float32x4],
+                array_stride: size_of::<OverlayVertex>() as BufferAddress,
+            }],
+        },
+        fragment: Some(FragmentState {
+            module: &shader,
+            entry_point: "main_fs",
+            targets: &[Some(ColorTargetState {
+                format,
+                blend: Some(BlendState::ALPHA_BLENDING),
+                write_mask: ColorWrites::ALL,
+            })],
+        }),
+        primitive: PrimitiveState {
+            topology: PrimitiveTopology::TriangleList,
+            cull_mode: None,
+            ..Default::default()
+        },
+        depth_stencil: None,
+        multisample: MultisampleState::default(),
+        multiview: None,
+    })
+}
+
 #[repr(C)]
 #[derive(Clone, Copy, Pod, Zeroable)]
 struct Uniforms {
     trans: Mat4,
+    inv_trans: Mat4,
+    view: Mat4,
+    sky_zenith: Vec4,
+    sky_horizon: Vec4,
+    /// Fog color in `xyz`, and fog density in `w`.
+    fog: Vec4,
+    /// AO strength in `x`, and the distances terrain starts and finishes fading into the fog
+    /// over in `y` and `z`.
+    params: Vec4,
+}
+
+/// The size of the buffers allocated by the staging belt. Larger uploads get buffers of their
+/// own size.
+const STAGING_CHUNK_SIZE: BufferAddress = 1 << 20;
+
+/// Get the size of `data` to be uploaded through a [`StagingBelt`], which doesn't take empty
+/// uploads.
+fn nonzero_size(data: &[u8]) -> BufferSize {
+    BufferSize::new(data.len() as BufferAddress).expect("Empty upload")
+}
+
+/// The distance of the near clipping plane, in blocks.
+const NEAR_PLANE: f32 = 0.1;
+
+/// Get the distance of the far clipping plane, in blocks. Terrain is fully faded into the fog
+/// before it, so that the far plane never visibly cuts off loaded chunks.
+fn far_plane(render_distance: u32) -> f32 {
+    fog_range(render_distance).1 + 16.0
+}
+
+/// The fraction of the fog end distance at which terrain starts fading into the fog.
+const FOG_START: f32 = 0.6;
+
+/// Get the distances, in blocks, over which terrain fades into the fog. The fade ends at the
+/// edge of the loaded area, so that chunks popping in and out there can't be seen.
+fn fog_range(render_distance: u32) -> (f32, f32) {
+    let end = render_distance.max(1) as f32 * 16.0;
+    (end * FOG_START, end)
 }
 
 impl Uniforms {
-    fn new(view: Mat4, proj: Mat4) -> Self {
-        Self { trans: proj * view }
+    fn new(view: Mat4, proj: Mat4, sky: &SkyColors, graphics: &GraphicsSettings) -> Self {
+        let trans = proj * view;
+        Self {
+            trans,
+            inv_trans: trans.inverse(),
+            view,
+            sky_zenith: sky.zenith.extend(1.0),
+            sky_horizon: sky.horizon.extend(1.0),
+            fog: sky.fog.extend(sky.fog_density),
+            params: {
+                let (fog_start, fog_end) = fog_range(graphics.render_distance);
+                vec4(graphics.ao_strength, fog_start, fog_end, 0.0)
+            },
+        }
     }
 }
 
@@ -511,6 +1350,12 @@ impl PushConstants {
     }
 }
 
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct OverlayPushConstants {
+    screen_size: Vec2,
+}
+
 /// A host-side rendered buffer containing vertices and indices.
 #[derive(Clone)]
 pub struct RenderedBuffer {
@@ -519,6 +1364,12 @@ pub struct RenderedBuffer {
     max_index: Option<u16>,
 }
 
+impl Default for RenderedBuffer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl RenderedBuffer {
     pub fn new() -> Self {
         Self {
@@ -528,20 +1379,29 @@ impl RenderedBuffer {
         }
     }
 
-    pub fn _push_face(
+    pub fn vertices(&self) -> &[Vertex] {
+        &self.vertices
+    }
+
+    pub fn _push_face(&mut self, base_face: [Vertex; 4], pos: (i64, i64, i64), layer: u32) {
+        self.push_scaled_face(base_face, pos, 1, layer);
+    }
+
+    /// Push a face of the cube of `scale` blocks along each axis whose lowest corner is at
+    /// subchunk-local `(sx, sy, sz)`, as meshed at a lower level of detail.
+    pub fn push_scaled_face(
         &mut self,
         base_face: [Vertex; 4],
-        // Every corner can have 0..=8 opaque blocks
-        opaque_counts: [u8; 4],
         (sx, sy, sz): (i64, i64, i64),
+        scale: i64,
+        layer: u32,
     ) {
-        let mut vertices = shift_face(base_face, (sx as f32, sy as f32, sz as f32));
-
-        // subtract 4 so that flat surfaces are bright
-        let sub_opaque_counts = opaque_counts.map(|c| c.saturating_sub(4));
-        for i in 0..4 {
-            vertices[i].brightness = (4.0 - (sub_opaque_counts[i] as f32)) / 4.0;
-        }
+        let scaled_face = base_face.map(|vertex| Vertex {
+            pos: vertex.pos.map(|p| p * scale as f32),
+            layer,
+            ..vertex
+        });
+        let vertices = shift_face(scaled_face, (sx as f32, sy as f32, sz as f32));
         self.vertices.extend_from_slice(&vertices);
 
         let index_start = self.max_index.map(|i| i + 1).unwrap_or(0);
@@ -552,6 +1412,35 @@ impl RenderedBuffer {
     }
 }
 
+/// The side length of an [`OpacityVolume`], i.e. a subchunk plus a 1-block halo on each side.
+pub const AO_HALO_SIZE: usize = 18;
+
+/// The number of block corners along each axis of a subchunk.
+const AO_CORNERS: usize = 17;
+
+/// Opacity of the blocks of a subchunk and its 1-block halo, from which AO is baked on the GPU.
+///
+/// Halo coordinate `(0, 0, 0)` is the block at subchunk-local `(-1, -1, -1)`.
+#[derive(Clone)]
+pub struct OpacityVolume(Vec<u8>);
+
+impl Default for OpacityVolume {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl OpacityVolume {
+    pub fn new() -> Self {
+        Self(vec![0; AO_HALO_SIZE * AO_HALO_SIZE * AO_HALO_SIZE])
+    }
+
+    pub fn set(&mut self, (hx, hy, hz): (usize, usize, usize), is_opaque: bool) {
+        // Laid out as rows along x, images along z, to match the texture layout
+        self.0[hz * AO_HALO_SIZE * AO_HALO_SIZE + hy * AO_HALO_SIZE + hx] = is_opaque as u8;
+    }
+}
+
 pub struct RenderedBufferCollection {
     buffers: HashMap<RenderedBufferKey, RenderedBufferEntry>,
 }
@@ -561,6 +1450,11 @@ struct RenderedBufferEntry {
     vertex_buffer: Buffer,
     index_buffer: Buffer,
     dirty: bool,
+    last_upload: Option<std::time::Instant>,
+    uploads: u32,
+    ao_bake_bind_group: BindGroup,
+    ao_corners_bind_group: BindGroup,
+    needs_ao_bake: bool,
 }
 
 pub type RenderedBufferKey = (i64, i64, i64);
@@ -578,42 +1472,30 @@ impl RenderedBufferCollection {
 pub struct Vertex {
     pub pos: [f32; 3],
     pub texcoord: [f32; 2],
-    pub brightness: f32,
-}
-
-impl Vertex {
-    const ZERO: Self = Self {
-        pos: [0.0; 3],
-        texcoord: [0.0; 2],
-        brightness: 0.0,
-    };
-
-    pub fn pos_i64(self) -> (i64, i64, i64) {
-        let [x, y, z] = self.pos;
-        (x as i64, y as i64, z as i64)
-    }
+    /// The layer of the block texture array, see [`texture_layer`].
+    pub layer: u32,
 }
 
 pub const TOP_FACE: [Vertex; 4] = [
     Vertex {
         pos: [0., 1., 0.],
         texcoord: [0., 0.],
-        ..Vertex::ZERO
+        layer: 0,
     },
     Vertex {
         pos: [0., 1., 1.],
         texcoord: [0., 1.],
-        ..Vertex::ZERO
+        layer: 0,
     },
     Vertex {
         pos: [1., 1., 1.],
         texcoord: [1., 1.],
-        ..Vertex::ZERO
+        layer: 0,
     },
     Vertex {
         pos: [1., 1., 0.],
         texcoord: [1., 0.],
-        ..Vertex::ZERO
+        layer: 0,
     },
 ];
 
@@ -621,22 +1503,22 @@ pub const BOTTOM_FACE: [Vertex; 4] = [
     Vertex {
         pos: [0., 0., 1.],
         texcoord: [0., 0.],
-        ..Vertex::ZERO
+        layer: 0,
     },
     Vertex {
         pos: [0., 0., 0.],
         texcoord: [0., 1.],
-        ..Vertex::ZERO
+        layer: 0,
     },
     Vertex {
         pos: [1., 0., 0.],
         texcoord: [1., 1.],
-        ..Vertex::ZERO
+        layer: 0,
     },
     Vertex {
         pos: [1., 0., 1.],
         texcoord: [1., 0.],
-        ..Vertex::ZERO
+        layer: 0,
     },
 ];
 
@@ -644,22 +1526,22 @@ pub const RIGHT_FACE: [Vertex; 4] = [
     Vertex {
         pos: [1., 1., 1.],
         texcoord: [0., 0.],
-        ..Vertex::ZERO
+        layer: 0,
     },
     Vertex {
         pos: [1., 0., 1.],
         texcoord: [0., 1.],
-        ..Vertex::ZERO
+        layer: 0,
     },
     Vertex {
         pos: [1., 0., 0.],
         texcoord: [1., 1.],
-        ..Vertex::ZERO
+        layer: 0,
     },
     Vertex {
         pos: [1., 1., 0.],
         texcoord: [1., 0.],
-        ..Vertex::ZERO
+        layer: 0,
     },
 ];
 
@@ -667,22 +1549,22 @@ pub const LEFT_FACE: [Vertex; 4] = [
     Vertex {
         pos: [0., 1., 0.],
         texcoord: [0., 0.],
-        ..Vertex::ZERO
+        layer: 0,
     },
     Vertex {
         pos: [0., 0., 0.],
         texcoord: [0., 1.],
-        ..Vertex::ZERO
+        layer: 0,
     },
     Vertex {
         pos: [0., 0., 1.],
         texcoord: [1., 1.],
-        ..Vertex::ZERO
+        layer: 0,
     },
     Vertex {
         pos: [0., 1., 1.],
         texcoord: [1., 0.],
-        ..Vertex::ZERO
+        layer: 0,
     },
 ];
 
@@ -690,22 +1572,22 @@ pub const FRONT_FACE: [Vertex; 4] = [
     Vertex {
         pos: [0., 1., 1.],
         texcoord: [0., 0.],
-        ..Vertex::ZERO
+        layer: 0,
     },
     Vertex {
         pos: [0., 0., 1.],
         texcoord: [0., 1.],
-        ..Vertex::ZERO
+        layer: 0,
     },
     Vertex {
         pos: [1., 0., 1.],
         texcoord: [1., 1.],
-        ..Vertex::ZERO
+        layer: 0,
     },
     Vertex {
         pos: [1., 1., 1.],
         texcoord: [1., 0.],
-        ..Vertex::ZERO
+        layer: 0,
     },
 ];
 
@@ -713,25 +1595,71 @@ pub const REAR_FACE: [Vertex; 4] = [
     Vertex {
         pos: [1., 1., 0.],
         texcoord: [0., 0.],
-        ..Vertex::ZERO
+        layer: 0,
     },
     Vertex {
         pos: [1., 0., 0.],
         texcoord: [0., 1.],
-        ..Vertex::ZERO
+        layer: 0,
     },
     Vertex {
         pos: [0., 0., 0.],
         texcoord: [1., 1.],
-        ..Vertex::ZERO
+        layer: 0,
     },
     Vertex {
         pos: [0., 1., 0.],
         texcoord: [1., 0.],
-        ..Vertex::ZERO
+        layer: 0,
     },
 ];
 
+/// Blocks with a layer in the block texture array, in layer order.
+const TEXTURED_BLOCKS: [Block; 7] = [
+    Block::Grass,
+    Block::Dirt,
+    Block::Stone,
+    Block::Sand,
+    Block::Water,
+    Block::Log,
+    Block::Leaves,
+];
+
+/// Get the layer of the block texture array to draw `block` with.
+pub fn texture_layer(block: Block) -> u32 {
+    TEXTURED_BLOCKS
+        .iter()
+        .position(|textured| *textured == block)
+        .unwrap_or(0) as u32
+}
+
+/// The linear color the base texture is multiplied by for each block. Until blocks get textures
+/// of their own, they share the grass pattern in different colors.
+fn block_tint(block: Block) -> [f32; 3] {
+    match block {
+        Block::Grass => [0.5, 0.76, 0.26],
+        Block::Dirt => [0.45, 0.3, 0.18],
+        Block::Stone => [0.5, 0.5, 0.5],
+        Block::Sand => [0.9, 0.85, 0.6],
+        Block::Water => [0.2, 0.35, 0.8],
+        Block::Log => [0.4, 0.28, 0.15],
+        Block::Leaves => [0.25, 0.55, 0.2],
+        Block::Empty => [1.0, 1.0, 1.0],
+    }
+}
+
+/// Multiply an sRGB image by a linear color.
+fn tinted(img: &RgbaImage, tint: [f32; 3]) -> RgbaImage {
+    let mut out = img.clone();
+    for pixel in out.pixels_mut() {
+        for (channel, tint) in pixel.0.iter_mut().zip(tint) {
+            let linear = (*channel as f32 / 255.0).powf(2.2) * tint;
+            *channel = (linear.powf(1.0 / 2.2) * 255.0).round() as u8;
+        }
+    }
+    out
+}
+
 pub fn shift_face(base_face: [Vertex; 4], (dx, dy, dz): (f32, f32, f32)) -> [Vertex; 4] {
     base_face.map(|mut v| {
         v.pos = [v.pos[0] + dx, v.pos[1] + dy, v.pos[2] + dz];
@@ -745,8 +1673,10 @@ pub fn shift_indices(base_indices: [u16; 6], start_index: u16) -> [u16; 6] {
     base_indices.map(|i| i + start_index)
 }
 
-mod assets {
-    pub const GRASSTOP: &[u8] = include_bytes!("../assets/grass-top.png");
+/// Get the image of the window icon, a grass block with the base texture `block_texture`.
+pub fn icon_image(block_texture: &RgbaImage) -> RgbaImage {
+    let icon = imageops::resize(block_texture, 32, 32, imageops::FilterType::Triangle);
+    tinted(&icon, block_tint(Block::Grass))
 }
 
 trait AsU8Slice<'a> {
@@ -781,6 +1711,46 @@ mod test {
         assert_eq!(size_of::<PushConstants>(), 4 * 4);
     }
 
+    #[test]
+    fn test_texture_layers() {
+        for (layer, block) in TEXTURED_BLOCKS.into_iter().enumerate() {
+            assert_eq!(texture_layer(block), layer as u32);
+        }
+        assert_eq!(size_of::<Vertex>(), 6 * 4);
+
+        let img = RgbaImage::from_pixel(1, 1, image::Rgba([255, 255, 255, 128]));
+        let pixel = tinted(&img, [1.0, 0.0, 0.5]).get_pixel(0, 0).0;
+        assert_eq!(pixel[..2], [255, 0]);
+        assert!((180..=190).contains(&pixel[2]));
+        assert_eq!(pixel[3], 128);
+    }
+
+    #[test]
+    fn test_fog_range() {
+        assert_eq!(fog_range(8), (76.8, 128.0));
+        assert_eq!(fog_range(0), fog_range(1));
+        for render_distance in [0, 4, 32] {
+            assert!(far_plane(render_distance) > fog_range(render_distance).1);
+        }
+    }
+
+    #[test]
+    fn test_choose_present_mode() {
+        let supported = [PresentMode::Fifo, PresentMode::Immediate];
+        assert_eq!(
+            choose_present_mode(VsyncMode::Mailbox, &supported),
+            PresentMode::Immediate
+        );
+        assert_eq!(
+            choose_present_mode(VsyncMode::Fifo, &supported),
+            PresentMode::Fifo
+        );
+        assert_eq!(
+            choose_present_mode(VsyncMode::Immediate, &[PresentMode::Fifo]),
+            PresentMode::Fifo
+        );
+    }
+
     #[test]
     fn test_euler() {
         // Rotate clockwise when looking down for 1/2 pi
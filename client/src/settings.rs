@@ -0,0 +1,204 @@
+//! Client settings, loaded from a flat TOML file of `key = value` lines:
+//!
+//! ```toml
+//! mouse_sensitivity = 0.01
+//! mouse_sensitivity_y = 0.008
+//! mouse_acceleration = 0.001
+//! look_smoothing = 0.5
+//! invert_y = false
+//! fov = 45
+//! render_distance = 8
+//! window_title = "Blocks"
+//! ```
+//!
+//! Missing keys keep their defaults, and a missing file means all defaults.
+
+use std::path::Path;
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Settings {
+    /// Radians turned per unit of raw mouse motion.
+    pub mouse_sensitivity: f32,
+    /// Radians turned per unit of raw vertical mouse motion, `mouse_sensitivity` if unset.
+    pub mouse_sensitivity_y: Option<f32>,
+    /// How much the sensitivity grows with the speed of the mouse, in units of raw motion per
+    /// second, 0 for none.
+    pub mouse_acceleration: f32,
+    /// The fraction of the look motion carried over from one 60 FPS frame into the next, from 0
+    /// for raw input up to but excluding 1. See [`LookSmoother`].
+    ///
+    /// [`LookSmoother`]: crate::look::LookSmoother
+    pub look_smoothing: f32,
+    /// Whether moving the mouse up looks down.
+    pub invert_y: bool,
+    /// Vertical field of view, in degrees.
+    pub fov: f32,
+    /// Radius of the loaded area around the camera, in chunks, overriding the quality preset.
+    pub render_distance: Option<u32>,
+    /// The title of the window, which the frame rate is appended to.
+    pub window_title: String,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            mouse_sensitivity: 0.01,
+            mouse_sensitivity_y: None,
+            mouse_acceleration: 0.0,
+            look_smoothing: 0.0,
+            invert_y: false,
+            fov: 45.0,
+            render_distance: None,
+            window_title: "wgpu-block-engine".to_owned(),
+        }
+    }
+}
+
+impl Settings {
+    pub fn load(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read settings {}", path.display()))?;
+        Self::parse(&text).with_context(|| format!("Bad settings {}", path.display()))
+    }
+
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut settings = Self::default();
+        for (line_no, key, value) in parse_key_values(text)? {
+            let bad_value = || format!("line {line_no}: bad value `{value}` for {key}");
+            match key {
+                "mouse_sensitivity" => {
+                    let sensitivity: f32 = value.parse().with_context(bad_value)?;
+                    if sensitivity <= 0.0 {
+                        bail!(bad_value());
+                    }
+                    settings.mouse_sensitivity = sensitivity;
+                }
+                "mouse_sensitivity_y" => {
+                    let sensitivity: f32 = value.parse().with_context(bad_value)?;
+                    if sensitivity <= 0.0 {
+                        bail!(bad_value());
+                    }
+                    settings.mouse_sensitivity_y = Some(sensitivity);
+                }
+                "mouse_acceleration" => {
+                    let acceleration: f32 = value.parse().with_context(bad_value)?;
+                    if acceleration < 0.0 {
+                        bail!(bad_value());
+                    }
+                    settings.mouse_acceleration = acceleration;
+                }
+                "look_smoothing" => {
+                    let smoothing: f32 = value.parse().with_context(bad_value)?;
+                    if !(0.0..1.0).contains(&smoothing) {
+                        bail!(bad_value());
+                    }
+                    settings.look_smoothing = smoothing;
+                }
+                "invert_y" => settings.invert_y = value.parse().with_context(bad_value)?,
+                "fov" => {
+                    let fov: f32 = value.parse().with_context(bad_value)?;
+                    if !(30.0..=120.0).contains(&fov) {
+                        bail!(bad_value());
+                    }
+                    settings.fov = fov;
+                }
+                "render_distance" => {
+                    let distance: u32 = value.parse().with_context(bad_value)?;
+                    if !(1..=32).contains(&distance) {
+                        bail!(bad_value());
+                    }
+                    settings.render_distance = Some(distance);
+                }
+                // May be quoted, as a TOML string
+                "window_title" => settings.window_title = value.trim_matches('"').to_owned(),
+                key => bail!("line {line_no}: unknown key `{key}`"),
+            }
+        }
+        Ok(settings)
+    }
+
+    /// Get the yaw and pitch changes for the raw mouse motion of a frame that took `frame_time`,
+    /// before smoothing.
+    pub fn look_delta(&self, (mouse_x, mouse_y): (f64, f64), frame_time: Duration) -> (f32, f32) {
+        let (mouse_x, mouse_y) = (mouse_x as f32, mouse_y as f32);
+        // Accelerate by the speed of the mouse rather than its motion per frame, which would
+        // make the sensitivity depend on the frame rate
+        let seconds = frame_time.as_secs_f32();
+        let speed = if seconds > 0.0 {
+            mouse_x.hypot(mouse_y) / seconds
+        } else {
+            0.0
+        };
+        let gain = 1.0 + self.mouse_acceleration * speed;
+        let sign = if self.invert_y { 1.0 } else { -1.0 };
+        let sensitivity_y = self.mouse_sensitivity_y.unwrap_or(self.mouse_sensitivity);
+        (
+            mouse_x * self.mouse_sensitivity * gain,
+            mouse_y * sensitivity_y * sign * gain,
+        )
+    }
+}
+
+/// Split a flat TOML file into its `key = value` lines, as `(line_no, key, value)` with line
+/// numbers starting from 1. Keys and values are trimmed, and comments and empty lines skipped.
+pub fn parse_key_values(text: &str) -> Result<Vec<(usize, &str, &str)>> {
+    let mut pairs = vec![];
+    for (line_no, line) in text.lines().enumerate().map(|(i, line)| (i + 1, line)) {
+        let line = match line.split_once('#') {
+            Some((line, _comment)) => line.trim(),
+            None => line.trim(),
+        };
+        if line.is_empty() {
+            continue;
+        }
+        let (key, value) = line
+            .split_once('=')
+            .with_context(|| format!("line {line_no}: expected `key = value`"))?;
+        pairs.push((line_no, key.trim(), value.trim()));
+    }
+    Ok(pairs)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let text = "# Settings\nfov = 70 # wide\ninvert_y = true\n\nrender_distance = 6\n\
+                    window_title = \"Blocks\"\n";
+        let settings = Settings::parse(text).unwrap();
+        assert_eq!(settings.fov, 70.0);
+        assert!(settings.invert_y);
+        assert_eq!(settings.render_distance, Some(6));
+        assert_eq!(settings.window_title, "Blocks");
+        assert_eq!(
+            settings.mouse_sensitivity,
+            Settings::default().mouse_sensitivity
+        );
+        let second = Duration::from_secs(1);
+        assert!(settings.look_delta((0.0, 1.0), second).1 > 0.0);
+
+        let settings = Settings::parse(
+            "mouse_sensitivity = 0.5\nmouse_sensitivity_y = 0.25\nmouse_acceleration = 1",
+        )
+        .unwrap();
+        assert_eq!(settings.look_delta((1.0, 0.0), second), (1.0, 0.0));
+        assert_eq!(settings.look_delta((0.0, 2.0), second), (0.0, -1.5));
+        // The same speed over a longer frame is accelerated the same
+        assert_eq!(settings.look_delta((2.0, 0.0), second * 2), (2.0, 0.0));
+
+        assert!(Settings::parse("fov = 10").is_err());
+        assert!(Settings::parse("invert_y = yes").is_err());
+        assert!(Settings::parse("volume = 1").is_err());
+        assert!(Settings::parse("fov").is_err());
+        assert!(Settings::parse("look_smoothing = 1").is_err());
+        assert!(Settings::parse("mouse_acceleration = -1").is_err());
+    }
+}
@@ -1,11 +1,21 @@
 struct VertexOutput {
     @location(1) texcoord: vec2<f32>,
     @location(2) brightness: f32,
+    @location(3) view_distance: f32,
+    @location(4) @interpolate(flat) layer: u32,
     @builtin(position) pos: vec4<f32>,
 };
 
 struct UniformData {
     trans: mat4x4<f32>,
+    inv_trans: mat4x4<f32>,
+    view: mat4x4<f32>,
+    sky_zenith: vec4<f32>,
+    sky_horizon: vec4<f32>,
+    // Fog color in rgb, density in a
+    fog: vec4<f32>,
+    // AO strength in x, distances of the start and the end of the fade into the fog in y and z
+    params: vec4<f32>,
 };
 
 struct PushConstantsData {
@@ -15,10 +25,15 @@ struct PushConstantsData {
 @group(0) @binding(0)
 var<uniform> uniform_data: UniformData;
 
+// One layer per textured block, see TEXTURED_BLOCKS in render.rs
 @group(1) @binding(0)
-var grass_texture: texture_2d<f32>;
+var block_textures: texture_2d_array<f32>;
 @group(1) @binding(1)
-var grass_sampler: sampler;
+var block_sampler: sampler;
+
+// Number of opaque blocks around each block corner of the subchunk, baked by ao.wgsl
+@group(2) @binding(0)
+var ao_corners: texture_3d<u32>;
 
 var<push_constant> pc: PushConstantsData;
 
@@ -26,16 +41,21 @@ var<push_constant> pc: PushConstantsData;
 fn main_vs(
     @location(0) pos: vec3<f32>,
     @location(1) texcoord: vec2<f32>,
-    @location(2) brightness: f32
+    @location(2) layer: u32
 ) -> VertexOutput {
     var out: VertexOutput;
 
     out.texcoord = texcoord;
+    out.layer = layer;
 
     out.pos = vec4<f32>(pos, 1.0);
     out.pos = uniform_data.trans * (out.pos + pc.shift);
+    out.view_distance = length((uniform_data.view * (vec4<f32>(pos, 1.0) + pc.shift)).xyz);
 
-    out.brightness = brightness;
+    // Subtract 4 so that flat surfaces are bright
+    let opaque_count = textureLoad(ao_corners, vec3<i32>(pos), 0).r;
+    let ao_brightness = (4.0 - f32(max(opaque_count, 4u) - 4u)) / 4.0;
+    out.brightness = mix(1.0, ao_brightness, uniform_data.params.x);
 
     return out;
 }
@@ -43,8 +63,13 @@ fn main_vs(
 
 @fragment
 fn main_fs(vertex: VertexOutput) -> @location(0) vec4<f32> {
-    let grass_multiplier = vec4<f32>(0.5, 0.76, 0.26, 1.0);
-    return grass_multiplier * textureSample(grass_texture, grass_sampler, vertex.texcoord) * vertex.brightness;
+    let color = textureSample(block_textures, block_sampler, vertex.texcoord, i32(vertex.layer)) * vertex.brightness;
+
+    // Exponential-squared fog, and a fade into the fog at the edge of the loaded area
+    let fog_amount = uniform_data.fog.a * vertex.view_distance;
+    let fade = smoothstep(uniform_data.params.y, uniform_data.params.z, vertex.view_distance);
+    let visibility = exp(-fog_amount * fog_amount) * (1.0 - fade);
+    return vec4<f32>(mix(uniform_data.fog.rgb, color.rgb, visibility), color.a);
 }
 
 // vim: set filetype=wgsl:
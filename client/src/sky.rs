@@ -0,0 +1,163 @@
+//! Time of day, and the sky and fog colors derived from it.
+
+use std::time::{Duration, Instant};
+
+use glam::{vec3, Vec3};
+
+/// The real-time duration of a full in-game day.
+const DAY_LENGTH: Duration = Duration::from_secs(20 * 60);
+
+/// Colors of the sky and the fog at some point in time.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SkyColors {
+    /// The color of the sky straight up.
+    pub zenith: Vec3,
+    /// The color of the sky at and below the horizon.
+    pub horizon: Vec3,
+    pub fog: Vec3,
+    /// Density of the exponential-squared fog, in 1 / blocks.
+    pub fog_density: f32,
+}
+
+impl SkyColors {
+    fn lerp(&self, other: &Self, t: f32) -> Self {
+        Self {
+            zenith: self.zenith.lerp(other.zenith, t),
+            horizon: self.horizon.lerp(other.horizon, t),
+            fog: self.fog.lerp(other.fog, t),
+            fog_density: self.fog_density + (other.fog_density - self.fog_density) * t,
+        }
+    }
+}
+
+/// Sky colors at certain hours, sorted by hour. Colors in between are interpolated linearly,
+/// wrapping around from the last keyframe to the first.
+const KEYFRAMES: [(f32, SkyColors); 6] = [
+    (
+        0.0,
+        SkyColors {
+            zenith: vec3(0.01, 0.01, 0.04),
+            horizon: vec3(0.03, 0.04, 0.08),
+            fog: vec3(0.03, 0.04, 0.08),
+            fog_density: 0.02,
+        },
+    ),
+    (
+        5.0,
+        SkyColors {
+            zenith: vec3(0.05, 0.06, 0.15),
+            horizon: vec3(0.45, 0.25, 0.2),
+            fog: vec3(0.35, 0.25, 0.25),
+            fog_density: 0.025,
+        },
+    ),
+    (
+        7.0,
+        SkyColors {
+            zenith: vec3(0.2, 0.35, 0.65),
+            horizon: vec3(0.7, 0.6, 0.5),
+            fog: vec3(0.6, 0.6, 0.6),
+            fog_density: 0.015,
+        },
+    ),
+    (
+        12.0,
+        SkyColors {
+            zenith: vec3(0.15, 0.35, 0.8),
+            horizon: vec3(0.6, 0.75, 0.9),
+            fog: vec3(0.6, 0.75, 0.9),
+            fog_density: 0.008,
+        },
+    ),
+    (
+        18.0,
+        SkyColors {
+            zenith: vec3(0.2, 0.25, 0.55),
+            horizon: vec3(0.9, 0.45, 0.2),
+            fog: vec3(0.65, 0.45, 0.35),
+            fog_density: 0.012,
+        },
+    ),
+    (
+        20.0,
+        SkyColors {
+            zenith: vec3(0.02, 0.02, 0.08),
+            horizon: vec3(0.1, 0.08, 0.15),
+            fog: vec3(0.08, 0.07, 0.12),
+            fog_density: 0.02,
+        },
+    ),
+];
+
+/// Get the sky colors at `hours` into the day, in `0.0..24.0`.
+pub fn sky_colors(hours: f32) -> SkyColors {
+    let hours = hours.rem_euclid(24.0);
+    let next_index = KEYFRAMES.iter().position(|(h, _)| *h > hours).unwrap_or(0);
+    let prev_index = (next_index + KEYFRAMES.len() - 1) % KEYFRAMES.len();
+
+    let (prev_hours, prev) = KEYFRAMES[prev_index];
+    let (next_hours, next) = KEYFRAMES[next_index];
+    let span = (next_hours - prev_hours).rem_euclid(24.0);
+    let t = (hours - prev_hours).rem_euclid(24.0) / span;
+    prev.lerp(&next, t)
+}
+
+/// An in-game clock, advancing with real time.
+pub struct TimeOfDay {
+    hours: f32,
+    last_update: Instant,
+}
+
+impl TimeOfDay {
+    pub fn new(hours: f32) -> Self {
+        Self {
+            hours,
+            last_update: Instant::now(),
+        }
+    }
+
+    /// Advance the clock by the real time elapsed since the last update.
+    pub fn update(&mut self) {
+        let elapsed = self.last_update.elapsed();
+        self.last_update = Instant::now();
+        self.hours += elapsed.as_secs_f32() / DAY_LENGTH.as_secs_f32() * 24.0;
+        self.hours = self.hours.rem_euclid(24.0);
+    }
+
+    /// Hours into the day, in `0.0..24.0`.
+    pub fn hours(&self) -> f32 {
+        self.hours
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_sky_colors_at_keyframes() {
+        for (hours, colors) in KEYFRAMES {
+            assert_eq!(sky_colors(hours), colors);
+        }
+    }
+
+    #[test]
+    fn test_sky_colors_interpolate() {
+        let (noon, noon_colors) = KEYFRAMES[3];
+        let (evening, evening_colors) = KEYFRAMES[4];
+        let colors = sky_colors((noon + evening) / 2.0);
+        let expected = noon_colors.lerp(&evening_colors, 0.5);
+        assert!(colors.zenith.abs_diff_eq(expected.zenith, 1e-6));
+        assert!((colors.fog_density - expected.fog_density).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_sky_colors_wrap_around_midnight() {
+        let (last_hours, last) = KEYFRAMES[KEYFRAMES.len() - 1];
+        let (_, midnight) = KEYFRAMES[0];
+        let colors = sky_colors((last_hours + 24.0) / 2.0);
+        let expected = last.lerp(&midnight, 0.5);
+        assert!(colors.horizon.abs_diff_eq(expected.horizon, 1e-6));
+        assert_eq!(sky_colors(24.0), sky_colors(0.0));
+    }
+}
@@ -0,0 +1,43 @@
+// Draws the sky gradient with a single full-screen triangle behind everything else.
+
+struct UniformData {
+    trans: mat4x4<f32>,
+    inv_trans: mat4x4<f32>,
+    view: mat4x4<f32>,
+    sky_zenith: vec4<f32>,
+    sky_horizon: vec4<f32>,
+    fog: vec4<f32>,
+};
+
+struct VertexOutput {
+    @location(0) ndc: vec2<f32>,
+    @builtin(position) pos: vec4<f32>,
+};
+
+@group(0) @binding(0)
+var<uniform> uniform_data: UniformData;
+
+@vertex
+fn main_vs(@builtin(vertex_index) index: u32) -> VertexOutput {
+    var out: VertexOutput;
+
+    // (-1, -1), (3, -1), (-1, 3)
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    out.ndc = uv * 2.0 - vec2<f32>(1.0, 1.0);
+    out.pos = vec4<f32>(out.ndc, 1.0, 1.0);
+
+    return out;
+}
+
+@fragment
+fn main_fs(vertex: VertexOutput) -> @location(0) vec4<f32> {
+    // Unproject the fragment onto the near and far planes to get the view direction
+    let near = uniform_data.inv_trans * vec4<f32>(vertex.ndc, 0.0, 1.0);
+    let far = uniform_data.inv_trans * vec4<f32>(vertex.ndc, 1.0, 1.0);
+    let dir = normalize(far.xyz / far.w - near.xyz / near.w);
+
+    let t = sqrt(clamp(dir.y, 0.0, 1.0));
+    return vec4<f32>(mix(uniform_data.sky_horizon.rgb, uniform_data.sky_zenith.rgb, t), 1.0);
+}
+
+// vim: set filetype=wgsl:
@@ -0,0 +1,277 @@
+//! World archives: a whole world directory packed into a single file, for backups and for moving
+//! worlds between servers.
+//!
+//! An archive is a gzip stream of the magic bytes `WBWA` and the archive format version,
+//! followed by one entry per file of the world: the length of its path, the path relative to the
+//! world directory with `/` separators, the length of its contents and the contents. Lengths are
+//! little-endian, `u32` for paths and `u64` for contents. The stream ends after the last entry.
+
+use std::ffi::OsStr;
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, ErrorKind, Read, Write};
+use std::path::{Component, Path, PathBuf};
+
+use anyhow::{bail, ensure, Context, Result};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+
+const MAGIC: &[u8; 4] = b"WBWA";
+
+/// The version of the archive layout.
+const ARCHIVE_FORMAT_VERSION: u32 = 1;
+
+/// The maximum length of a path in an archive, in bytes, so that a corrupt length can't make
+/// the import allocate gigabytes.
+const MAX_NAME_LEN: u32 = 4096;
+
+/// Pack the files of the world in `world_dir` into an archive at `path`, returning the number
+/// of files packed. The world should be saved first, as only what's on disk is packed.
+pub fn export(world_dir: &Path, path: &Path) -> Result<usize> {
+    let mut files = vec![];
+    list_files(world_dir, Path::new(""), &mut files)?;
+    files.sort();
+
+    // Write to a temporary file first, so that a failed export can't leave half an archive
+    let temp_path = path.with_extension("tmp");
+    let file = File::create(&temp_path)
+        .with_context(|| format!("Failed to create {}", temp_path.display()))?;
+    let mut out = GzEncoder::new(BufWriter::new(file), Compression::default());
+    out.write_all(MAGIC)?;
+    out.write_all(&ARCHIVE_FORMAT_VERSION.to_le_bytes())?;
+    for relative in &files {
+        let full_path = world_dir.join(relative);
+        let contents = fs::read(&full_path)
+            .with_context(|| format!("Failed to read {}", full_path.display()))?;
+        let name = archive_name(relative)?;
+        out.write_all(&(name.len() as u32).to_le_bytes())?;
+        out.write_all(name.as_bytes())?;
+        out.write_all(&(contents.len() as u64).to_le_bytes())?;
+        out.write_all(&contents)?;
+    }
+    out.finish()?.flush()?;
+    fs::rename(&temp_path, path).with_context(|| format!("Failed to write {}", path.display()))?;
+    Ok(files.len())
+}
+
+/// Unpack the archive at `path` into `world_dir`, returning the number of files unpacked.
+/// Fails if `world_dir` already holds files, so that no world is overwritten.
+///
+/// The archive is unpacked into a temporary sibling directory first, which is only moved into
+/// place once the whole archive was read, so that a failed import leaves no partial world.
+pub fn import(path: &Path, world_dir: &Path) -> Result<usize> {
+    let is_empty = match fs::read_dir(world_dir) {
+        Ok(mut entries) => entries.next().is_none(),
+        Err(e) if e.kind() == ErrorKind::NotFound => true,
+        Err(e) => return Err(e).context(format!("Failed to read {}", world_dir.display())),
+    };
+    ensure!(
+        is_empty,
+        "{} is not empty, refusing to import over it",
+        world_dir.display()
+    );
+
+    let file = File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    let mut input = GzDecoder::new(BufReader::new(file));
+    let mut header = [0; 8];
+    input
+        .read_exact(&mut header)
+        .context("not a world archive")?;
+    ensure!(&header[..4] == MAGIC, "not a world archive");
+    let version = u32::from_le_bytes(header[4..].try_into().unwrap());
+    ensure!(
+        version == ARCHIVE_FORMAT_VERSION,
+        "unsupported archive format version {version}"
+    );
+
+    let temp_dir = world_dir.with_extension("tmp");
+    match fs::remove_dir_all(&temp_dir) {
+        Err(e) if e.kind() != ErrorKind::NotFound => {
+            return Err(e).context(format!("Failed to remove {}", temp_dir.display()))
+        }
+        _ => {}
+    }
+    let count = match unpack(&mut input, &temp_dir) {
+        Ok(count) => count,
+        Err(e) => {
+            let _ = fs::remove_dir_all(&temp_dir);
+            return Err(e);
+        }
+    };
+    // Renaming over an empty directory isn't possible everywhere
+    if world_dir.exists() {
+        fs::remove_dir(world_dir)
+            .with_context(|| format!("Failed to remove {}", world_dir.display()))?;
+    }
+    fs::rename(&temp_dir, world_dir)
+        .with_context(|| format!("Failed to move the world into {}", world_dir.display()))?;
+    Ok(count)
+}
+
+/// Unpack the entries of an archive following its header into `dir`, returning the number of
+/// files unpacked.
+fn unpack(input: &mut impl Read, dir: &Path) -> Result<usize> {
+    fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    let mut count = 0;
+    while let Some(name_len) = read_u32_or_end(input)? {
+        ensure!(name_len <= MAX_NAME_LEN, "bad file name length in archive");
+        let mut name = vec![0; name_len as usize];
+        input.read_exact(&mut name).context("truncated archive")?;
+        let name = String::from_utf8(name).context("bad file name in archive")?;
+        let relative = checked_path(&name)?;
+
+        let mut len = [0; 8];
+        input.read_exact(&mut len).context("truncated archive")?;
+        let len = u64::from_le_bytes(len);
+        let mut contents = vec![];
+        input.take(len).read_to_end(&mut contents)?;
+        ensure!(contents.len() as u64 == len, "truncated archive");
+
+        let full_path = dir.join(relative);
+        if let Some(parent) = full_path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create {}", parent.display()))?;
+        }
+        fs::write(&full_path, contents)
+            .with_context(|| format!("Failed to write {}", full_path.display()))?;
+        count += 1;
+    }
+    Ok(count)
+}
+
+/// Collect the paths of the files under `dir`, relative to the world directory.
+fn list_files(world_dir: &Path, dir: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+    let full_dir = world_dir.join(dir);
+    let entries = fs::read_dir(&full_dir)
+        .with_context(|| format!("Failed to read {}", full_dir.display()))?;
+    for entry in entries {
+        let entry = entry?;
+        let relative = dir.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            list_files(world_dir, &relative, files)?;
+        } else if relative.extension() != Some(OsStr::new("tmp")) {
+            // Temporary files are leftovers of interrupted saves
+            files.push(relative);
+        }
+    }
+    Ok(())
+}
+
+fn archive_name(relative: &Path) -> Result<String> {
+    let parts = relative
+        .components()
+        .map(|component| match component {
+            Component::Normal(part) => part.to_str(),
+            _ => None,
+        })
+        .collect::<Option<Vec<_>>>()
+        .with_context(|| format!("Can't archive {}", relative.display()))?;
+    Ok(parts.join("/"))
+}
+
+/// Turn a path from an archive into a relative path, refusing any that would escape the world
+/// directory.
+fn checked_path(name: &str) -> Result<PathBuf> {
+    let mut path = PathBuf::new();
+    for part in name.split('/') {
+        // Each part must be a plain file name on every platform, e.g. `C:` would be taken as a
+        // drive prefix on Windows and replace the path instead of extending it
+        let mut components = Path::new(part).components();
+        let is_normal = matches!(
+            (components.next(), components.next()),
+            (Some(Component::Normal(_)), None)
+        );
+        if !is_normal || part.contains(['\\', ':']) {
+            bail!("bad file name `{name}` in archive");
+        }
+        path.push(part);
+    }
+    Ok(path)
+}
+
+/// Read a `u32`, or `None` at the end of the stream.
+fn read_u32_or_end(input: &mut impl Read) -> Result<Option<u32>> {
+    let mut bytes = [0; 4];
+    let mut read = 0;
+    while read < bytes.len() {
+        match input.read(&mut bytes[read..])? {
+            0 if read == 0 => return Ok(None),
+            0 => bail!("truncated archive"),
+            n => read += n,
+        }
+    }
+    Ok(Some(u32::from_le_bytes(bytes)))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::world::World;
+    use wgpu_block_shared::chunk::Block;
+
+    #[test]
+    fn test_export_import() {
+        let dir = std::env::temp_dir().join(format!("wgpu-block-archive-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let (world_dir, archive, imported_dir) = (
+            dir.join("world"),
+            dir.join("world.wba"),
+            dir.join("imported"),
+        );
+
+        let mut world = World::open_or_create(&world_dir, Some(42), Some(64)).unwrap();
+        world.set_block((-100, 200, 300), Block::Log).unwrap();
+        world.save().unwrap();
+        // The level file and one region file
+        assert_eq!(export(&world_dir, &archive).unwrap(), 2);
+
+        assert_eq!(import(&archive, &imported_dir).unwrap(), 2);
+        let mut imported = World::open_or_create(&imported_dir, None, None).unwrap();
+        assert_eq!(imported.seed(), 42);
+        assert_eq!(imported.block((-100, 200, 300)), Block::Log);
+        assert!(!imported.is_inside_border((65, 0)));
+
+        // Existing worlds are never overwritten
+        assert!(import(&archive, &world_dir).is_err());
+        assert!(import(&world_dir.join("level.txt"), &dir.join("other")).is_err());
+
+        // Failed imports leave no partial world behind
+        let truncated = dir.join("truncated.wba");
+        let mut input = GzDecoder::new(File::open(&archive).unwrap());
+        let mut bytes = vec![];
+        input.read_to_end(&mut bytes).unwrap();
+        bytes.truncate(bytes.len() - 1);
+        let mut out = GzEncoder::new(File::create(&truncated).unwrap(), Compression::default());
+        out.write_all(&bytes).unwrap();
+        out.finish().unwrap();
+        assert!(import(&truncated, &dir.join("partial")).is_err());
+        assert!(!dir.join("partial").exists());
+        assert!(!dir.join("partial.tmp").exists());
+
+        // Corrupt lengths are caught before allocating
+        let huge_name = dir.join("huge-name.wba");
+        let mut out = GzEncoder::new(File::create(&huge_name).unwrap(), Compression::default());
+        out.write_all(MAGIC).unwrap();
+        out.write_all(&ARCHIVE_FORMAT_VERSION.to_le_bytes())
+            .unwrap();
+        out.write_all(&u32::MAX.to_le_bytes()).unwrap();
+        out.finish().unwrap();
+        assert!(import(&huge_name, &dir.join("huge")).is_err());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_checked_path() {
+        assert_eq!(
+            checked_path("region/r.0.-1.bin").unwrap(),
+            Path::new("region").join("r.0.-1.bin")
+        );
+        assert!(checked_path("../level.txt").is_err());
+        assert!(checked_path("/etc/passwd").is_err());
+        assert!(checked_path("region//r.bin").is_err());
+        assert!(checked_path("").is_err());
+        assert!(checked_path("C:/x").is_err());
+        assert!(checked_path("region/./r.bin").is_err());
+    }
+}
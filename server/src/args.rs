@@ -0,0 +1,185 @@
+//! Command-line arguments of the server.
+
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
+
+use crate::core::DEFAULT_TICK_RATE;
+use crate::watchdog::WatchdogConfig;
+
+const USAGE: &str = "usage: wgpu-block-server [--world <dir>] [--seed <seed>] \
+                     [--world-border <chunks>] [--spawn-protection <blocks>] \
+                     [--stuck-tick-secs <secs>] [--stop-on-stuck-tick] \
+                     [--metrics <addr:port>] [--autosave-secs <secs>|off] \
+                     [--tick-rate <ticks/s>] [--import <archive>]";
+
+const DEFAULT_AUTOSAVE_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+const MAX_TICK_RATE: u32 = 1000;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Args {
+    /// The directory the world is saved in.
+    pub world_dir: PathBuf,
+    /// The seed to generate a new world with. Ignored if the world already exists.
+    pub seed: Option<u32>,
+    /// The world border to set, in chunks from the origin. Saved with the world.
+    pub world_border: Option<u32>,
+    /// The radius around the origin, in blocks, where blocks can't be changed.
+    pub spawn_protection: Option<u32>,
+    pub watchdog: WatchdogConfig,
+    /// The address to serve Prometheus metrics on, if any.
+    pub metrics: Option<SocketAddr>,
+    /// How often to save the world while running, if at all.
+    pub autosave: Option<Duration>,
+    /// The number of ticks per second.
+    pub tick_rate: u32,
+    /// A world archive to unpack into the world directory before opening it.
+    pub import: Option<PathBuf>,
+}
+
+impl Args {
+    pub fn parse(mut args: impl Iterator<Item = String>) -> Result<Self> {
+        let mut out = Self {
+            world_dir: PathBuf::from("world"),
+            seed: None,
+            world_border: None,
+            spawn_protection: None,
+            watchdog: WatchdogConfig::default(),
+            metrics: None,
+            autosave: Some(DEFAULT_AUTOSAVE_INTERVAL),
+            tick_rate: DEFAULT_TICK_RATE,
+            import: None,
+        };
+        while let Some(arg) = args.next() {
+            let mut value = || {
+                args.next()
+                    .with_context(|| format!("missing value for {arg}"))
+            };
+            match arg.as_str() {
+                "--world" => out.world_dir = PathBuf::from(value()?),
+                "--seed" => {
+                    let seed = value()?;
+                    out.seed = Some(seed.parse().with_context(|| format!("bad seed `{seed}`"))?);
+                }
+                "--world-border" => {
+                    let chunks = value()?;
+                    out.world_border = Some(
+                        chunks
+                            .parse()
+                            .with_context(|| format!("bad world border `{chunks}`"))?,
+                    );
+                }
+                "--spawn-protection" => {
+                    let blocks = value()?;
+                    out.spawn_protection = Some(
+                        blocks
+                            .parse()
+                            .with_context(|| format!("bad spawn protection `{blocks}`"))?,
+                    );
+                }
+                "--stuck-tick-secs" => {
+                    let secs = value()?;
+                    match secs.parse() {
+                        Ok(secs) if secs > 0 => {
+                            out.watchdog.threshold = Duration::from_secs(secs);
+                        }
+                        _ => bail!("bad stuck tick threshold `{secs}`"),
+                    }
+                }
+                "--stop-on-stuck-tick" => out.watchdog.stop_on_stuck = true,
+                "--metrics" => {
+                    let addr = value()?;
+                    out.metrics = Some(
+                        addr.parse()
+                            .with_context(|| format!("bad metrics address `{addr}`"))?,
+                    );
+                }
+                "--autosave-secs" => {
+                    let secs = value()?;
+                    out.autosave = match secs.as_str() {
+                        "off" => None,
+                        _ => match secs.parse() {
+                            Ok(secs) if secs > 0 => Some(Duration::from_secs(secs)),
+                            _ => bail!("bad autosave interval `{secs}`"),
+                        },
+                    };
+                }
+                "--tick-rate" => {
+                    let rate = value()?;
+                    out.tick_rate = match rate.parse() {
+                        Ok(rate) if (1..=MAX_TICK_RATE).contains(&rate) => rate,
+                        _ => bail!("bad tick rate `{rate}`, expected 1 to {MAX_TICK_RATE}"),
+                    };
+                }
+                "--import" => out.import = Some(PathBuf::from(value()?)),
+                "--help" | "-h" => bail!(USAGE),
+                _ => bail!("unknown argument `{arg}`\n{USAGE}"),
+            }
+        }
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Args> {
+        Args::parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn test_parse() {
+        let args = parse(&["--seed", "42", "--world", "saves/a"]).unwrap();
+        assert_eq!(args.seed, Some(42));
+        assert_eq!(args.world_dir, PathBuf::from("saves/a"));
+
+        assert_eq!(parse(&[]).unwrap().seed, None);
+        assert!(parse(&["--seed"]).is_err());
+        assert!(parse(&["--seed", "-1"]).is_err());
+        assert!(parse(&["--fast"]).is_err());
+
+        assert_eq!(
+            parse(&["--world-border", "8"]).unwrap().world_border,
+            Some(8)
+        );
+        assert!(parse(&["--world-border", "-8"]).is_err());
+        assert_eq!(
+            parse(&["--spawn-protection", "16"])
+                .unwrap()
+                .spawn_protection,
+            Some(16)
+        );
+
+        let args = parse(&["--stop-on-stuck-tick", "--stuck-tick-secs", "30"]).unwrap();
+        assert!(args.watchdog.stop_on_stuck);
+        assert_eq!(args.watchdog.threshold, Duration::from_secs(30));
+        assert!(parse(&["--stuck-tick-secs", "0"]).is_err());
+
+        let args = parse(&["--metrics", "127.0.0.1:9100"]).unwrap();
+        assert_eq!(args.metrics, Some("127.0.0.1:9100".parse().unwrap()));
+        assert!(parse(&["--metrics", "9100"]).is_err());
+
+        assert_eq!(
+            parse(&[]).unwrap().autosave,
+            Some(DEFAULT_AUTOSAVE_INTERVAL)
+        );
+        assert_eq!(
+            parse(&["--autosave-secs", "30"]).unwrap().autosave,
+            Some(Duration::from_secs(30))
+        );
+        assert_eq!(parse(&["--autosave-secs", "off"]).unwrap().autosave, None);
+        assert!(parse(&["--autosave-secs", "0"]).is_err());
+
+        assert_eq!(parse(&[]).unwrap().tick_rate, DEFAULT_TICK_RATE);
+        assert_eq!(parse(&["--tick-rate", "60"]).unwrap().tick_rate, 60);
+        assert!(parse(&["--tick-rate", "0"]).is_err());
+        assert_eq!(
+            parse(&["--import", "a.wba"]).unwrap().import,
+            Some(PathBuf::from("a.wba"))
+        );
+    }
+}
@@ -0,0 +1,262 @@
+//! The admin console, reading commands from stdin.
+
+use std::io::BufRead;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::mpsc::Sender;
+use std::thread;
+
+use anyhow::{bail, Context, Error, Result};
+use tracing::{info, warn};
+use wgpu_block_shared::chunk::Block;
+use wgpu_block_shared::structure::{Mirror, Rotation};
+
+const NAMES: [&str; 12] = [
+    "help",
+    "list",
+    "kick",
+    "setblock",
+    "explode",
+    "raycast",
+    "place-structure",
+    "spawn",
+    "tps",
+    "save",
+    "export",
+    "stop",
+];
+const HELP: &str = "commands: help, list, kick <uuid>, setblock <x> <y> <z> <block>, \
+                    explode <x> <y> <z> <power>, \
+                    raycast <x> <y> <z> <dx> <dy> <dz>, \
+                    place-structure <name> <x> <y> <z> [0|90|180|270] [x|z], \
+                    spawn, tps, save, export <path>, stop";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Help,
+    /// List the connected clients.
+    List,
+    /// Disconnect the client with the given UUID.
+    Kick(String),
+    SetBlock {
+        pos: (i64, i64, i64),
+        block: Block,
+    },
+    Explode {
+        pos: (i64, i64, i64),
+        power: f32,
+    },
+    /// Report the first solid block along a ray.
+    Raycast {
+        origin: [f32; 3],
+        direction: [f32; 3],
+    },
+    /// Place the structure template of the given name from the world's structures directory,
+    /// rotated and then mirrored, with its anchor at `pos`.
+    PlaceStructure {
+        name: String,
+        pos: (i64, i64, i64),
+        rotation: Rotation,
+        mirror: Option<Mirror>,
+    },
+    /// Report the spawn point.
+    Spawn,
+    /// Report the measured and target ticks per second.
+    Tps,
+    /// Save the world.
+    Save,
+    /// Save the world and pack it into an archive at the given path.
+    Export(PathBuf),
+    /// Save the world and shut down the server.
+    Stop,
+}
+
+impl FromStr for Command {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let words = s.split_whitespace().collect::<Vec<_>>();
+        let coord = |s: &str| {
+            s.parse::<i64>()
+                .with_context(|| format!("bad coordinate `{s}`"))
+        };
+        let float = |s: &str| {
+            s.parse::<f32>()
+                .with_context(|| format!("bad number `{s}`"))
+        };
+        let rotation = |s: &str| match s {
+            "0" => Ok(Rotation::None),
+            "90" => Ok(Rotation::Clockwise90),
+            "180" => Ok(Rotation::Rotate180),
+            "270" => Ok(Rotation::CounterClockwise90),
+            _ => bail!("bad rotation `{s}`, expected 0, 90, 180 or 270"),
+        };
+        let mirror = |s: &str| match s {
+            "x" => Ok(Mirror::X),
+            "z" => Ok(Mirror::Z),
+            _ => bail!("bad mirror `{s}`, expected x or z"),
+        };
+        let command = match words.as_slice() {
+            ["help"] => Command::Help,
+            ["list"] => Command::List,
+            ["kick", uuid] => Command::Kick(uuid.to_string()),
+            ["setblock", x, y, z, block] => Command::SetBlock {
+                pos: (coord(x)?, coord(y)?, coord(z)?),
+                block: block.parse()?,
+            },
+            ["explode", x, y, z, power] => Command::Explode {
+                pos: (coord(x)?, coord(y)?, coord(z)?),
+                power: match power.parse::<f32>() {
+                    Ok(power) if power > 0.0 && power <= 32.0 => power,
+                    _ => bail!("bad power `{power}`, expected a number in 0..=32"),
+                },
+            },
+            ["raycast", x, y, z, dx, dy, dz] => Command::Raycast {
+                origin: [float(x)?, float(y)?, float(z)?],
+                direction: [float(dx)?, float(dy)?, float(dz)?],
+            },
+            ["place-structure", name, x, y, z, options @ ..] if options.len() <= 2 => {
+                // Names are file stems in the structures directory, so keep them from leaving it
+                if !name
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+                {
+                    bail!("bad structure name `{name}`");
+                }
+                Command::PlaceStructure {
+                    name: name.to_string(),
+                    pos: (coord(x)?, coord(y)?, coord(z)?),
+                    rotation: options
+                        .first()
+                        .map_or(Ok(Rotation::None), |s| rotation(s))?,
+                    mirror: options.get(1).map(|s| mirror(s)).transpose()?,
+                }
+            }
+            ["spawn"] => Command::Spawn,
+            ["tps"] => Command::Tps,
+            ["save"] => Command::Save,
+            ["export", path] => Command::Export(PathBuf::from(path)),
+            ["stop"] => Command::Stop,
+            [] => bail!("empty command"),
+            [name, ..] if NAMES.contains(name) => bail!("bad usage of `{name}`\n{HELP}"),
+            [name, ..] => bail!("unknown command `{name}`\n{HELP}"),
+        };
+        Ok(command)
+    }
+}
+
+/// Spawn a thread reading commands from stdin, one per line, sending them to `sender`. Lines
+/// that fail to parse are reported and skipped.
+pub fn spawn_stdin_reader(sender: Sender<Command>) {
+    thread::Builder::new()
+        .name("console".to_owned())
+        .spawn(move || {
+            for line in std::io::stdin().lock().lines() {
+                let line = match line {
+                    Ok(line) => line,
+                    Err(e) => {
+                        warn!("Failed to read from stdin: {e}");
+                        break;
+                    }
+                };
+                if line.trim().is_empty() {
+                    continue;
+                }
+                match line.parse::<Command>() {
+                    Ok(Command::Help) => info!("{HELP}"),
+                    Ok(command) => {
+                        if sender.send(command).is_err() {
+                            // The game loop has stopped
+                            break;
+                        }
+                    }
+                    Err(e) => warn!("{e:#}"),
+                }
+            }
+            info!("Console closed");
+        })
+        .expect("Failed to spawn console thread");
+}
+
+/// Spawn a thread sending [`Command::Stop`] to `sender` on Ctrl-C, so that the world is saved
+/// before the server exits. A second Ctrl-C exits right away.
+pub fn spawn_interrupt_handler(sender: Sender<Command>) -> Result<()> {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_io()
+        .build()
+        .context("Failed to create the interrupt handler runtime")?;
+    thread::Builder::new()
+        .name("interrupt".to_owned())
+        .spawn(move || {
+            runtime.block_on(async {
+                if let Err(e) = tokio::signal::ctrl_c().await {
+                    warn!("Failed to listen for Ctrl-C: {e}");
+                    return;
+                }
+                info!("Received Ctrl-C, saving the world before stopping");
+                let _ = sender.send(Command::Stop);
+                if tokio::signal::ctrl_c().await.is_ok() {
+                    warn!("Received Ctrl-C again, exiting without saving");
+                    std::process::exit(130);
+                }
+            });
+        })
+        .context("Failed to spawn interrupt thread")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!("stop".parse::<Command>().unwrap(), Command::Stop);
+        assert_eq!("spawn".parse::<Command>().unwrap(), Command::Spawn);
+        assert!("spawn 1".parse::<Command>().is_err());
+        assert_eq!("tps".parse::<Command>().unwrap(), Command::Tps);
+        assert_eq!(
+            "export backups/a.wba".parse::<Command>().unwrap(),
+            Command::Export(PathBuf::from("backups/a.wba"))
+        );
+        assert!("export".parse::<Command>().is_err());
+        assert_eq!(
+            "place-structure hut 1 64 -3".parse::<Command>().unwrap(),
+            Command::PlaceStructure {
+                name: "hut".to_owned(),
+                pos: (1, 64, -3),
+                rotation: Rotation::None,
+                mirror: None,
+            }
+        );
+        assert_eq!(
+            "place-structure hut 1 64 -3 270 z"
+                .parse::<Command>()
+                .unwrap(),
+            Command::PlaceStructure {
+                name: "hut".to_owned(),
+                pos: (1, 64, -3),
+                rotation: Rotation::CounterClockwise90,
+                mirror: Some(Mirror::Z),
+            }
+        );
+        assert!("place-structure hut 1 64 -3 45".parse::<Command>().is_err());
+        assert!("place-structure ../hut 1 64 -3".parse::<Command>().is_err());
+        assert!("place-structure hut 1 64 -3 90 x 1"
+            .parse::<Command>()
+            .is_err());
+        assert_eq!(
+            " setblock 1 -2  3 stone".parse::<Command>().unwrap(),
+            Command::SetBlock {
+                pos: (1, -2, 3),
+                block: Block::Stone
+            }
+        );
+        assert!("setblock 1 2 3".parse::<Command>().is_err());
+        assert!("setblock 1 2 z stone".parse::<Command>().is_err());
+        assert!("setblock 1 2 3 marble".parse::<Command>().is_err());
+        assert!("explode 0 0 0 100".parse::<Command>().is_err());
+        assert!("".parse::<Command>().is_err());
+        assert!("fly".parse::<Command>().is_err());
+    }
+}
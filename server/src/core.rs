@@ -0,0 +1,192 @@
+//! The server game loop.
+
+use std::net::SocketAddr;
+use std::ops::ControlFlow;
+use std::sync::atomic::Ordering;
+use std::sync::mpsc::Receiver;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use tracing::{info, warn};
+
+use crate::console::Command;
+use crate::events::EventBus;
+use crate::metrics::{self, EventCounter, Gauges};
+use crate::plugin::Plugins;
+use crate::tick::{TickPhase, TickScheduler, TickStatsHandle};
+use crate::watchdog::{Watchdog, WatchdogConfig};
+use crate::world::World;
+
+/// The number of server ticks per second, unless set with `--tick-rate`.
+pub const DEFAULT_TICK_RATE: u32 = 20;
+
+/// The maximum distance of rays cast by commands.
+const RAYCAST_DISTANCE: f32 = 256.0;
+
+/// The time between two tick-stats reports in the log.
+const STATS_REPORT_INTERVAL: Duration = Duration::from_secs(60);
+
+pub fn run(
+    mut world: World,
+    mut plugins: Plugins,
+    commands: Receiver<Command>,
+    watchdog: WatchdogConfig,
+    metrics: Option<SocketAddr>,
+    autosave: Option<Duration>,
+    tick_rate: u32,
+) -> Result<()> {
+    world.generate_spawn_area();
+
+    let mut scheduler = TickScheduler::new(tick_rate);
+    let stats = scheduler.stats();
+    let watchdog = Watchdog::spawn(scheduler.marker(), watchdog);
+    let gauges = Arc::new(Gauges::default());
+    let mut events = EventBus::default();
+    if let Some(addr) = metrics {
+        metrics::spawn_exporter(addr, stats.clone(), gauges.clone())?;
+        events.subscribe(Box::new(EventCounter(gauges.clone())));
+    }
+    info!(
+        seed = world.seed(),
+        "Server running at {tick_rate} ticks per second"
+    );
+
+    let to_ticks = |interval: Duration| (interval.as_secs_f64() * tick_rate as f64).ceil() as u64;
+    let autosave_ticks = autosave.map(to_ticks);
+    let stats_report_ticks = to_ticks(STATS_REPORT_INTERVAL);
+    let mut stopping = false;
+    let mut tick = 0;
+    while !stopping {
+        let due = scheduler.wait();
+        for _ in 0..due {
+            scheduler.run_tick(|timer| {
+                let commands: Vec<_> =
+                    timer.phase(TickPhase::Inbound, || commands.try_iter().collect());
+                timer.phase(TickPhase::GameTick, || {
+                    for command in commands {
+                        let flow = execute(&mut world, &mut plugins, tick_rate, &stats, command);
+                        if flow.is_break() {
+                            stopping = true;
+                        }
+                    }
+                    plugins.on_tick(&mut world, tick);
+                    events.dispatch(&mut world, &mut plugins);
+                });
+                tick += 1;
+            });
+            gauges
+                .loaded_chunks
+                .store(world.loaded_chunk_count(), Ordering::Relaxed);
+            // Stopping saves anyway
+            if !stopping && matches!(autosave_ticks, Some(n) if tick % n == 0) {
+                match world.save() {
+                    Ok(written) => info!("Autosaved the world, {written} chunks written"),
+                    Err(e) => warn!("Failed to autosave the world: {e:#}"),
+                }
+            }
+            if watchdog.stop_requested() {
+                warn!("Stopping the server after a stuck tick");
+                stopping = true;
+            }
+            if stopping {
+                break;
+            }
+        }
+
+        let stats = stats.snapshot();
+        if stats.ticks % stats_report_ticks < due as u64 {
+            info!(
+                tps = stats.tps,
+                slow_ticks = stats.slow_ticks,
+                skipped_ticks = stats.skipped_ticks,
+                "Average tick time {:?}, slowest {:?}",
+                stats.average.total(),
+                stats.max.total()
+            );
+        }
+    }
+
+    let written = world.save()?;
+    info!("Server stopped, {written} chunks written");
+    Ok(())
+}
+
+/// Execute a console command, breaking if the server should stop.
+fn execute(
+    world: &mut World,
+    plugins: &mut Plugins,
+    tick_rate: u32,
+    stats: &TickStatsHandle,
+    command: Command,
+) -> ControlFlow<()> {
+    match command {
+        // Handled by the console itself
+        Command::Help => {}
+        Command::List => info!("0 clients connected"),
+        Command::Kick(uuid) => warn!("No connected client has UUID {uuid}"),
+        Command::SetBlock { pos, block } => match plugins.set_block(world, pos, block) {
+            Ok(()) => info!("Set block at {pos:?} to {block}"),
+            Err(e) => warn!("Failed to set block: {e:#}"),
+        },
+        Command::Explode { pos, power } => {
+            // Blocks the plugins don't allow breaking survive the explosion
+            let destroyed = world.explode(pos, power, |pos, block| {
+                plugins.on_block_break(pos, block).is_ok()
+            });
+            info!("Explosion at {pos:?} destroyed {destroyed} blocks");
+        }
+        Command::Raycast { origin, direction } => {
+            match world.raycast(origin, direction, RAYCAST_DISTANCE) {
+                Some(hit) => info!(
+                    "Ray hit {} at {:?} through {:?} after {} blocks",
+                    world.block(hit.pos),
+                    hit.pos,
+                    hit.face,
+                    hit.distance
+                ),
+                None => info!("Ray hit nothing within {RAYCAST_DISTANCE} blocks"),
+            }
+        }
+        Command::PlaceStructure {
+            name,
+            pos,
+            rotation,
+            mirror,
+        } => match world.load_structure(&name) {
+            Ok(structure) => {
+                let structure = structure.rotated(rotation);
+                let structure = match mirror {
+                    Some(mirror) => structure.mirrored(mirror),
+                    None => structure,
+                };
+                let placed = plugins.place_structure(world, &structure, pos);
+                let total = structure.blocks().count();
+                info!("Placed structure {name} at {pos:?}, {placed} of {total} blocks");
+            }
+            Err(e) => warn!("Failed to place structure: {e:#}"),
+        },
+        Command::Spawn => info!("The spawn point is {:?}", world.spawn_point()),
+        Command::Tps => {
+            let stats = stats.snapshot();
+            info!(
+                "{:.1} ticks per second of {tick_rate}, average tick time {:?}",
+                stats.tps,
+                stats.average.total()
+            );
+        }
+        Command::Save => match world.save() {
+            Ok(written) => info!("Saved the world, {written} chunks written"),
+            Err(e) => warn!("Failed to save the world: {e:#}"),
+        },
+        Command::Export(path) => match world.export(&path) {
+            Ok(files) => info!("Exported the world to {}, {files} files", path.display()),
+            Err(e) => warn!("Failed to export the world: {e:#}"),
+        },
+        Command::Stop => {
+            info!("Stopping the server");
+            return ControlFlow::Break(());
+        }
+    }
+    ControlFlow::Continue(())
+}
@@ -0,0 +1,108 @@
+//! Events of the game world, delivered to the subsystems subscribed to them.
+//!
+//! The world records an event for every change as it happens, and [`EventBus::dispatch`]
+//! delivers the events recorded so far once per tick. Events raised by subscribers while
+//! handling an event are delivered in the next dispatch.
+
+use wgpu_block_shared::chunk::Block;
+
+use crate::plugin::Plugins;
+use crate::world::World;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WorldEvent {
+    /// The block at `pos` changed from `old` to `new`.
+    BlockChanged {
+        pos: (i64, i64, i64),
+        old: Block,
+        new: Block,
+    },
+    /// The chunk at chunk coordinates `coords` was generated, rather than loaded from disk.
+    ChunkGenerated { coords: (i64, i64) },
+}
+
+pub trait Subscriber: Send {
+    fn on_event(&mut self, world: &mut World, event: &WorldEvent);
+}
+
+/// The subsystems subscribed to world events.
+#[derive(Default)]
+pub struct EventBus {
+    subscribers: Vec<Box<dyn Subscriber>>,
+}
+
+impl EventBus {
+    pub fn subscribe(&mut self, subscriber: Box<dyn Subscriber>) {
+        self.subscribers.push(subscriber);
+    }
+
+    /// Deliver the events recorded by `world` since the last dispatch, returning how many were
+    /// delivered. The plugins see each event first, then the subscribers in the order they
+    /// subscribed.
+    pub fn dispatch(&mut self, world: &mut World, plugins: &mut Plugins) -> usize {
+        let events = world.take_events();
+        for event in &events {
+            plugins.on_event(world, event);
+            for subscriber in &mut self.subscribers {
+                subscriber.on_event(world, event);
+            }
+        }
+        events.len()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::fs;
+    use std::sync::{Arc, Mutex};
+
+    /// Records the events it sees, and sets a block in reaction to the first block change.
+    struct Recorder(Arc<Mutex<Vec<WorldEvent>>>);
+
+    impl Subscriber for Recorder {
+        fn on_event(&mut self, world: &mut World, event: &WorldEvent) {
+            let mut events = self.0.lock().unwrap();
+            if events.is_empty() {
+                world.set_block((0, 255, 0), Block::Stone).unwrap();
+            }
+            events.push(*event);
+        }
+    }
+
+    #[test]
+    fn test_dispatch() {
+        let dir = std::env::temp_dir().join(format!("wgpu-block-events-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let mut world = World::open_or_create(&dir, Some(0), None).unwrap();
+        let mut plugins = Plugins::default();
+        let events = Arc::new(Mutex::new(vec![]));
+        let mut bus = EventBus::default();
+        bus.subscribe(Box::new(Recorder(events.clone())));
+
+        world.chunk((0, 0));
+        world.set_block((1, 255, 2), Block::Dirt).unwrap();
+        // Setting a block to itself changes nothing
+        let bottom = world.block((1, 0, 2));
+        world.set_block((1, 0, 2), bottom).unwrap();
+        assert_eq!(bus.dispatch(&mut world, &mut plugins), 2);
+        assert_eq!(
+            *events.lock().unwrap(),
+            vec![
+                WorldEvent::ChunkGenerated { coords: (0, 0) },
+                WorldEvent::BlockChanged {
+                    pos: (1, 255, 2),
+                    old: Block::Empty,
+                    new: Block::Dirt
+                }
+            ]
+        );
+
+        // The block set by the subscriber is seen in the next dispatch
+        assert_eq!(bus.dispatch(&mut world, &mut plugins), 1);
+        assert_eq!(bus.dispatch(&mut world, &mut plugins), 0);
+        assert_eq!(events.lock().unwrap().len(), 3);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
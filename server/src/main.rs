@@ -1,3 +1,58 @@
-fn main() {
-    println!("Hello, world!");
+use std::sync::mpsc;
+
+use anyhow::{Context, Result};
+use tracing::info;
+
+mod archive;
+mod args;
+mod console;
+mod core;
+mod events;
+mod metrics;
+mod plugin;
+mod region;
+mod tick;
+mod watchdog;
+mod world;
+
+fn main() -> Result<()> {
+    init_tracing();
+    let args = args::Args::parse(std::env::args().skip(1))?;
+    if let Some(path) = &args.import {
+        let files = archive::import(path, &args.world_dir)
+            .with_context(|| format!("Failed to import {}", path.display()))?;
+        info!(
+            "Imported {} into {}, {files} files",
+            path.display(),
+            args.world_dir.display()
+        );
+    }
+    let world = world::World::open_or_create(&args.world_dir, args.seed, args.world_border)?;
+    let plugins = plugin::Plugins::from_args(&args);
+    let (command_tx, commands) = mpsc::channel();
+    console::spawn_stdin_reader(command_tx.clone());
+    console::spawn_interrupt_handler(command_tx)?;
+    core::run(
+        world,
+        plugins,
+        commands,
+        args.watchdog,
+        args.metrics,
+        args.autosave,
+        args.tick_rate,
+    )
+}
+
+fn init_tracing() {
+    use std::str::FromStr;
+    use tracing_subscriber::*;
+
+    const PKG_NAME: &str = env!("CARGO_PKG_NAME");
+    fmt()
+        .with_env_filter(EnvFilter::try_from_default_env().unwrap_or_else(|_| {
+            let pkg_name = PKG_NAME.replace("-", "_");
+            EnvFilter::from_str(&format!("warn,{pkg_name}=info"))
+                .expect("Failed to parse env-filter string")
+        }))
+        .init();
 }
@@ -0,0 +1,179 @@
+//! An HTTP endpoint exposing server metrics in the Prometheus text format.
+//!
+//! The exporter serves `GET /metrics` on its own thread, one connection at a time, which is
+//! plenty for a scraper polling every few seconds.
+
+use std::fmt::Write as _;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use tracing::{info, warn};
+
+use crate::events::{Subscriber, WorldEvent};
+use crate::tick::{TickStats, TickStatsHandle, TICK_HISTOGRAM_BOUNDS};
+use crate::world::World;
+
+/// How long to wait for a scraper to send its request.
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Gauges updated by the game loop, which the exporter can't read from the world directly.
+#[derive(Debug, Default)]
+pub struct Gauges {
+    pub loaded_chunks: AtomicUsize,
+    pub blocks_changed: AtomicUsize,
+    pub chunks_generated: AtomicUsize,
+}
+
+/// Counts the world events exported as metrics.
+pub struct EventCounter(pub Arc<Gauges>);
+
+impl Subscriber for EventCounter {
+    fn on_event(&mut self, _world: &mut World, event: &WorldEvent) {
+        let counter = match event {
+            WorldEvent::BlockChanged { .. } => &self.0.blocks_changed,
+            WorldEvent::ChunkGenerated { .. } => &self.0.chunks_generated,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Start serving metrics on `addr`.
+pub fn spawn_exporter(addr: SocketAddr, stats: TickStatsHandle, gauges: Arc<Gauges>) -> Result<()> {
+    let listener =
+        TcpListener::bind(addr).with_context(|| format!("Failed to bind metrics to {addr}"))?;
+    info!("Serving metrics on http://{addr}/metrics");
+    thread::Builder::new()
+        .name("metrics".to_owned())
+        .spawn(move || {
+            for stream in listener.incoming() {
+                let result = stream
+                    .map_err(Into::into)
+                    .and_then(|stream| serve(stream, &stats, &gauges));
+                if let Err(e) = result {
+                    warn!("Failed to serve metrics: {e:#}");
+                }
+            }
+        })
+        .context("Failed to spawn metrics thread")?;
+    Ok(())
+}
+
+fn serve(mut stream: TcpStream, stats: &TickStatsHandle, gauges: &Gauges) -> Result<()> {
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+    let mut request_line = String::new();
+    BufReader::new(&stream).read_line(&mut request_line)?;
+
+    let (status, body) = match request_line.split_whitespace().take(2).collect::<Vec<_>>()[..] {
+        ["GET", "/metrics"] => ("200 OK", render(&stats.snapshot(), gauges)),
+        _ => ("404 Not Found", "not found\n".to_owned()),
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {status}\r\n\
+         Content-Type: text/plain; version=0.0.4\r\n\
+         Content-Length: {}\r\n\
+         Connection: close\r\n\r\n\
+         {body}",
+        body.len()
+    )?;
+    Ok(())
+}
+
+/// Render the metrics in the Prometheus text format.
+fn render(stats: &TickStats, gauges: &Gauges) -> String {
+    let mut out = String::new();
+    let mut metric = |name: &str, kind: &str, help: &str, value: &dyn std::fmt::Display| {
+        let _ = write!(
+            out,
+            "# HELP {name} {help}\n# TYPE {name} {kind}\n{name} {value}\n"
+        );
+    };
+    metric(
+        "server_tps",
+        "gauge",
+        "Measured ticks per second.",
+        &stats.tps,
+    );
+    metric("server_ticks_total", "counter", "Ticks run.", &stats.ticks);
+    metric(
+        "server_slow_ticks_total",
+        "counter",
+        "Ticks that exceeded the tick budget.",
+        &stats.slow_ticks,
+    );
+    metric(
+        "server_skipped_ticks_total",
+        "counter",
+        "Ticks dropped because the server fell behind.",
+        &stats.skipped_ticks,
+    );
+    metric(
+        "server_loaded_chunks",
+        "gauge",
+        "Chunks held in memory.",
+        &gauges.loaded_chunks.load(Ordering::Relaxed),
+    );
+    metric(
+        "server_blocks_changed_total",
+        "counter",
+        "Blocks changed.",
+        &gauges.blocks_changed.load(Ordering::Relaxed),
+    );
+    metric(
+        "server_chunks_generated_total",
+        "counter",
+        "Chunks generated rather than loaded from disk.",
+        &gauges.chunks_generated.load(Ordering::Relaxed),
+    );
+
+    // Histogram buckets are cumulative in the Prometheus format
+    let name = "server_tick_duration_seconds";
+    let _ = writeln!(out, "# HELP {name} Total duration of ticks.");
+    let _ = writeln!(out, "# TYPE {name} histogram");
+    let mut count = 0;
+    for (bound, bucket) in TICK_HISTOGRAM_BOUNDS.iter().zip(stats.histogram.counts) {
+        count += bucket;
+        let _ = writeln!(
+            out,
+            "{name}_bucket{{le=\"{}\"}} {count}",
+            bound.as_secs_f64()
+        );
+    }
+    count += stats.histogram.counts[TICK_HISTOGRAM_BOUNDS.len()];
+    let _ = writeln!(out, "{name}_bucket{{le=\"+Inf\"}} {count}");
+    let _ = writeln!(out, "{name}_sum {}", stats.histogram.sum.as_secs_f64());
+    let _ = writeln!(out, "{name}_count {count}");
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let mut stats = TickStats {
+            ticks: 3,
+            tps: 20.0,
+            ..Default::default()
+        };
+        stats.histogram.counts[0] = 2;
+        stats.histogram.counts[TICK_HISTOGRAM_BOUNDS.len()] = 1;
+        let gauges = Gauges::default();
+        gauges.loaded_chunks.store(81, Ordering::Relaxed);
+
+        let text = render(&stats, &gauges);
+        assert!(text.contains("\nserver_tps 20\n"));
+        assert!(text.contains("\nserver_loaded_chunks 81\n"));
+        assert!(text.contains("\nserver_blocks_changed_total 0\n"));
+        assert!(text.contains("\nserver_tick_duration_seconds_bucket{le=\"0.001\"} 2\n"));
+        assert!(text.contains("\nserver_tick_duration_seconds_bucket{le=\"0.25\"} 2\n"));
+        assert!(text.contains("\nserver_tick_duration_seconds_bucket{le=\"+Inf\"} 3\n"));
+        assert!(text.contains("\nserver_tick_duration_seconds_count 3\n"));
+    }
+}
@@ -0,0 +1,241 @@
+//! Plugins customizing the game rules of the server.
+//!
+//! A plugin implements the hooks of [`Plugin`] it's interested in, and is registered in
+//! [`Plugins::from_args`]. Block hooks run before the change is made, and may deny it by
+//! returning an error.
+
+use anyhow::{bail, Context, Result};
+use tracing::info;
+use wgpu_block_shared::chunk::Block;
+use wgpu_block_shared::structure::Structure;
+
+use crate::args::Args;
+use crate::events::WorldEvent;
+use crate::world::World;
+
+pub trait Plugin: Send {
+    fn name(&self) -> &str;
+
+    /// Called before `block` is placed at `pos`. Returning an error denies the placement.
+    fn on_block_place(&mut self, _pos: (i64, i64, i64), _block: Block) -> Result<()> {
+        Ok(())
+    }
+
+    /// Called before `block` at `pos` is broken or replaced. Returning an error denies it.
+    fn on_block_break(&mut self, _pos: (i64, i64, i64), _block: Block) -> Result<()> {
+        Ok(())
+    }
+
+    /// Called at the end of every game tick, numbered from 0.
+    fn on_tick(&mut self, _world: &mut World, _tick: u64) {}
+
+    /// Called for every world event, after the tick it happened in.
+    fn on_event(&mut self, _world: &mut World, _event: &WorldEvent) {}
+}
+
+/// The registered plugins, whose hooks run in the order they were registered.
+#[derive(Default)]
+pub struct Plugins {
+    plugins: Vec<Box<dyn Plugin>>,
+}
+
+impl Plugins {
+    /// Register the built-in plugins enabled by `args`.
+    pub fn from_args(args: &Args) -> Self {
+        let mut plugins = Self::default();
+        if let Some(radius) = args.spawn_protection {
+            plugins.register(Box::new(SpawnProtection { radius }));
+        }
+        plugins
+    }
+
+    pub fn register(&mut self, plugin: Box<dyn Plugin>) {
+        info!("Registered plugin {}", plugin.name());
+        self.plugins.push(plugin);
+    }
+
+    pub fn on_block_place(&mut self, pos: (i64, i64, i64), block: Block) -> Result<()> {
+        for plugin in &mut self.plugins {
+            plugin
+                .on_block_place(pos, block)
+                .with_context(|| format!("denied by plugin {}", plugin.name()))?;
+        }
+        Ok(())
+    }
+
+    pub fn on_block_break(&mut self, pos: (i64, i64, i64), block: Block) -> Result<()> {
+        for plugin in &mut self.plugins {
+            plugin
+                .on_block_break(pos, block)
+                .with_context(|| format!("denied by plugin {}", plugin.name()))?;
+        }
+        Ok(())
+    }
+
+    pub fn on_tick(&mut self, world: &mut World, tick: u64) {
+        for plugin in &mut self.plugins {
+            plugin.on_tick(world, tick);
+        }
+    }
+
+    pub fn on_event(&mut self, world: &mut World, event: &WorldEvent) {
+        for plugin in &mut self.plugins {
+            plugin.on_event(world, event);
+        }
+    }
+
+    /// Set a block in `world`, if the plugins allow breaking the old block and placing the new
+    /// one.
+    pub fn set_block(
+        &mut self,
+        world: &mut World,
+        pos: (i64, i64, i64),
+        block: Block,
+    ) -> Result<()> {
+        let old = world.block(pos);
+        if old != Block::Empty {
+            self.on_block_break(pos, old)?;
+        }
+        if block != Block::Empty {
+            self.on_block_place(pos, block)?;
+        }
+        world.set_block(pos, block)
+    }
+
+    /// Place `structure` in `world` with its anchor at `pos`, block by block through
+    /// [`Plugins::set_block`]. Blocks that can't be set are skipped. Returns the number of blocks
+    /// placed.
+    pub fn place_structure(
+        &mut self,
+        world: &mut World,
+        structure: &Structure,
+        (x, y, z): (i64, i64, i64),
+    ) -> usize {
+        structure
+            .blocks()
+            .filter(|&((dx, dy, dz), block)| {
+                self.set_block(world, (x + dx, y + dy, z + dz), block)
+                    .is_ok()
+            })
+            .count()
+    }
+}
+
+/// Keeps blocks within `radius` blocks of the origin, horizontally, from being changed.
+struct SpawnProtection {
+    radius: u32,
+}
+
+impl SpawnProtection {
+    fn check(&self, (x, _, z): (i64, i64, i64)) -> Result<()> {
+        let radius = self.radius as u64;
+        if x.unsigned_abs() < radius && z.unsigned_abs() < radius {
+            bail!("({x}, {z}) is within {radius} blocks of spawn");
+        }
+        Ok(())
+    }
+}
+
+impl Plugin for SpawnProtection {
+    fn name(&self) -> &str {
+        "spawn-protection"
+    }
+
+    fn on_block_place(&mut self, pos: (i64, i64, i64), _block: Block) -> Result<()> {
+        self.check(pos)
+    }
+
+    fn on_block_break(&mut self, pos: (i64, i64, i64), _block: Block) -> Result<()> {
+        self.check(pos)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::fs;
+
+    /// Allows breaking but not placing stone, and counts ticks.
+    #[derive(Default)]
+    struct NoStone {
+        ticks: u64,
+    }
+
+    impl Plugin for NoStone {
+        fn name(&self) -> &str {
+            "no-stone"
+        }
+
+        fn on_block_place(&mut self, _pos: (i64, i64, i64), block: Block) -> Result<()> {
+            if block == Block::Stone {
+                bail!("no stone");
+            }
+            Ok(())
+        }
+
+        fn on_tick(&mut self, _world: &mut World, tick: u64) {
+            assert_eq!(tick, self.ticks);
+            self.ticks += 1;
+        }
+    }
+
+    #[test]
+    fn test_hooks() {
+        let dir = std::env::temp_dir().join(format!("wgpu-block-plugin-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let mut world = World::open_or_create(&dir, Some(0), None).unwrap();
+
+        let mut plugins = Plugins::default();
+        plugins.register(Box::new(NoStone::default()));
+        plugins.register(Box::new(SpawnProtection { radius: 4 }));
+        for tick in 0..3 {
+            plugins.on_tick(&mut world, tick);
+        }
+
+        assert!(plugins
+            .set_block(&mut world, (10, 200, 0), Block::Stone)
+            .is_err());
+        assert_eq!(world.block((10, 200, 0)), Block::Empty);
+        plugins
+            .set_block(&mut world, (10, 200, 0), Block::Dirt)
+            .unwrap();
+        plugins
+            .set_block(&mut world, (10, 200, 0), Block::Empty)
+            .unwrap();
+
+        // Spawn protection applies to both placing and breaking
+        assert!(plugins
+            .set_block(&mut world, (3, 200, -3), Block::Dirt)
+            .is_err());
+        world.set_block((3, 200, -3), Block::Dirt).unwrap();
+        assert!(plugins
+            .set_block(&mut world, (3, 200, -3), Block::Empty)
+            .is_err());
+        assert_eq!(world.block((3, 200, -3)), Block::Dirt);
+        assert!(plugins.on_block_break((4, 200, 0), Block::Dirt).is_ok());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_place_structure() {
+        let dir =
+            std::env::temp_dir().join(format!("wgpu-block-place-structure-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let mut world = World::open_or_create(&dir, Some(0), None).unwrap();
+        let mut plugins = Plugins::default();
+        plugins.register(Box::new(SpawnProtection { radius: 4 }));
+
+        // A row of dirt along x, crossing the border of spawn protection
+        let structure = Structure::parse("palette\nD dirt\nlayer\nDDDD").unwrap();
+        assert_eq!(
+            plugins.place_structure(&mut world, &structure, (2, 200, 0)),
+            2
+        );
+        assert_eq!(world.block((3, 200, 0)), Block::Empty);
+        assert_eq!(world.block((4, 200, 0)), Block::Dirt);
+        assert_eq!(world.block((5, 200, 0)), Block::Dirt);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,325 @@
+//! The region files chunks are saved in.
+//!
+//! A region file holds the chunks of a 32x32 chunk area. It starts with a header of the magic
+//! bytes `WBRG` and the region format version, followed by the offset and length in the file of
+//! each of the 1024 chunks' data, both zero if the chunk isn't saved. All numbers are
+//! little-endian `u32`s.
+//!
+//! The data of a chunk is its chunk format version followed by the zlib-compressed
+//! [`Chunk::to_bytes`]. Chunks saved in an older format version are upgraded when loaded, see
+//! [`migrate_chunk`], so that chunks only need to be rewritten once they change.
+
+use std::fs;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, ensure, Context, Result};
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use hashbrown::HashMap;
+use tracing::error;
+use wgpu_block_shared::chunk::Chunk;
+
+/// The width of a region, in chunks.
+const REGION_SIZE: i64 = 32;
+
+const REGION_CHUNKS: usize = (REGION_SIZE * REGION_SIZE) as usize;
+
+const MAGIC: &[u8; 4] = b"WBRG";
+
+/// The version of the region file layout.
+const REGION_FORMAT_VERSION: u32 = 1;
+
+/// The version of the chunk data layout, to be bumped along with a new case in [`migrate_chunk`].
+const CHUNK_FORMAT_VERSION: u32 = 2;
+
+const HEADER_LEN: usize = 8 + REGION_CHUNKS * 8;
+
+/// The chunks of one region, kept compressed.
+#[derive(Debug, Clone)]
+struct Region {
+    chunks: Vec<Option<Vec<u8>>>,
+}
+
+impl Default for Region {
+    fn default() -> Self {
+        Self {
+            chunks: vec![None; REGION_CHUNKS],
+        }
+    }
+}
+
+impl Region {
+    fn parse(bytes: &[u8]) -> Result<Self> {
+        ensure!(
+            bytes.len() >= HEADER_LEN && &bytes[..4] == MAGIC,
+            "not a region file"
+        );
+        let version = read_u32(bytes, 4);
+        ensure!(
+            version == REGION_FORMAT_VERSION,
+            "unsupported region format version {version}"
+        );
+
+        let mut region = Self::default();
+        for (index, chunk) in region.chunks.iter_mut().enumerate() {
+            let offset = read_u32(bytes, 8 + index * 8) as usize;
+            let len = read_u32(bytes, 12 + index * 8) as usize;
+            if len == 0 {
+                continue;
+            }
+            let data = bytes
+                .get(offset..offset + len)
+                .with_context(|| format!("chunk {index} is out of bounds"))?;
+            *chunk = Some(data.to_owned());
+        }
+        Ok(region)
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut header = Vec::with_capacity(HEADER_LEN);
+        header.extend_from_slice(MAGIC);
+        header.extend_from_slice(&REGION_FORMAT_VERSION.to_le_bytes());
+        let mut body = vec![];
+        for chunk in &self.chunks {
+            let (offset, len) = match chunk {
+                Some(data) => {
+                    let offset = HEADER_LEN + body.len();
+                    body.extend_from_slice(data);
+                    (offset as u32, data.len() as u32)
+                }
+                None => (0, 0),
+            };
+            header.extend_from_slice(&offset.to_le_bytes());
+            header.extend_from_slice(&len.to_le_bytes());
+        }
+        header.extend(body);
+        header
+    }
+
+    fn chunk(&self, coords: (i64, i64)) -> Result<Option<Chunk>> {
+        self.chunks[local_index(coords)]
+            .as_deref()
+            .map(decode_chunk)
+            .transpose()
+    }
+
+    fn set_chunk(&mut self, coords: (i64, i64), chunk: &Chunk) -> Result<()> {
+        self.chunks[local_index(coords)] = Some(encode_chunk(chunk)?);
+        Ok(())
+    }
+}
+
+/// Loads and saves chunks in the region files of a directory.
+pub struct RegionStore {
+    dir: PathBuf,
+    /// Regions read so far, so that loading a chunk doesn't read its whole region file again.
+    regions: HashMap<(i64, i64), Region>,
+}
+
+impl RegionStore {
+    pub fn new(dir: impl AsRef<Path>) -> Self {
+        Self {
+            dir: dir.as_ref().to_owned(),
+            regions: HashMap::new(),
+        }
+    }
+
+    /// Load the chunk at chunk coordinates `coords`, or `None` if it was never saved.
+    pub fn load_chunk(&mut self, coords: (i64, i64)) -> Result<Option<Chunk>> {
+        self.region(region_coords(coords))?.chunk(coords)
+    }
+
+    /// Save `chunks`, rewriting the region files they are in.
+    ///
+    /// A region that fails to load or write doesn't stop the others from being saved, an error
+    /// listing the failed regions is returned once the rest are written.
+    pub fn save_chunks<'a>(
+        &mut self,
+        chunks: impl IntoIterator<Item = ((i64, i64), &'a Chunk)>,
+    ) -> Result<()> {
+        let mut changed = vec![];
+        let mut failed = vec![];
+        for (coords, chunk) in chunks {
+            let region_coords = region_coords(coords);
+            if failed.contains(&region_coords) {
+                continue;
+            }
+            match self.region(region_coords) {
+                Ok(region) => region.set_chunk(coords, chunk)?,
+                Err(e) => {
+                    error!("{e:#}, not saving its chunks");
+                    failed.push(region_coords);
+                    continue;
+                }
+            }
+            if !changed.contains(&region_coords) {
+                changed.push(region_coords);
+            }
+        }
+
+        if !changed.is_empty() {
+            fs::create_dir_all(&self.dir)
+                .with_context(|| format!("Failed to create {}", self.dir.display()))?;
+        }
+        for region_coords in changed {
+            let path = self.path(region_coords);
+            // Write to a temporary file first, so that a crash can't leave half a region behind
+            let temp_path = path.with_extension("tmp");
+            let written = fs::write(&temp_path, self.regions[&region_coords].to_bytes())
+                .and_then(|()| fs::rename(&temp_path, &path));
+            if let Err(e) = written {
+                error!("Failed to write {}: {e}", path.display());
+                failed.push(region_coords);
+            }
+        }
+
+        if !failed.is_empty() {
+            let paths: Vec<_> = failed
+                .iter()
+                .map(|&region_coords| self.path(region_coords).display().to_string())
+                .collect();
+            bail!("Failed to save the chunks of {}", paths.join(", "));
+        }
+        Ok(())
+    }
+
+    fn region(&mut self, region_coords: (i64, i64)) -> Result<&mut Region> {
+        if !self.regions.contains_key(&region_coords) {
+            let path = self.path(region_coords);
+            let region = if path.exists() {
+                let bytes = fs::read(&path)
+                    .with_context(|| format!("Failed to read {}", path.display()))?;
+                Region::parse(&bytes).with_context(|| format!("Bad region {}", path.display()))?
+            } else {
+                Region::default()
+            };
+            self.regions.insert(region_coords, region);
+        }
+        Ok(self.regions.get_mut(&region_coords).unwrap())
+    }
+
+    fn path(&self, (rx, rz): (i64, i64)) -> PathBuf {
+        self.dir.join(format!("r.{rx}.{rz}.bin"))
+    }
+}
+
+fn region_coords((cx, cz): (i64, i64)) -> (i64, i64) {
+    (cx.div_euclid(REGION_SIZE), cz.div_euclid(REGION_SIZE))
+}
+
+fn local_index((cx, cz): (i64, i64)) -> usize {
+    (cx.rem_euclid(REGION_SIZE) * REGION_SIZE + cz.rem_euclid(REGION_SIZE)) as usize
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+}
+
+fn encode_chunk(chunk: &Chunk) -> Result<Vec<u8>> {
+    let mut encoder = ZlibEncoder::new(
+        CHUNK_FORMAT_VERSION.to_le_bytes().to_vec(),
+        Compression::default(),
+    );
+    encoder.write_all(&chunk.to_bytes())?;
+    Ok(encoder.finish()?)
+}
+
+fn decode_chunk(data: &[u8]) -> Result<Chunk> {
+    ensure!(data.len() >= 4, "chunk data is truncated");
+    let version = read_u32(data, 0);
+    let mut bytes = vec![];
+    ZlibDecoder::new(&data[4..])
+        .read_to_end(&mut bytes)
+        .context("Failed to decompress chunk")?;
+    let bytes = migrate_chunk(version, bytes)?;
+    Chunk::from_bytes(&bytes).context("malformed chunk data")
+}
+
+/// Upgrade the uncompressed data of a chunk saved in format `version` to the current format.
+fn migrate_chunk(mut version: u32, mut bytes: Vec<u8>) -> Result<Vec<u8>> {
+    ensure!(
+        version <= CHUNK_FORMAT_VERSION,
+        "chunk format version {version} is newer than this server"
+    );
+    while version < CHUNK_FORMAT_VERSION {
+        match version {
+            // Version 1 had no block entities
+            1 => bytes.extend_from_slice(&0u32.to_le_bytes()),
+            _ => bail!("unknown chunk format version {version}"),
+        }
+        version += 1;
+    }
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use wgpu_block_shared::chunk::{Block, BlockEntity};
+
+    #[test]
+    fn test_region_round_trip() {
+        let mut chunk = Chunk::default();
+        for (i, block) in Block::ALL.into_iter().enumerate() {
+            chunk.set((i, 2 * i, 15), block);
+        }
+        chunk.set_block_entity((3, 255, 0), BlockEntity::Text("hello".to_owned()));
+
+        let mut region = Region::default();
+        region.set_chunk((-1, 33), &chunk).unwrap();
+        let region = Region::parse(&region.to_bytes()).unwrap();
+        let loaded = region.chunk((-1, 33)).unwrap().unwrap();
+        assert_eq!(loaded.to_bytes(), chunk.to_bytes());
+        assert_eq!(
+            loaded.block_entity((3, 255, 0)),
+            Some(&BlockEntity::Text("hello".to_owned()))
+        );
+        assert!(region.chunk((-1, 32)).unwrap().is_none());
+
+        assert!(Region::parse(b"WBRG").is_err());
+        assert!(migrate_chunk(CHUNK_FORMAT_VERSION + 1, vec![]).is_err());
+        assert!(migrate_chunk(0, vec![]).is_err());
+    }
+
+    #[test]
+    fn test_migrate_chunk() {
+        // Version 1 chunks are only blocks
+        let mut chunk = Chunk::default();
+        chunk.set((1, 2, 3), Block::Stone);
+        let blocks = chunk.to_bytes()[..16 * 16 * 256].to_vec();
+        let migrated = Chunk::from_bytes(&migrate_chunk(1, blocks).unwrap()).unwrap();
+        assert_eq!(migrated.get((1, 2, 3)), Block::Stone);
+    }
+
+    #[test]
+    fn test_region_coords() {
+        assert_eq!(region_coords((31, -1)), (0, -1));
+        assert_eq!(region_coords((32, -32)), (1, -1));
+        assert_eq!(region_coords((-33, 0)), (-2, 0));
+        assert_eq!(local_index((-1, -1)), REGION_CHUNKS - 1);
+        assert_eq!(local_index((32, 1)), 1);
+    }
+
+    #[test]
+    fn test_save_past_bad_region() {
+        let dir = std::env::temp_dir().join(format!("wgpu-block-region-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("r.0.0.bin"), b"not a region").unwrap();
+
+        let mut chunk = Chunk::default();
+        chunk.set((1, 2, 3), Block::Stone);
+        let mut store = RegionStore::new(&dir);
+        let err = store
+            .save_chunks([((0, 0), &chunk), ((32, 0), &chunk), ((1, 0), &chunk)])
+            .unwrap_err();
+        assert!(format!("{err}").contains("r.0.0.bin"));
+
+        // The good region was still written, the bad one was left alone
+        let loaded = RegionStore::new(&dir).load_chunk((32, 0)).unwrap().unwrap();
+        assert_eq!(loaded.get((1, 2, 3)), Block::Stone);
+        assert_eq!(fs::read(dir.join("r.0.0.bin")).unwrap(), b"not a region");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
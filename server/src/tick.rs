@@ -0,0 +1,396 @@
+//! Fixed-timestep tick scheduling with per-phase timing metrics.
+
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use tracing::warn;
+
+/// The number of ticks that may be run back-to-back to catch up after a stall. Anything beyond
+/// this is dropped, so that one long hiccup doesn't cause a spiral of ever-longer catch-ups.
+const MAX_CATCH_UP_TICKS: u32 = 10;
+
+/// Smoothing factor of the exponential moving averages kept in [`TickStats`].
+const AVERAGE_WEIGHT: f64 = 0.05;
+
+/// The phases a single server tick is divided into.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TickPhase {
+    /// Handling of messages received from clients.
+    Inbound,
+    /// Generating and sending chunks to clients.
+    ChunkStreaming,
+    /// Updating the game state itself.
+    GameTick,
+}
+
+impl TickPhase {
+    pub const ALL: [TickPhase; 3] = [
+        TickPhase::Inbound,
+        TickPhase::ChunkStreaming,
+        TickPhase::GameTick,
+    ];
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+/// Wall-clock durations spent in each phase of a tick.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TickTimings {
+    phases: [Duration; TickPhase::ALL.len()],
+    total: Duration,
+}
+
+impl TickTimings {
+    pub fn phase(&self, phase: TickPhase) -> Duration {
+        self.phases[phase.index()]
+    }
+
+    pub fn total(&self) -> Duration {
+        self.total
+    }
+
+    fn blend(&mut self, sample: &TickTimings, weight: f64) {
+        let blend = |avg: Duration, new: Duration| {
+            Duration::from_secs_f64(avg.as_secs_f64() * (1.0 - weight) + new.as_secs_f64() * weight)
+        };
+        for (avg, new) in self.phases.iter_mut().zip(sample.phases) {
+            *avg = blend(*avg, new);
+        }
+        self.total = blend(self.total, sample.total);
+    }
+}
+
+/// Upper bounds of the buckets of [`TickHistogram`].
+pub const TICK_HISTOGRAM_BOUNDS: [Duration; 8] = [
+    Duration::from_millis(1),
+    Duration::from_millis(2),
+    Duration::from_millis(5),
+    Duration::from_millis(10),
+    Duration::from_millis(25),
+    Duration::from_millis(50),
+    Duration::from_millis(100),
+    Duration::from_millis(250),
+];
+
+/// A histogram of total tick durations.
+#[derive(Debug, Clone, Default)]
+pub struct TickHistogram {
+    /// The number of ticks in each bucket of [`TICK_HISTOGRAM_BOUNDS`], followed by the number of
+    /// ticks longer than every bound.
+    pub counts: [u64; TICK_HISTOGRAM_BOUNDS.len() + 1],
+    /// The sum of all tick durations.
+    pub sum: Duration,
+}
+
+impl TickHistogram {
+    fn record(&mut self, duration: Duration) {
+        let bucket = TICK_HISTOGRAM_BOUNDS
+            .iter()
+            .position(|bound| duration <= *bound)
+            .unwrap_or(TICK_HISTOGRAM_BOUNDS.len());
+        self.counts[bucket] += 1;
+        self.sum += duration;
+    }
+}
+
+/// Aggregated tick metrics, readable by other subsystems through [`TickStatsHandle`].
+#[derive(Debug, Clone, Default)]
+pub struct TickStats {
+    /// The number of ticks run so far.
+    pub ticks: u64,
+    /// The number of ticks that took longer than the tick budget.
+    pub slow_ticks: u64,
+    /// The number of ticks dropped because the server fell too far behind.
+    pub skipped_ticks: u64,
+    /// Timings of the most recent tick.
+    pub last: TickTimings,
+    /// Exponential moving average of the tick timings.
+    pub average: TickTimings,
+    /// The slowest tick observed so far.
+    pub max: TickTimings,
+    /// Measured ticks per second, as an exponential moving average.
+    pub tps: f64,
+    /// Total tick durations, counted per bucket of [`TICK_HISTOGRAM_BOUNDS`] plus an overflow
+    /// bucket. Each tick is counted in a single bucket, so the counts aren't cumulative.
+    pub histogram: TickHistogram,
+}
+
+/// A cheaply clonable handle to the [`TickStats`] of a running [`TickScheduler`].
+#[derive(Debug, Clone, Default)]
+pub struct TickStatsHandle(Arc<Mutex<TickStats>>);
+
+impl TickStatsHandle {
+    /// Get a copy of the current stats.
+    pub fn snapshot(&self) -> TickStats {
+        self.0.lock().unwrap().clone()
+    }
+}
+
+/// The tick in progress, for inspecting a tick that seems stuck from another thread.
+#[derive(Debug, Clone, Copy)]
+pub struct TickMarker {
+    /// The number of the tick, counting from 1.
+    pub tick: u64,
+    pub started: Instant,
+    /// The phase the tick is in, or `None` if it's between phases.
+    pub phase: Option<TickPhase>,
+}
+
+/// A cheaply clonable handle to the [`TickMarker`] of a running [`TickScheduler`].
+#[derive(Debug, Clone, Default)]
+pub struct TickMarkerHandle(Arc<Mutex<Option<TickMarker>>>);
+
+impl TickMarkerHandle {
+    /// Get the tick in progress, or `None` between ticks.
+    pub fn current(&self) -> Option<TickMarker> {
+        *self.0.lock().unwrap()
+    }
+
+    fn set(&self, marker: Option<TickMarker>) {
+        *self.0.lock().unwrap() = marker;
+    }
+
+    fn set_phase(&self, phase: Option<TickPhase>) {
+        if let Some(marker) = self.0.lock().unwrap().as_mut() {
+            marker.phase = phase;
+        }
+    }
+}
+
+/// Schedules ticks at a fixed rate using a time accumulator.
+pub struct TickScheduler {
+    tick_duration: Duration,
+    budget: Duration,
+    accumulator: Duration,
+    last_instant: Instant,
+    last_tick_start: Option<Instant>,
+    stats: TickStatsHandle,
+    marker: TickMarkerHandle,
+}
+
+impl TickScheduler {
+    /// Create a scheduler running `tps` ticks per second, with the whole tick duration as the
+    /// budget of a single tick.
+    pub fn new(tps: u32) -> Self {
+        let tick_duration = Duration::from_secs(1) / tps;
+        Self {
+            tick_duration,
+            budget: tick_duration,
+            accumulator: Duration::ZERO,
+            last_instant: Instant::now(),
+            last_tick_start: None,
+            stats: TickStatsHandle::default(),
+            marker: TickMarkerHandle::default(),
+        }
+    }
+
+    pub fn stats(&self) -> TickStatsHandle {
+        self.stats.clone()
+    }
+
+    pub fn marker(&self) -> TickMarkerHandle {
+        self.marker.clone()
+    }
+
+    /// Sleep until at least one tick is due, and return the number of ticks to run.
+    pub fn wait(&mut self) -> u32 {
+        self.wait_with(Instant::now, spin_sleep::sleep)
+    }
+
+    /// [`TickScheduler::wait`] with the clock read by `now` and slept on by `sleep`.
+    fn wait_with(&mut self, now: impl Fn() -> Instant, sleep: impl FnOnce(Duration)) -> u32 {
+        let due = self.advance_to(now());
+        if due > 0 {
+            return due;
+        }
+
+        sleep(self.tick_duration - self.accumulator);
+        self.advance_to(now())
+    }
+
+    /// Advance by the time elapsed since the last call, so that no time is counted twice.
+    fn advance_to(&mut self, now: Instant) -> u32 {
+        let elapsed = now.saturating_duration_since(self.last_instant);
+        self.last_instant = now;
+        self.advance(elapsed)
+    }
+
+    /// Add `elapsed` to the accumulator and take out as many whole ticks as possible.
+    fn advance(&mut self, elapsed: Duration) -> u32 {
+        self.accumulator += elapsed;
+        let mut due = 0;
+        while self.accumulator >= self.tick_duration {
+            self.accumulator -= self.tick_duration;
+            due += 1;
+        }
+
+        if due > MAX_CATCH_UP_TICKS {
+            let skipped = due - MAX_CATCH_UP_TICKS;
+            warn!("Server is running behind, skipping {skipped} ticks");
+            self.stats.0.lock().unwrap().skipped_ticks += skipped as u64;
+            due = MAX_CATCH_UP_TICKS;
+        }
+        due
+    }
+
+    /// Run a single tick, with `f` measuring its phases through the given [`TickTimer`].
+    pub fn run_tick(&mut self, f: impl FnOnce(&mut TickTimer)) {
+        let start = Instant::now();
+        let tick = self.stats.snapshot().ticks + 1;
+        self.marker.set(Some(TickMarker {
+            tick,
+            started: start,
+            phase: None,
+        }));
+        let mut timer = TickTimer {
+            timings: TickTimings::default(),
+            marker: self.marker.clone(),
+        };
+        f(&mut timer);
+        timer.timings.total = start.elapsed();
+        self.marker.set(None);
+
+        let interval = self.last_tick_start.map(|last| start - last);
+        self.last_tick_start = Some(start);
+        self.record(timer.timings, interval);
+    }
+
+    fn record(&mut self, timings: TickTimings, interval: Option<Duration>) {
+        let mut stats = self.stats.0.lock().unwrap();
+        stats.ticks += 1;
+        stats.last = timings;
+        stats.histogram.record(timings.total);
+
+        if stats.ticks == 1 {
+            stats.average = timings;
+        } else {
+            stats.average.blend(&timings, AVERAGE_WEIGHT);
+        }
+
+        if timings.total > stats.max.total {
+            stats.max = timings;
+        }
+
+        if let Some(interval) = interval.filter(|i| !i.is_zero()) {
+            let tps = 1.0 / interval.as_secs_f64();
+            stats.tps = stats.tps * (1.0 - AVERAGE_WEIGHT) + tps * AVERAGE_WEIGHT;
+        }
+
+        if timings.total > self.budget {
+            stats.slow_ticks += 1;
+            let [inbound, streaming, game] = TickPhase::ALL.map(|p| timings.phase(p));
+            warn!(
+                ?inbound,
+                ?streaming,
+                ?game,
+                "Tick {} took {:?}, exceeding the budget of {:?}",
+                stats.ticks,
+                timings.total,
+                self.budget
+            );
+        }
+    }
+}
+
+/// Measures the phases of a tick in progress.
+pub struct TickTimer {
+    timings: TickTimings,
+    marker: TickMarkerHandle,
+}
+
+impl TickTimer {
+    /// Run `f` and account its duration to `phase`.
+    pub fn phase<T>(&mut self, phase: TickPhase, f: impl FnOnce() -> T) -> T {
+        self.marker.set_phase(Some(phase));
+        let start = Instant::now();
+        let out = f();
+        self.timings.phases[phase.index()] += start.elapsed();
+        self.marker.set_phase(None);
+        out
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_advance_accumulates_partial_ticks() {
+        let mut scheduler = TickScheduler::new(20);
+        assert_eq!(scheduler.advance(Duration::from_millis(30)), 0);
+        assert_eq!(scheduler.advance(Duration::from_millis(30)), 1);
+        assert_eq!(scheduler.advance(Duration::from_millis(90)), 2);
+        assert_eq!(scheduler.accumulator, Duration::from_millis(0));
+    }
+
+    #[test]
+    fn test_wait_keeps_tick_rate() {
+        let mut scheduler = TickScheduler::new(20);
+        let start = scheduler.last_instant;
+        let clock = std::cell::Cell::new(start);
+        let mut ticks = 0;
+        while clock.get() - start < Duration::from_secs(1) {
+            let due =
+                scheduler.wait_with(|| clock.get(), |duration| clock.set(clock.get() + duration));
+            // Every tick works for 40% of its duration
+            clock.set(clock.get() + Duration::from_millis(20) * due);
+            ticks += due;
+        }
+        assert_eq!(ticks, 20);
+    }
+
+    #[test]
+    fn test_advance_caps_catch_up() {
+        let mut scheduler = TickScheduler::new(20);
+        assert_eq!(
+            scheduler.advance(Duration::from_secs(10)),
+            MAX_CATCH_UP_TICKS
+        );
+        assert_eq!(
+            scheduler.stats().snapshot().skipped_ticks,
+            200 - MAX_CATCH_UP_TICKS as u64
+        );
+    }
+
+    #[test]
+    fn test_histogram() {
+        let mut histogram = TickHistogram::default();
+        histogram.record(Duration::from_millis(1));
+        histogram.record(Duration::from_millis(30));
+        histogram.record(Duration::from_secs(1));
+        assert_eq!(histogram.counts, [1, 0, 0, 0, 0, 1, 0, 0, 1]);
+        assert_eq!(histogram.sum, Duration::from_millis(1031));
+    }
+
+    #[test]
+    fn test_run_tick_sets_marker() {
+        let mut scheduler = TickScheduler::new(20);
+        let marker = scheduler.marker();
+        scheduler.run_tick(|timer| {
+            let current = marker.current().unwrap();
+            assert_eq!((current.tick, current.phase), (1, None));
+            timer.phase(TickPhase::Inbound, || {
+                assert_eq!(marker.current().unwrap().phase, Some(TickPhase::Inbound));
+            });
+        });
+        assert!(marker.current().is_none());
+    }
+
+    #[test]
+    fn test_run_tick_records_phases() {
+        let mut scheduler = TickScheduler::new(20);
+        scheduler.run_tick(|timer| {
+            timer.phase(TickPhase::GameTick, || {
+                std::thread::sleep(Duration::from_millis(2))
+            });
+        });
+
+        let stats = scheduler.stats().snapshot();
+        assert_eq!(stats.ticks, 1);
+        assert!(stats.last.phase(TickPhase::GameTick) >= Duration::from_millis(2));
+        assert_eq!(stats.last.phase(TickPhase::Inbound), Duration::ZERO);
+        assert!(stats.last.total() >= stats.last.phase(TickPhase::GameTick));
+    }
+}
@@ -0,0 +1,111 @@
+//! A watchdog thread reporting ticks that take far too long, e.g. because of a deadlock or an
+//! endless loop.
+//!
+//! The standard library can't capture the stack of another thread, so the report is limited to
+//! the tick and the phase it's stuck in. Attach a debugger to the process for full stacks.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use tracing::error;
+
+use crate::tick::{TickMarker, TickMarkerHandle};
+
+/// How often the watchdog checks the tick in progress.
+const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WatchdogConfig {
+    /// How long a tick may run before it's reported as stuck.
+    pub threshold: Duration,
+    /// Whether to stop the server, saving the world, once a stuck tick finishes.
+    pub stop_on_stuck: bool,
+}
+
+impl Default for WatchdogConfig {
+    fn default() -> Self {
+        Self {
+            threshold: Duration::from_secs(10),
+            stop_on_stuck: false,
+        }
+    }
+}
+
+pub struct Watchdog {
+    stop_requested: Arc<AtomicBool>,
+}
+
+impl Watchdog {
+    /// Spawn the watchdog thread, watching the ticks marked in `marker`.
+    pub fn spawn(marker: TickMarkerHandle, config: WatchdogConfig) -> Self {
+        let stop_requested = Arc::new(AtomicBool::new(false));
+        let stop = stop_requested.clone();
+        thread::Builder::new()
+            .name("watchdog".to_owned())
+            .spawn(move || {
+                let mut reported = None;
+                loop {
+                    thread::sleep(CHECK_INTERVAL);
+                    let current = marker.current();
+                    let stuck = match current {
+                        Some(current) if reported != Some(current.tick) => current,
+                        _ => continue,
+                    };
+                    let elapsed = match stuck_for(stuck, Instant::now(), config.threshold) {
+                        Some(elapsed) => elapsed,
+                        None => continue,
+                    };
+
+                    reported = Some(stuck.tick);
+                    error!(
+                        tick = stuck.tick,
+                        phase = ?stuck.phase,
+                        "Tick has been running for {elapsed:?}, the server may be stuck"
+                    );
+                    if config.stop_on_stuck {
+                        error!("The server will stop once the tick finishes");
+                        stop.store(true, Ordering::Relaxed);
+                    }
+                }
+            })
+            .expect("Failed to spawn watchdog thread");
+        Self { stop_requested }
+    }
+
+    /// Whether a stuck tick was detected and the server should stop.
+    pub fn stop_requested(&self) -> bool {
+        self.stop_requested.load(Ordering::Relaxed)
+    }
+}
+
+/// Get how long the tick has been running, if that's longer than `threshold`.
+fn stuck_for(marker: TickMarker, now: Instant, threshold: Duration) -> Option<Duration> {
+    let elapsed = now.saturating_duration_since(marker.started);
+    (elapsed > threshold).then_some(elapsed)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_stuck_for() {
+        let started = Instant::now();
+        let marker = TickMarker {
+            tick: 1,
+            started,
+            phase: None,
+        };
+        let threshold = Duration::from_secs(10);
+        assert_eq!(
+            stuck_for(marker, started + Duration::from_secs(5), threshold),
+            None
+        );
+        assert_eq!(
+            stuck_for(marker, started + Duration::from_secs(11), threshold),
+            Some(Duration::from_secs(11))
+        );
+    }
+}
@@ -0,0 +1,505 @@
+//! The world the server simulates, and the metadata saved along with it.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{bail, Context, Result};
+use hashbrown::{HashMap, HashSet};
+use itertools::iproduct;
+use tracing::{error, info, warn};
+use wgpu_block_shared::chunk::{Block, Chunk};
+use wgpu_block_shared::raycast::{raycast, RaycastHit};
+use wgpu_block_shared::structure::Structure;
+use wgpu_block_shared::tag::BlockTag;
+use wgpu_block_shared::worldgen::{TerrainGenerator, WorldGenerator};
+
+use crate::archive;
+use crate::events::WorldEvent;
+use crate::region::RegionStore;
+
+/// The name of the metadata file in the world directory.
+const LEVEL_FILE: &str = "level.txt";
+
+/// The name of the directory of region files in the world directory.
+const REGION_DIR: &str = "region";
+
+/// The name of the directory of structure templates in the world directory, see
+/// [`World::load_structure`].
+const STRUCTURE_DIR: &str = "structures";
+
+/// Metadata of a world, saved as `key = value` lines in the level file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LevelMeta {
+    /// The seed of every noise source in the world generator.
+    pub seed: u32,
+    /// The largest `|cx|` and `|cz|` of chunks inside the world, if the world is bounded.
+    pub world_border: Option<u32>,
+}
+
+impl LevelMeta {
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut seed = None;
+        let mut world_border = None;
+        for (line_no, line) in text
+            .lines()
+            .enumerate()
+            .map(|(i, line)| (i + 1, line.trim()))
+        {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (key, value) = line
+                .split_once('=')
+                .with_context(|| format!("line {line_no}: expected `key = value`"))?;
+            match key.trim() {
+                "seed" => {
+                    let value = value.trim();
+                    seed = Some(
+                        value
+                            .parse()
+                            .with_context(|| format!("line {line_no}: bad seed `{value}`"))?,
+                    );
+                }
+                "world_border" => {
+                    let value = value.trim();
+                    world_border =
+                        Some(value.parse().with_context(|| {
+                            format!("line {line_no}: bad world border `{value}`")
+                        })?);
+                }
+                key => bail!("line {line_no}: unknown key `{key}`"),
+            }
+        }
+        Ok(Self {
+            seed: seed.context("missing seed")?,
+            world_border,
+        })
+    }
+
+    pub fn to_text(self) -> String {
+        let mut text = format!("seed = {}\n", self.seed);
+        if let Some(world_border) = self.world_border {
+            text += &format!("world_border = {world_border}\n");
+        }
+        text
+    }
+}
+
+/// The radius, in chunks, of the area around the origin generated when the server starts.
+const SPAWN_RADIUS: i64 = 4;
+
+/// The distance from the origin, in blocks, within which the spawn point is searched.
+const SPAWN_SEARCH_RADIUS: i64 = 16;
+
+pub struct World {
+    dir: PathBuf,
+    meta: LevelMeta,
+    generator: Box<dyn WorldGenerator>,
+    chunks: HashMap<(i64, i64), Chunk>,
+    regions: RegionStore,
+    /// Chunks changed since they were last saved. Unchanged chunks are never saved, as the
+    /// generator reproduces them from the seed.
+    modified: HashSet<(i64, i64)>,
+    /// Events recorded since they were last taken, see [`World::take_events`].
+    events: Vec<WorldEvent>,
+}
+
+impl World {
+    /// Open the world in `dir`, or create it with `seed` (or a random seed) if it doesn't exist.
+    ///
+    /// A `world_border` replaces the one saved with the world.
+    pub fn open_or_create(
+        dir: impl AsRef<Path>,
+        seed: Option<u32>,
+        world_border: Option<u32>,
+    ) -> Result<Self> {
+        let dir = dir.as_ref();
+        let level_path = dir.join(LEVEL_FILE);
+
+        let meta = if level_path.exists() {
+            let text = fs::read_to_string(&level_path)
+                .with_context(|| format!("Failed to read {}", level_path.display()))?;
+            let mut meta = LevelMeta::parse(&text)
+                .with_context(|| format!("Failed to parse {}", level_path.display()))?;
+            if matches!(seed, Some(seed) if seed != meta.seed) {
+                warn!(
+                    "Ignoring the seed argument, the world already has seed {}",
+                    meta.seed
+                );
+            }
+            info!("Opened world {} with seed {}", dir.display(), meta.seed);
+            if world_border.is_some() {
+                meta.world_border = world_border;
+            }
+            meta
+        } else {
+            let meta = LevelMeta {
+                seed: seed.unwrap_or_else(random_seed),
+                world_border,
+            };
+            fs::create_dir_all(dir)
+                .with_context(|| format!("Failed to create {}", dir.display()))?;
+            fs::write(&level_path, meta.to_text())
+                .with_context(|| format!("Failed to write {}", level_path.display()))?;
+            info!("Created world {} with seed {}", dir.display(), meta.seed);
+            meta
+        };
+
+        if let Some(world_border) = meta.world_border {
+            info!("The world border is {world_border} chunks from the origin");
+        }
+        Ok(Self {
+            dir: dir.to_owned(),
+            meta,
+            generator: Box::new(TerrainGenerator::new(meta.seed)),
+            chunks: HashMap::new(),
+            regions: RegionStore::new(dir.join(REGION_DIR)),
+            modified: HashSet::new(),
+            events: vec![],
+        })
+    }
+
+    /// Generate the chunks around the origin, so that they are ready when players join.
+    pub fn generate_spawn_area(&mut self) {
+        for (cx, cz) in iproduct!(-SPAWN_RADIUS..SPAWN_RADIUS, -SPAWN_RADIUS..SPAWN_RADIUS) {
+            if self.is_inside_border((cx, cz)) {
+                self.chunk((cx, cz));
+            }
+        }
+        info!("Generated {} spawn chunks", self.chunks.len());
+    }
+
+    /// Check whether the chunk at chunk coordinates `(cx, cz)` is inside the world border.
+    pub fn is_inside_border(&self, (cx, cz): (i64, i64)) -> bool {
+        match self.meta.world_border {
+            Some(border) => {
+                cx.unsigned_abs() <= border as u64 && cz.unsigned_abs() <= border as u64
+            }
+            None => true,
+        }
+    }
+
+    /// Get the chunk at chunk coordinates `(cx, cz)`, loading or generating it if needed.
+    ///
+    /// This doesn't check the world border, see [`World::is_inside_border`].
+    pub fn chunk(&mut self, (cx, cz): (i64, i64)) -> &Chunk {
+        if !self.chunks.contains_key(&(cx, cz)) {
+            let chunk = match self.regions.load_chunk((cx, cz)) {
+                Ok(Some(chunk)) => chunk,
+                Ok(None) => self.generate((cx, cz)),
+                Err(e) => {
+                    error!("Failed to load chunk ({cx}, {cz}), generating it again: {e:#}");
+                    self.generate((cx, cz))
+                }
+            };
+            self.chunks.insert((cx, cz), chunk);
+        }
+        &self.chunks[&(cx, cz)]
+    }
+
+    fn generate(&mut self, coords: (i64, i64)) -> Chunk {
+        self.events.push(WorldEvent::ChunkGenerated { coords });
+        self.generator.generate(coords)
+    }
+
+    /// Set a block at world coordinates `(x, y, z)`, generating its chunk if needed.
+    pub fn set_block(&mut self, (x, y, z): (i64, i64, i64), block: Block) -> Result<()> {
+        if !(0..256).contains(&y) {
+            bail!("y = {y} is outside of the world");
+        }
+        let (cx, cz) = (x.div_euclid(16), z.div_euclid(16));
+        if !self.is_inside_border((cx, cz)) {
+            bail!("({x}, {z}) is outside of the world border");
+        }
+        let local = (
+            x.rem_euclid(16) as usize,
+            y as usize,
+            z.rem_euclid(16) as usize,
+        );
+        let old = self.chunk((cx, cz)).get(local);
+        if old == block {
+            return Ok(());
+        }
+        self.modified.insert((cx, cz));
+        self.chunks.get_mut(&(cx, cz)).unwrap().set(local, block);
+        self.events.push(WorldEvent::BlockChanged {
+            pos: (x, y, z),
+            old,
+            new: block,
+        });
+        Ok(())
+    }
+
+    /// Get the block at world coordinates `(x, y, z)`, generating its chunk if needed.
+    ///
+    /// Everything outside of the world is empty.
+    pub fn block(&mut self, (x, y, z): (i64, i64, i64)) -> Block {
+        let (cx, cz) = (x.div_euclid(16), z.div_euclid(16));
+        if !(0..256).contains(&y) || !self.is_inside_border((cx, cz)) {
+            return Block::Empty;
+        }
+        self.chunk((cx, cz)).get((
+            x.rem_euclid(16) as usize,
+            y as usize,
+            z.rem_euclid(16) as usize,
+        ))
+    }
+
+    /// Get the y above the highest visible block of the column at world coordinates `(x, z)`,
+    /// generating its chunk if needed.
+    pub fn height(&mut self, (x, z): (i64, i64)) -> i64 {
+        let (cx, cz) = (x.div_euclid(16), z.div_euclid(16));
+        self.chunk((cx, cz))
+            .height((x.rem_euclid(16) as usize, z.rem_euclid(16) as usize)) as i64
+    }
+
+    /// Find the position players spawn at: on top of the column closest to the origin whose top
+    /// block can be stood on, i.e. is solid and not replaceable.
+    pub fn spawn_point(&mut self) -> (i64, i64, i64) {
+        let mut columns: Vec<_> = iproduct!(
+            -SPAWN_SEARCH_RADIUS..=SPAWN_SEARCH_RADIUS,
+            -SPAWN_SEARCH_RADIUS..=SPAWN_SEARCH_RADIUS
+        )
+        .collect();
+        columns.sort_by_key(|(x, z)| x * x + z * z);
+        for (x, z) in columns {
+            let height = self.height((x, z));
+            if !(1..256).contains(&height) {
+                continue;
+            }
+            let top = self.block((x, height - 1, z));
+            // Solid blocks that are replaceable, such as leaves, are too flimsy to be stood on
+            if top.is_solid() && !top.has_tag(BlockTag::Replaceable) {
+                return (x, height, z);
+            }
+        }
+        // Somewhere to stand on is better than nothing
+        (0, self.height((0, 0)), 0)
+    }
+
+    /// Find the first solid block along a ray, within `max_distance` blocks.
+    pub fn raycast(
+        &mut self,
+        origin: [f32; 3],
+        direction: [f32; 3],
+        max_distance: f32,
+    ) -> Option<RaycastHit> {
+        raycast(origin, direction, max_distance, |pos| {
+            self.block(pos).is_solid()
+        })
+    }
+
+    /// Blow up the blocks around `center`, returning the number of blocks destroyed.
+    ///
+    /// The explosion's strength is `power` at the center, and falls off by 1 per block of
+    /// distance. Blocks whose blast resistance is lower than the strength reaching them are
+    /// destroyed, if `can_break` allows it.
+    pub fn explode(
+        &mut self,
+        center: (i64, i64, i64),
+        power: f32,
+        mut can_break: impl FnMut((i64, i64, i64), Block) -> bool,
+    ) -> usize {
+        let radius = power.ceil() as i64;
+        let mut destroyed = 0;
+        for (dx, dy, dz) in iproduct!(-radius..=radius, -radius..=radius, -radius..=radius) {
+            let pos = (center.0 + dx, center.1 + dy, center.2 + dz);
+            let distance = ((dx * dx + dy * dy + dz * dz) as f32).sqrt();
+            let block = self.block(pos);
+            if block != Block::Empty
+                && power - distance > block.blast_resistance()
+                && can_break(pos, block)
+            {
+                self.set_block(pos, Block::Empty)
+                    .expect("Blocks in the world can be set");
+                destroyed += 1;
+            }
+        }
+        destroyed
+    }
+
+    /// Save the world metadata and the modified chunks to the world directory, returning the
+    /// number of chunks written.
+    pub fn save(&mut self) -> Result<usize> {
+        let level_path = self.dir.join(LEVEL_FILE);
+        fs::write(&level_path, self.meta.to_text())
+            .with_context(|| format!("Failed to write {}", level_path.display()))?;
+
+        let chunks = &self.chunks;
+        self.regions.save_chunks(
+            self.modified
+                .iter()
+                .map(|coords| (*coords, &chunks[coords])),
+        )?;
+        let written = self.modified.len();
+        self.modified.clear();
+        Ok(written)
+    }
+
+    /// Save the world and pack it into an archive at `path`, see [`archive::export`].
+    pub fn export(&mut self, path: &Path) -> Result<usize> {
+        self.save()?;
+        archive::export(&self.dir, path)
+    }
+
+    /// Load the structure template `<name>.txt` from the structures directory of the world.
+    pub fn load_structure(&self, name: &str) -> Result<Structure> {
+        let path = self.dir.join(STRUCTURE_DIR).join(format!("{name}.txt"));
+        Structure::load(&path).with_context(|| format!("Failed to load {}", path.display()))
+    }
+
+    /// Take the events recorded since they were last taken, oldest first.
+    pub fn take_events(&mut self) -> Vec<WorldEvent> {
+        std::mem::take(&mut self.events)
+    }
+
+    /// The number of chunks held in memory.
+    pub fn loaded_chunk_count(&self) -> usize {
+        self.chunks.len()
+    }
+
+    pub fn seed(&self) -> u32 {
+        self.meta.seed
+    }
+}
+
+fn random_seed() -> u32 {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.subsec_nanos());
+    nanos ^ std::process::id().rotate_left(16)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_level_meta_round_trip() {
+        let mut meta = LevelMeta {
+            seed: 1234,
+            world_border: None,
+        };
+        assert_eq!(LevelMeta::parse(&meta.to_text()).unwrap(), meta);
+        meta.world_border = Some(8);
+        assert_eq!(LevelMeta::parse(&meta.to_text()).unwrap(), meta);
+        assert_eq!(LevelMeta::parse("# comment\n\n seed=7 ").unwrap().seed, 7);
+        assert!(LevelMeta::parse("").is_err());
+        assert!(LevelMeta::parse("seed = x").is_err());
+        assert!(LevelMeta::parse("seed = 1\nsize = 2").is_err());
+        assert!(LevelMeta::parse("seed = 1\nworld_border = -1").is_err());
+    }
+
+    #[test]
+    fn test_explode() {
+        let dir = std::env::temp_dir().join(format!("wgpu-block-explode-{}", std::process::id()));
+        let mut world = World::open_or_create(&dir, Some(0), None).unwrap();
+        for x in 0..8 {
+            world.set_block((x, 100, 0), Block::Dirt).unwrap();
+            world.set_block((x, 101, 0), Block::Stone).unwrap();
+        }
+
+        // Dirt withstands 0.5, so it's destroyed up to 3 blocks away, while stone withstands 6
+        assert_eq!(world.explode((0, 100, 0), 4.0, |_, _| true), 4);
+        assert_eq!(world.block((3, 100, 0)), Block::Empty);
+        assert_eq!(world.block((4, 100, 0)), Block::Dirt);
+        assert_eq!(world.block((0, 101, 0)), Block::Stone);
+
+        // A stronger explosion also breaks the stone right above it
+        assert_eq!(world.explode((0, 100, 0), 7.25, |_, _| true), 3 + 1);
+        assert_eq!(world.block((0, 101, 0)), Block::Empty);
+        assert_eq!(world.block((1, 101, 0)), Block::Stone);
+        assert_eq!(world.block((7, 100, 0)), Block::Dirt);
+
+        // Blocks that can't be broken are kept
+        assert_eq!(
+            world.explode((7, 100, 0), 2.0, |_, block| block != Block::Dirt),
+            0
+        );
+        assert_eq!(world.block((7, 100, 0)), Block::Dirt);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_spawn_point() {
+        let dir = std::env::temp_dir().join(format!("wgpu-block-spawn-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let mut world = World::open_or_create(&dir, Some(3), None).unwrap();
+
+        let (x, y, z) = world.spawn_point();
+        assert!(x.abs() <= SPAWN_SEARCH_RADIUS && z.abs() <= SPAWN_SEARCH_RADIUS);
+        assert_eq!(world.height((x, z)), y);
+        assert!(world.block((x, y - 1, z)).is_solid());
+        assert_eq!(world.block((x, y, z)), Block::Empty);
+
+        // Neither water nor leaves can be spawned on
+        world.set_block((x, y, z), Block::Water).unwrap();
+        assert_ne!(world.spawn_point(), (x, y + 1, z));
+        world.set_block((x, y + 1, z), Block::Leaves).unwrap();
+        assert_ne!(world.spawn_point(), (x, y + 2, z));
+        world.set_block((x, y + 2, z), Block::Stone).unwrap();
+        assert_eq!(world.spawn_point(), (x, y + 3, z));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_world_border() {
+        let dir = std::env::temp_dir().join(format!("wgpu-block-border-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let mut world = World::open_or_create(&dir, Some(0), Some(1)).unwrap();
+        world.generate_spawn_area();
+        assert_eq!(world.loaded_chunk_count(), 9);
+
+        assert!(world.set_block((31, 255, -16), Block::Stone).is_ok());
+        assert!(world.set_block((32, 255, 0), Block::Stone).is_err());
+        assert!(world.set_block((0, 255, -17), Block::Stone).is_err());
+        assert_eq!(world.block((32, 0, 0)), Block::Empty);
+        assert_eq!(world.loaded_chunk_count(), 9);
+
+        // The border is saved, and kept when reopening without one
+        world.save().unwrap();
+        let reopened = World::open_or_create(&dir, None, None).unwrap();
+        assert_eq!(reopened.meta.world_border, Some(1));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_modified_chunks_persisted() {
+        let dir = std::env::temp_dir().join(format!("wgpu-block-chunks-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+
+        let mut world = World::open_or_create(&dir, Some(5), None).unwrap();
+        world.set_block((-40, 200, 7), Block::Log).unwrap();
+        assert_eq!(world.save().unwrap(), 1);
+        assert!(world.modified.is_empty());
+        assert!(dir.join(REGION_DIR).join("r.-1.0.bin").exists());
+
+        let mut reopened = World::open_or_create(&dir, None, None).unwrap();
+        assert_eq!(reopened.block((-40, 200, 7)), Block::Log);
+        assert_eq!(reopened.block((-40, 201, 7)), Block::Empty);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_seed_persisted() {
+        let dir = std::env::temp_dir().join(format!("wgpu-block-world-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+
+        let mut created = World::open_or_create(&dir, Some(99), None).unwrap();
+        let mut reopened = World::open_or_create(&dir, Some(100), None).unwrap();
+        assert_eq!(reopened.seed(), 99);
+
+        // The same seed reproduces the same terrain
+        let (a, b) = (created.chunk((1, 2)), reopened.chunk((1, 2)).clone());
+        for y in 0..256 {
+            assert_eq!(a.get((3, y, 4)), b.get((3, y, 4)));
+        }
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
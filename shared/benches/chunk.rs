@@ -0,0 +1,123 @@
+//! Benchmarks of chunk storage, serialization and generation.
+//!
+//! Run with `cargo bench -p wgpu-block-shared`.
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use itertools::iproduct;
+use wgpu_block_shared::chunk::{Block, Chunk, SubChunk};
+use wgpu_block_shared::worldgen::{TerrainGenerator, WorldGenerator};
+
+/// Representative chunks, from the cheapest to the worst case for the palette and meshing.
+fn chunks() -> Vec<(&'static str, Chunk)> {
+    let mut flat = Chunk::default();
+    for (x, y, z) in iproduct!(0..16, 0..64, 0..16) {
+        flat.set((x, y, z), if y == 63 { Block::Grass } else { Block::Stone });
+    }
+
+    let noisy = TerrainGenerator::new(0).generate((3, -2));
+
+    let mut checkerboard = Chunk::default();
+    for (x, y, z) in iproduct!(0..16, 0..256, 0..16) {
+        if (x + y + z) % 2 == 0 {
+            checkerboard.set((x, y, z), Block::ALL[(x + z) % Block::ALL.len()]);
+        }
+    }
+
+    vec![
+        ("flat", flat),
+        ("noisy", noisy),
+        ("checkerboard", checkerboard),
+    ]
+}
+
+fn bench_get(c: &mut Criterion) {
+    let mut group = c.benchmark_group("chunk_get_all");
+    for (name, chunk) in chunks() {
+        group.bench_with_input(BenchmarkId::from_parameter(name), &chunk, |b, chunk| {
+            b.iter(|| {
+                let mut opaque = 0;
+                for (x, y, z) in iproduct!(0..16, 0..256, 0..16) {
+                    opaque += chunk.get((x, y, z)).is_opaque() as usize;
+                }
+                black_box(opaque)
+            })
+        });
+    }
+    group.finish();
+}
+
+/// Compare the palette storage of subchunks against a flat array of blocks, for filling a subchunk
+/// with a checkerboard of every block and reading it back.
+fn bench_palette(c: &mut Criterion) {
+    let pattern = |(x, y, z): (usize, usize, usize)| {
+        if (x + y + z) % 2 == 0 {
+            Block::ALL[(x + z) % Block::ALL.len()]
+        } else {
+            Block::Empty
+        }
+    };
+
+    let mut group = c.benchmark_group("subchunk_storage");
+    group.bench_function("palette", |b| {
+        b.iter(|| {
+            let mut subchunk = SubChunk::default();
+            for pos in iproduct!(0..16, 0..16, 0..16) {
+                subchunk.set(pos, pattern(pos));
+            }
+            let mut opaque = 0;
+            for pos in iproduct!(0..16, 0..16, 0..16) {
+                opaque += subchunk.get(pos).is_opaque() as usize;
+            }
+            black_box(opaque)
+        })
+    });
+    group.bench_function("flat", |b| {
+        b.iter(|| {
+            let mut blocks = [Block::Empty; 16 * 16 * 16];
+            for (x, y, z) in iproduct!(0..16, 0..16, 0..16) {
+                blocks[(y * 16 + z) * 16 + x] = pattern((x, y, z));
+            }
+            let mut opaque = 0;
+            for (x, y, z) in iproduct!(0..16, 0..16, 0..16) {
+                opaque += blocks[(y * 16 + z) * 16 + x].is_opaque() as usize;
+            }
+            black_box(opaque)
+        })
+    });
+    group.finish();
+}
+
+fn bench_serialization(c: &mut Criterion) {
+    let mut group = c.benchmark_group("chunk_serialization");
+    for (name, chunk) in chunks() {
+        let bytes = chunk.to_bytes();
+        group.bench_with_input(BenchmarkId::new("to_bytes", name), &chunk, |b, chunk| {
+            b.iter(|| black_box(chunk.to_bytes()))
+        });
+        group.bench_with_input(BenchmarkId::new("from_bytes", name), &bytes, |b, bytes| {
+            b.iter(|| black_box(Chunk::from_bytes(bytes)))
+        });
+    }
+    group.finish();
+}
+
+fn bench_generate(c: &mut Criterion) {
+    let generator = TerrainGenerator::new(0);
+    let mut cx = 0;
+    c.bench_function("terrain_generate", |b| {
+        b.iter(|| {
+            // A new chunk every time, so that nothing is cached between iterations
+            cx += 1;
+            black_box(generator.generate((cx, 0)))
+        })
+    });
+}
+
+criterion_group!(
+    benches,
+    bench_get,
+    bench_palette,
+    bench_serialization,
+    bench_generate
+);
+criterion_main!(benches);
@@ -1,52 +1,496 @@
-use std::fmt::Debug;
+use std::fmt::{Debug, Display};
+use std::str::FromStr;
+
+use hashbrown::HashMap;
+use itertools::iproduct;
+
+use crate::tag::BlockTag;
 
 #[derive(Default, Debug, Clone)]
 pub struct Chunk {
     subchunks: [SubChunk; 16],
+    /// Block entities, by chunk-local coordinates.
+    block_entities: HashMap<(usize, usize, usize), BlockEntity>,
+    heightmap: Heightmap,
+}
+
+/// The height of each column of a chunk, i.e. the y above its highest visible block, or 0 if the
+/// column is empty. Indexed `z * 16 + x`.
+#[derive(Debug, Clone)]
+struct Heightmap([u16; 16 * 16]);
+
+impl Default for Heightmap {
+    fn default() -> Self {
+        Self([0; 16 * 16])
+    }
+}
+
+/// Extra data of a block that doesn't fit in its [`Block`], such as the text of a sign.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BlockEntity {
+    Text(String),
+}
+
+impl BlockEntity {
+    /// The id of the kind of block entity, as used in binary formats.
+    fn kind_id(&self) -> u8 {
+        match self {
+            BlockEntity::Text(_) => 0,
+        }
+    }
+
+    fn payload(&self) -> &[u8] {
+        match self {
+            BlockEntity::Text(text) => text.as_bytes(),
+        }
+    }
+
+    fn from_parts(kind_id: u8, payload: &[u8]) -> Option<BlockEntity> {
+        match kind_id {
+            0 => Some(BlockEntity::Text(
+                String::from_utf8(payload.to_owned()).ok()?,
+            )),
+            _ => None,
+        }
+    }
 }
 
-/// And POD type holding block data for 16x16x16 areas, row-major
+/// The number of blocks in a subchunk.
+const SUBCHUNK_VOLUME: usize = 16 * 16 * 16;
+
+/// Block data of a 16x16x16 area, row-major.
+///
+/// Most subchunks hold few distinct blocks, and many are all air, so the blocks are stored as
+/// indices into a palette of the blocks in the subchunk, packed into as few bits as the palette
+/// needs.
 #[derive(Debug, Clone)]
 pub struct SubChunk {
-    blocks: [Block; 16 * 16 * 16],
+    storage: BlockStorage,
+}
+
+#[derive(Debug, Clone)]
+enum BlockStorage {
+    /// Every block is the same, e.g. an empty subchunk.
+    Single(Block),
+    Packed {
+        palette: Vec<Block>,
+        /// The number of bits of each palette index.
+        bits: usize,
+        /// Palette indices, `64 / bits` per word starting from the lowest bits.
+        words: Vec<u64>,
+    },
+}
+
+impl BlockStorage {
+    fn get(&self, index: usize) -> Block {
+        match self {
+            BlockStorage::Single(block) => *block,
+            BlockStorage::Packed {
+                palette,
+                bits,
+                words,
+            } => palette[get_bits(words, *bits, index)],
+        }
+    }
+
+    fn set(&mut self, index: usize, block: Block) {
+        match self {
+            BlockStorage::Single(single) if *single == block => {}
+            BlockStorage::Single(single) => {
+                *self = BlockStorage::Packed {
+                    palette: vec![*single],
+                    bits: 1,
+                    words: vec![0; word_count(1)],
+                };
+                self.set(index, block);
+            }
+            BlockStorage::Packed {
+                palette,
+                bits,
+                words,
+            } => {
+                let palette_index = match palette.iter().position(|b| *b == block) {
+                    Some(palette_index) => palette_index,
+                    None => {
+                        palette.push(block);
+                        palette.len() - 1
+                    }
+                };
+                if palette_index >= 1 << *bits {
+                    // Widen the indices to fit the grown palette
+                    let new_bits = *bits + 1;
+                    let mut new_words = vec![0; word_count(new_bits)];
+                    for i in 0..SUBCHUNK_VOLUME {
+                        set_bits(&mut new_words, new_bits, i, get_bits(words, *bits, i));
+                    }
+                    *bits = new_bits;
+                    *words = new_words;
+                }
+                set_bits(words, *bits, index, palette_index);
+            }
+        }
+    }
+}
+
+/// The number of words needed for the palette indices of a subchunk, `bits` each.
+fn word_count(bits: usize) -> usize {
+    let per_word = 64 / bits;
+    (SUBCHUNK_VOLUME + per_word - 1) / per_word
+}
+
+fn get_bits(words: &[u64], bits: usize, index: usize) -> usize {
+    let per_word = 64 / bits;
+    let shift = (index % per_word) * bits;
+    ((words[index / per_word] >> shift) & ((1 << bits) - 1)) as usize
+}
+
+fn set_bits(words: &mut [u64], bits: usize, index: usize, value: usize) {
+    let per_word = 64 / bits;
+    let shift = (index % per_word) * bits;
+    let word = &mut words[index / per_word];
+    *word = (*word & !(((1 << bits) - 1) << shift)) | ((value as u64) << shift);
 }
 
 impl Chunk {
+    /// Set a block, removing the block entity of the block it replaces.
     pub fn set(&mut self, (x, y, z): (usize, usize, usize), block: Block) {
+        self.block_entities.remove(&(x, y, z));
         let subchunk_index = y.div_euclid(16);
         let sy = y.rem_euclid(16);
-        self.subchunks[subchunk_index].blocks[sy * 16 * 16 + z * 16 + x] = block;
+        self.subchunks[subchunk_index].set((x, sy, z), block);
+
+        let height = self.height((x, z));
+        if block.is_visible() && y >= height {
+            self.heightmap.0[z * 16 + x] = y as u16 + 1;
+        } else if !block.is_visible() && y + 1 == height {
+            self.update_height((x, z), y);
+        }
+    }
+
+    /// Get the y above the highest visible block in the column at chunk-local `(x, z)`, or 0 if
+    /// the column is empty. Everything at or above it is empty.
+    pub fn height(&self, (x, z): (usize, usize)) -> usize {
+        self.heightmap.0[z * 16 + x] as usize
+    }
+
+    /// Recompute the height of a column from the blocks below `top`.
+    fn update_height(&mut self, (x, z): (usize, usize), top: usize) {
+        let height = (0..top)
+            .rev()
+            .find(|y| self.get((x, *y, z)).is_visible())
+            .map_or(0, |y| y + 1);
+        self.heightmap.0[z * 16 + x] = height as u16;
     }
 
     pub fn get(&self, (x, y, z): (usize, usize, usize)) -> Block {
         let subchunk_index = y.div_euclid(16);
         let sy = y.rem_euclid(16);
-        self.subchunks[subchunk_index].blocks[sy * 16 * 16 + z * 16 + x]
+        self.subchunks[subchunk_index].get((x, sy, z))
+    }
+
+    /// Get the `s`-th subchunk from the bottom.
+    pub fn subchunk(&self, s: usize) -> &SubChunk {
+        &self.subchunks[s]
+    }
+
+    pub fn block_entity(&self, pos: (usize, usize, usize)) -> Option<&BlockEntity> {
+        self.block_entities.get(&pos)
+    }
+
+    pub fn set_block_entity(&mut self, pos: (usize, usize, usize), entity: BlockEntity) {
+        self.block_entities.insert(pos, entity);
+    }
+
+    pub fn remove_block_entity(&mut self, pos: (usize, usize, usize)) -> Option<BlockEntity> {
+        self.block_entities.remove(&pos)
+    }
+
+    /// Serialize the blocks as their [`Block::id`]s, bottom subchunk first, followed by the
+    /// number of block entities as a little-endian `u32` and the block entities. Each block
+    /// entity is its `x`, `y` and `z` and kind as bytes, followed by the length of its payload
+    /// as a little-endian `u32` and the payload.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes: Vec<u8> = self
+            .subchunks
+            .iter()
+            .flat_map(|subchunk| (0..SUBCHUNK_VOLUME).map(|i| subchunk.storage.get(i).id()))
+            .collect();
+
+        let mut block_entities: Vec<_> = self.block_entities.iter().collect();
+        block_entities.sort_by_key(|(pos, _)| **pos);
+        bytes.extend_from_slice(&(block_entities.len() as u32).to_le_bytes());
+        for (&(x, y, z), entity) in block_entities {
+            bytes.extend_from_slice(&[x as u8, y as u8, z as u8, entity.kind_id()]);
+            bytes.extend_from_slice(&(entity.payload().len() as u32).to_le_bytes());
+            bytes.extend_from_slice(entity.payload());
+        }
+        bytes
+    }
+
+    /// Deserialize a chunk written by [`Chunk::to_bytes`], or `None` if `bytes` are malformed.
+    pub fn from_bytes(bytes: &[u8]) -> Option<Chunk> {
+        fn take<'a>(bytes: &mut &'a [u8], len: usize) -> Option<&'a [u8]> {
+            if bytes.len() < len {
+                return None;
+            }
+            let (taken, rest) = bytes.split_at(len);
+            *bytes = rest;
+            Some(taken)
+        }
+        fn take_u32(bytes: &mut &[u8]) -> Option<u32> {
+            Some(u32::from_le_bytes(take(bytes, 4)?.try_into().ok()?))
+        }
+
+        let mut bytes = bytes;
+        let mut chunk = Chunk::default();
+        let blocks = take(&mut bytes, SUBCHUNK_VOLUME * 16)?;
+        for (subchunk, blocks) in chunk
+            .subchunks
+            .iter_mut()
+            .zip(blocks.chunks(SUBCHUNK_VOLUME))
+        {
+            for (i, id) in blocks.iter().enumerate() {
+                subchunk.storage.set(i, Block::from_id(*id)?);
+            }
+        }
+        for (x, z) in iproduct!(0..16, 0..16) {
+            chunk.update_height((x, z), 256);
+        }
+
+        for _ in 0..take_u32(&mut bytes)? {
+            let [x, y, z, kind_id]: [u8; 4] = take(&mut bytes, 4)?.try_into().ok()?;
+            if x >= 16 || z >= 16 {
+                return None;
+            }
+            let len = take_u32(&mut bytes)? as usize;
+            let entity = BlockEntity::from_parts(kind_id, take(&mut bytes, len)?)?;
+            chunk.set_block_entity((x as usize, y as usize, z as usize), entity);
+        }
+        bytes.is_empty().then_some(chunk)
+    }
+}
+
+impl SubChunk {
+    /// Get a block from its subchunk-local coordinates.
+    pub fn get(&self, (x, y, z): (usize, usize, usize)) -> Block {
+        self.storage.get(y * 16 * 16 + z * 16 + x)
+    }
+
+    /// Set a block at its subchunk-local coordinates.
+    pub fn set(&mut self, (x, y, z): (usize, usize, usize), block: Block) {
+        self.storage.set(y * 16 * 16 + z * 16 + x, block);
     }
 }
 
 impl Default for SubChunk {
     fn default() -> Self {
         Self {
-            blocks: [Block::Empty; 16 * 16 * 16],
+            storage: BlockStorage::Single(Block::Empty),
         }
     }
 }
 
-#[derive(Default, Debug, Clone, Copy)]
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Hash)]
 #[repr(u8)]
 pub enum Block {
     #[default]
     Empty,
     Grass,
+    Dirt,
+    Stone,
+    Sand,
+    Water,
+    Log,
+    Leaves,
 }
 
 impl Block {
+    pub const ALL: [Block; 8] = [
+        Block::Empty,
+        Block::Grass,
+        Block::Dirt,
+        Block::Stone,
+        Block::Sand,
+        Block::Water,
+        Block::Log,
+        Block::Leaves,
+    ];
+
+    /// The id of the block, as used in binary formats.
+    pub fn id(self) -> u8 {
+        self as u8
+    }
+
+    pub fn from_id(id: u8) -> Option<Block> {
+        Block::ALL.get(id as usize).copied()
+    }
+
+    /// The name of the block, as used in text formats.
+    pub fn name(&self) -> &'static str {
+        use Block::*;
+        match self {
+            Empty => "empty",
+            Grass => "grass",
+            Dirt => "dirt",
+            Stone => "stone",
+            Sand => "sand",
+            Water => "water",
+            Log => "log",
+            Leaves => "leaves",
+        }
+    }
+
+    /// The tags this block carries.
+    pub fn tags(&self) -> &'static [BlockTag] {
+        use Block::*;
+        use BlockTag::*;
+        match self {
+            Empty => &[Transparent, Replaceable],
+            Grass | Dirt => &[Soil],
+            Stone | Sand | Log => &[],
+            Water => &[Transparent, Fluid, Replaceable],
+            Leaves => &[Transparent, Replaceable],
+        }
+    }
+
+    pub fn has_tag(&self, tag: BlockTag) -> bool {
+        self.tags().contains(&tag)
+    }
+
     pub fn is_opaque(&self) -> bool {
+        !self.has_tag(BlockTag::Transparent)
+    }
+
+    /// Whether the block has faces to draw. Transparent blocks other than [`Block::Empty`], such
+    /// as water, are visible but don't hide the faces of their neighbors.
+    pub fn is_visible(&self) -> bool {
+        *self != Block::Empty
+    }
+
+    /// Whether rays and moving things stop at the block, as opposed to passing through it.
+    pub fn is_solid(&self) -> bool {
+        *self != Block::Empty && !self.has_tag(BlockTag::Fluid)
+    }
+
+    /// How much of an explosion's strength the block withstands. Blocks tagged
+    /// [`BlockTag::Unbreakable`] withstand any explosion.
+    pub fn blast_resistance(&self) -> f32 {
         use Block::*;
+        if self.has_tag(BlockTag::Unbreakable) {
+            return f32::INFINITY;
+        }
         match self {
-            Empty => false,
-            _ => true,
+            Empty => 0.0,
+            Leaves => 0.2,
+            Dirt | Sand => 0.5,
+            Grass => 0.6,
+            Log => 2.0,
+            Stone => 6.0,
+            Water => 100.0,
         }
     }
 }
+
+impl Display for Block {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseBlockError(String);
+
+impl Display for ParseBlockError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "unknown block `{}`", self.0)
+    }
+}
+
+impl std::error::Error for ParseBlockError {}
+
+impl FromStr for Block {
+    type Err = ParseBlockError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Block::ALL
+            .into_iter()
+            .find(|block| block.name() == s)
+            .ok_or_else(|| ParseBlockError(s.to_owned()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_subchunk_palette() {
+        let mut subchunk = SubChunk::default();
+        assert!(matches!(
+            subchunk.storage,
+            BlockStorage::Single(Block::Empty)
+        ));
+        subchunk.set((1, 2, 3), Block::Empty);
+        assert!(matches!(subchunk.storage, BlockStorage::Single(_)));
+
+        // The palette grows, and the indices widen, as distinct blocks are set
+        let mut expected = vec![Block::Empty; SUBCHUNK_VOLUME];
+        for (i, block) in (0..SUBCHUNK_VOLUME)
+            .step_by(7)
+            .zip(Block::ALL.iter().cycle())
+        {
+            let pos = (i % 16, i / 256, i / 16 % 16);
+            subchunk.set(pos, *block);
+            expected[i] = *block;
+        }
+        for (i, block) in expected.iter().enumerate() {
+            assert_eq!(subchunk.get((i % 16, i / 256, i / 16 % 16)), *block);
+        }
+        match &subchunk.storage {
+            BlockStorage::Packed { palette, bits, .. } => {
+                assert_eq!(palette.len(), Block::ALL.len());
+                assert_eq!(*bits, 3);
+            }
+            BlockStorage::Single(_) => panic!("expected packed storage"),
+        }
+    }
+
+    #[test]
+    fn test_heightmap() {
+        let mut chunk = Chunk::default();
+        assert_eq!(chunk.height((3, 4)), 0);
+        chunk.set((3, 10, 4), Block::Stone);
+        chunk.set((3, 20, 4), Block::Dirt);
+        assert_eq!(chunk.height((3, 4)), 21);
+        assert_eq!(chunk.height((4, 3)), 0);
+
+        chunk.set((3, 20, 4), Block::Empty);
+        assert_eq!(chunk.height((3, 4)), 11);
+        chunk.set((3, 255, 4), Block::Log);
+        assert_eq!(chunk.height((3, 4)), 256);
+
+        let loaded = Chunk::from_bytes(&chunk.to_bytes()).unwrap();
+        assert_eq!(loaded.height((3, 4)), 256);
+        chunk.set((3, 255, 4), Block::Empty);
+        chunk.set((3, 10, 4), Block::Empty);
+        assert_eq!(chunk.height((3, 4)), 0);
+
+        // Transparent blocks are still visible
+        chunk.set((3, 30, 4), Block::Water);
+        assert_eq!(chunk.height((3, 4)), 31);
+    }
+
+    #[test]
+    fn test_chunk_bytes_round_trip() {
+        let mut chunk = Chunk::default();
+        chunk.set((15, 255, 0), Block::Leaves);
+        chunk.set((0, 17, 9), Block::Water);
+        chunk.set_block_entity((2, 3, 4), BlockEntity::Text("sign".to_owned()));
+        let loaded = Chunk::from_bytes(&chunk.to_bytes()).unwrap();
+        assert_eq!(loaded.get((15, 255, 0)), Block::Leaves);
+        assert_eq!(loaded.get((0, 17, 9)), Block::Water);
+        assert_eq!(loaded.get((0, 16, 9)), Block::Empty);
+        assert_eq!(loaded.to_bytes(), chunk.to_bytes());
+        assert!(Chunk::from_bytes(&chunk.to_bytes()[1..]).is_none());
+    }
+}
@@ -1 +1,6 @@
 pub mod chunk;
+pub mod raycast;
+pub mod structure;
+pub mod tag;
+pub mod visibility;
+pub mod worldgen;
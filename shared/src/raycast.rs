@@ -0,0 +1,153 @@
+//! Voxel raycasting, shared by the client and the server so that both agree on what a ray hits.
+
+use crate::visibility::Face;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RaycastHit {
+    /// World coordinates of the hit block.
+    pub pos: (i64, i64, i64),
+    /// The face of the block the ray entered through, or `None` if the ray started inside it.
+    pub face: Option<Face>,
+    /// Distance from the origin of the ray to where it entered the block.
+    pub distance: f32,
+}
+
+/// Cast a ray from `origin` along `direction`, returning the first block within `max_distance`
+/// for which `is_hit` returns true.
+///
+/// This is the DDA algorithm of Amanatides and Woo, visiting every block the ray passes through
+/// in order. A point exactly on the boundary between two blocks is in the block on the positive
+/// side, and a ray with a zero direction only checks the block it starts in.
+pub fn raycast(
+    origin: [f32; 3],
+    direction: [f32; 3],
+    max_distance: f32,
+    mut is_hit: impl FnMut((i64, i64, i64)) -> bool,
+) -> Option<RaycastHit> {
+    let origin = origin.map(|v| v as f64);
+    let length = direction
+        .iter()
+        .map(|&v| v as f64 * v as f64)
+        .sum::<f64>()
+        .sqrt();
+    let direction = direction.map(|v| if length > 0.0 { v as f64 / length } else { 0.0 });
+
+    let mut pos = origin.map(|v| v.floor() as i64);
+    let step = direction.map(|v| {
+        if v > 0.0 {
+            1
+        } else if v < 0.0 {
+            -1
+        } else {
+            0
+        }
+    });
+    // Distance along the ray to the next block boundary on each axis, and between two boundaries
+    let mut t_max = [0, 1, 2].map(|i| {
+        let d = direction[i];
+        if d > 0.0 {
+            (origin[i].floor() + 1.0 - origin[i]) / d
+        } else if d < 0.0 {
+            (origin[i] - origin[i].floor()) / -d
+        } else {
+            f64::INFINITY
+        }
+    });
+    let t_delta = direction.map(|d| {
+        if d != 0.0 {
+            1.0 / d.abs()
+        } else {
+            f64::INFINITY
+        }
+    });
+
+    let mut face = None;
+    let mut t = 0.0;
+    loop {
+        if is_hit((pos[0], pos[1], pos[2])) {
+            return Some(RaycastHit {
+                pos: (pos[0], pos[1], pos[2]),
+                face,
+                distance: t as f32,
+            });
+        }
+
+        let axis = (0..3)
+            .min_by(|&a, &b| t_max[a].total_cmp(&t_max[b]))
+            .unwrap();
+        t = t_max[axis];
+        if t > max_distance as f64 {
+            return None;
+        }
+        pos[axis] += step[axis];
+        t_max[axis] += t_delta[axis];
+        face = Some(match (axis, step[axis] > 0) {
+            (0, true) => Face::NegX,
+            (0, false) => Face::PosX,
+            (1, true) => Face::NegY,
+            (1, false) => Face::PosY,
+            (_, true) => Face::NegZ,
+            (_, false) => Face::PosZ,
+        });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Collect the blocks visited by a ray that never hits anything.
+    fn visited(origin: [f32; 3], direction: [f32; 3], max_distance: f32) -> Vec<(i64, i64, i64)> {
+        let mut out = vec![];
+        raycast(origin, direction, max_distance, |pos| {
+            out.push(pos);
+            false
+        });
+        out
+    }
+
+    #[test]
+    fn test_raycast_axis_aligned() {
+        let hit = raycast([0.5, 0.5, 0.5], [0.0, 0.0, 2.0], 10.0, |pos| {
+            pos == (0, 0, 3)
+        })
+        .unwrap();
+        assert_eq!(hit.pos, (0, 0, 3));
+        assert_eq!(hit.face, Some(Face::NegZ));
+        assert_eq!(hit.distance, 2.5);
+
+        let hit = raycast([0.5, 0.5, 0.5], [-1.0, 0.0, 0.0], 10.0, |pos| pos.0 < -1).unwrap();
+        assert_eq!(hit.pos, (-2, 0, 0));
+        assert_eq!(hit.face, Some(Face::PosX));
+
+        assert!(raycast([0.5, 0.5, 0.5], [0.0, 0.0, 1.0], 2.0, |pos| pos
+            == (0, 0, 3))
+        .is_none());
+    }
+
+    #[test]
+    fn test_raycast_edge_cases() {
+        // Starting inside a block hits it without a face
+        let hit = raycast([0.5, 0.5, 0.5], [1.0, 0.0, 0.0], 10.0, |_| true).unwrap();
+        assert_eq!((hit.pos, hit.face, hit.distance), ((0, 0, 0), None, 0.0));
+
+        // A zero direction only checks the starting block
+        assert_eq!(visited([0.5, 0.5, 0.5], [0.0; 3], 10.0), vec![(0, 0, 0)]);
+
+        // Points on a boundary belong to the block on the positive side
+        assert_eq!(
+            visited([1.0, 0.5, 0.5], [-1.0, 0.0, 0.0], 0.5),
+            vec![(1, 0, 0), (0, 0, 0)]
+        );
+        assert_eq!(
+            visited([-1.0, 0.5, 0.5], [1.0, 0.0, 0.0], 0.5),
+            vec![(-1, 0, 0)]
+        );
+
+        // A diagonal ray through a corner visits blocks on both sides of it in a fixed order
+        assert_eq!(
+            visited([0.5, 0.5, 0.5], [1.0, 1.0, 0.0], 1.0),
+            vec![(0, 0, 0), (1, 0, 0), (1, 1, 0)]
+        );
+    }
+}
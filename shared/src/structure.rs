@@ -0,0 +1,391 @@
+//! Structure templates: grids of blocks that can be stamped into the world.
+//!
+//! # Text format
+//!
+//! ```text
+//! # Lines starting with `#` are comments.
+//! anchor 1 0 1
+//! palette
+//! G grass
+//! layer
+//! GGG
+//! G.G
+//! GGG
+//! layer
+//! .G.
+//! ```
+//!
+//! The `palette` section maps single characters to block names. A `.` always means "leave the
+//! existing block as is". Each `layer` section is one horizontal slice of the structure, starting
+//! from the bottom, with rows going along `+z` and columns along `+x`. Rows and layers shorter
+//! than the widest one are padded with `.`. The `anchor` is the position, relative to the
+//! bottom-north-west corner, that gets placed at the target location; it defaults to `0 0 0`.
+
+use std::fmt::Display;
+use std::path::Path;
+
+use hashbrown::HashMap;
+
+use crate::chunk::{Block, Chunk};
+
+/// A rotation around the `y` axis, clockwise when looking down.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rotation {
+    None,
+    Clockwise90,
+    Rotate180,
+    CounterClockwise90,
+}
+
+impl Rotation {
+    fn apply(self, (x, y, z): (i64, i64, i64)) -> (i64, i64, i64) {
+        use Rotation::*;
+        match self {
+            None => (x, y, z),
+            Clockwise90 => (-z, y, x),
+            Rotate180 => (-x, y, -z),
+            CounterClockwise90 => (z, y, -x),
+        }
+    }
+}
+
+/// A mirroring across a vertical plane through the anchor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mirror {
+    /// Flip the `x` coordinates.
+    X,
+    /// Flip the `z` coordinates.
+    Z,
+}
+
+impl Mirror {
+    fn apply(self, (x, y, z): (i64, i64, i64)) -> (i64, i64, i64) {
+        match self {
+            Mirror::X => (-x, y, z),
+            Mirror::Z => (x, y, -z),
+        }
+    }
+}
+
+/// A template of blocks with an anchor point.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Structure {
+    size: (usize, usize, usize),
+    /// Cells in y-major, then z, then x order. `None` cells leave the world untouched.
+    cells: Vec<Option<Block>>,
+    anchor: (i64, i64, i64),
+}
+
+impl Structure {
+    /// Load a structure from a file in the text format.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, StructureError> {
+        let text = std::fs::read_to_string(path).map_err(StructureError::Io)?;
+        Self::parse(&text)
+    }
+
+    /// Parse a structure from the text format.
+    pub fn parse(text: &str) -> Result<Self, StructureError> {
+        enum Section {
+            Header,
+            Palette,
+            Layer,
+        }
+
+        let mut anchor = (0, 0, 0);
+        let mut palette = HashMap::new();
+        let mut layers: Vec<Vec<&str>> = vec![];
+        let mut section = Section::Header;
+
+        for (i, line) in text.lines().enumerate() {
+            let line_no = i + 1;
+            let error = |message: String| StructureError::Parse { line_no, message };
+
+            let trimmed = line.trim();
+            if trimmed.is_empty() || trimmed.starts_with('#') {
+                continue;
+            }
+
+            match trimmed {
+                "palette" => {
+                    section = Section::Palette;
+                    continue;
+                }
+                "layer" => {
+                    section = Section::Layer;
+                    layers.push(vec![]);
+                    continue;
+                }
+                _ => {}
+            }
+
+            if let Some(rest) = trimmed.strip_prefix("anchor ") {
+                let coords = rest
+                    .split_whitespace()
+                    .map(|c| c.parse::<i64>())
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(|e| error(format!("invalid anchor: {e}")))?;
+                match coords[..] {
+                    [x, y, z] => anchor = (x, y, z),
+                    _ => return Err(error("anchor needs exactly 3 coordinates".to_owned())),
+                }
+                continue;
+            }
+
+            match section {
+                Section::Header => return Err(error(format!("unexpected line `{trimmed}`"))),
+                Section::Palette => {
+                    let (key, name) = trimmed
+                        .split_once(char::is_whitespace)
+                        .ok_or_else(|| error("palette entries are `<char> <block>`".to_owned()))?;
+                    let mut chars = key.chars();
+                    let key = match (chars.next(), chars.next()) {
+                        (Some(c), None) if c != '.' => c,
+                        _ => return Err(error(format!("invalid palette key `{key}`"))),
+                    };
+                    let block = name
+                        .trim()
+                        .parse::<Block>()
+                        .map_err(|e| error(e.to_string()))?;
+                    palette.insert(key, block);
+                }
+                Section::Layer => {
+                    for c in trimmed.chars() {
+                        if c != '.' && !palette.contains_key(&c) {
+                            return Err(error(format!("`{c}` is not in the palette")));
+                        }
+                    }
+                    layers.last_mut().unwrap().push(trimmed);
+                }
+            }
+        }
+
+        let size_x = layers
+            .iter()
+            .flatten()
+            .map(|row| row.chars().count())
+            .max()
+            .unwrap_or(0);
+        let size_y = layers.len();
+        let size_z = layers.iter().map(Vec::len).max().unwrap_or(0);
+
+        let mut structure = Self::empty((size_x, size_y, size_z), anchor);
+        for (y, layer) in layers.iter().enumerate() {
+            for (z, row) in layer.iter().enumerate() {
+                for (x, c) in row.chars().enumerate() {
+                    let index = structure.index((x, y, z));
+                    structure.cells[index] = palette.get(&c).copied();
+                }
+            }
+        }
+
+        Ok(structure)
+    }
+
+    fn empty((sx, sy, sz): (usize, usize, usize), anchor: (i64, i64, i64)) -> Self {
+        Self {
+            size: (sx, sy, sz),
+            cells: vec![None; sx * sy * sz],
+            anchor,
+        }
+    }
+
+    fn index(&self, (x, y, z): (usize, usize, usize)) -> usize {
+        let (sx, _, sz) = self.size;
+        y * sx * sz + z * sx + x
+    }
+
+    /// The size of the bounding box of the structure, as `(x, y, z)`.
+    pub fn size(&self) -> (usize, usize, usize) {
+        self.size
+    }
+
+    /// Iterate over the non-`.` blocks of the structure, with positions relative to the anchor.
+    pub fn blocks(&self) -> impl Iterator<Item = ((i64, i64, i64), Block)> + '_ {
+        let (sx, sy, sz) = self.size;
+        let (ax, ay, az) = self.anchor;
+        itertools::iproduct!(0..sy, 0..sz, 0..sx).filter_map(move |(y, z, x)| {
+            self.cells[self.index((x, y, z))]
+                .map(|block| ((x as i64 - ax, y as i64 - ay, z as i64 - az), block))
+        })
+    }
+
+    /// Get a copy of the structure rotated around its anchor.
+    pub fn rotated(&self, rotation: Rotation) -> Self {
+        self.transformed(|pos| rotation.apply(pos))
+    }
+
+    /// Get a copy of the structure mirrored through its anchor.
+    pub fn mirrored(&self, mirror: Mirror) -> Self {
+        self.transformed(|pos| mirror.apply(pos))
+    }
+
+    /// Map every cell (including `.` cells, so that the bounding box is preserved) through `f`,
+    /// which operates on positions relative to the anchor.
+    fn transformed(&self, f: impl Fn((i64, i64, i64)) -> (i64, i64, i64)) -> Self {
+        let (sx, sy, sz) = self.size;
+        let (ax, ay, az) = self.anchor;
+        let cells = itertools::iproduct!(0..sy, 0..sz, 0..sx)
+            .map(|(y, z, x)| {
+                let pos = f((x as i64 - ax, y as i64 - ay, z as i64 - az));
+                (pos, self.cells[self.index((x, y, z))])
+            })
+            .collect::<Vec<_>>();
+
+        if cells.is_empty() {
+            return self.clone();
+        }
+
+        let min = cells.iter().fold(
+            (i64::MAX, i64::MAX, i64::MAX),
+            |(mx, my, mz), &((x, y, z), _)| (mx.min(x), my.min(y), mz.min(z)),
+        );
+        let max = cells.iter().fold(
+            (i64::MIN, i64::MIN, i64::MIN),
+            |(mx, my, mz), &((x, y, z), _)| (mx.max(x), my.max(y), mz.max(z)),
+        );
+        let size = (
+            (max.0 - min.0 + 1) as usize,
+            (max.1 - min.1 + 1) as usize,
+            (max.2 - min.2 + 1) as usize,
+        );
+
+        let mut out = Self::empty(size, (-min.0, -min.1, -min.2));
+        for ((x, y, z), cell) in cells {
+            let index = out.index((
+                (x - min.0) as usize,
+                (y - min.1) as usize,
+                (z - min.2) as usize,
+            ));
+            out.cells[index] = cell;
+        }
+        out
+    }
+
+    /// Place the part of the structure that falls into the chunk at `(cx, cz)`, with the anchor at
+    /// the world coordinates `(x, y, z)`. Blocks outside of the chunk or the world height are
+    /// skipped, so a structure crossing chunk borders can be placed chunk by chunk.
+    pub fn place_in_chunk(
+        &self,
+        chunk: &mut Chunk,
+        (cx, cz): (i64, i64),
+        (x, y, z): (i64, i64, i64),
+    ) {
+        for ((dx, dy, dz), block) in self.blocks() {
+            let (bx, by, bz) = (x + dx, y + dy, z + dz);
+            if bx.div_euclid(16) != cx || bz.div_euclid(16) != cz || !(0..256).contains(&by) {
+                continue;
+            }
+            chunk.set(
+                (
+                    bx.rem_euclid(16) as usize,
+                    by as usize,
+                    bz.rem_euclid(16) as usize,
+                ),
+                block,
+            );
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum StructureError {
+    Io(std::io::Error),
+    Parse { line_no: usize, message: String },
+}
+
+impl Display for StructureError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StructureError::Io(e) => write!(f, "failed to read structure: {e}"),
+            StructureError::Parse { line_no, message } => write!(f, "line {line_no}: {message}"),
+        }
+    }
+}
+
+impl std::error::Error for StructureError {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const L_SHAPE: &str = "
+        # An L-shaped floor with a pillar on its corner
+        anchor 0 0 0
+        palette
+        G grass
+        layer
+        GG
+        G
+        layer
+        G
+    ";
+
+    fn sorted_blocks(structure: &Structure) -> Vec<(i64, i64, i64)> {
+        let mut out = structure.blocks().map(|(pos, _)| pos).collect::<Vec<_>>();
+        out.sort();
+        out
+    }
+
+    #[test]
+    fn test_parse() {
+        let structure = Structure::parse(L_SHAPE).unwrap();
+        assert_eq!(structure.size(), (2, 2, 2));
+        assert_eq!(
+            sorted_blocks(&structure),
+            vec![(0, 0, 0), (0, 0, 1), (0, 1, 0), (1, 0, 0)]
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(matches!(
+            Structure::parse("layer\nX"),
+            Err(StructureError::Parse { line_no: 2, .. })
+        ));
+        assert!(Structure::parse("palette\nG marble").is_err());
+        assert!(Structure::parse("anchor 1 2").is_err());
+    }
+
+    #[test]
+    fn test_rotate() {
+        let structure = Structure::parse(L_SHAPE).unwrap();
+        let rotated = structure.rotated(Rotation::Clockwise90);
+        assert_eq!(
+            sorted_blocks(&rotated),
+            vec![(-1, 0, 0), (0, 0, 0), (0, 0, 1), (0, 1, 0)]
+        );
+
+        let full_turn = rotated
+            .rotated(Rotation::Clockwise90)
+            .rotated(Rotation::Rotate180);
+        assert_eq!(full_turn, structure);
+        assert_eq!(
+            structure.rotated(Rotation::CounterClockwise90),
+            structure
+                .rotated(Rotation::Rotate180)
+                .rotated(Rotation::Clockwise90)
+        );
+    }
+
+    #[test]
+    fn test_mirror() {
+        let structure = Structure::parse(L_SHAPE).unwrap();
+        assert_eq!(
+            sorted_blocks(&structure.mirrored(Mirror::X)),
+            vec![(-1, 0, 0), (0, 0, 0), (0, 0, 1), (0, 1, 0)]
+        );
+        assert_eq!(structure.mirrored(Mirror::Z).mirrored(Mirror::Z), structure);
+    }
+
+    #[test]
+    fn test_place_in_chunk_clips() {
+        let structure = Structure::parse(L_SHAPE).unwrap();
+        let mut chunk = Chunk::default();
+        // Anchor on the last column of chunk (0, 0), so the `+x` arm falls into chunk (1, 0)
+        structure.place_in_chunk(&mut chunk, (0, 0), (15, 10, 3));
+        assert_eq!(chunk.get((15, 10, 3)), Block::Grass);
+        assert_eq!(chunk.get((15, 10, 4)), Block::Grass);
+        assert_eq!(chunk.get((15, 11, 3)), Block::Grass);
+        assert_eq!(chunk.get((0, 10, 3)), Block::Empty);
+    }
+}
@@ -0,0 +1,85 @@
+//! Block tags, used to express behavior over groups of blocks instead of individual ones.
+
+use std::fmt::Display;
+use std::str::FromStr;
+
+/// A category a block can belong to, written as `#name` in text form.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BlockTag {
+    /// Blocks that light passes through and that don't hide the faces of their neighbors.
+    Transparent,
+    /// Blocks that may be overwritten when another block is placed into them.
+    Replaceable,
+    /// Blocks that players can't break.
+    Unbreakable,
+    /// Blocks that plants (e.g. trees during world generation) can grow on.
+    Soil,
+    /// Liquids, which entities can swim through.
+    Fluid,
+}
+
+impl BlockTag {
+    pub const ALL: [BlockTag; 5] = [
+        BlockTag::Transparent,
+        BlockTag::Replaceable,
+        BlockTag::Unbreakable,
+        BlockTag::Soil,
+        BlockTag::Fluid,
+    ];
+
+    /// The name of the tag, without the leading `#`.
+    pub fn name(&self) -> &'static str {
+        use BlockTag::*;
+        match self {
+            Transparent => "transparent",
+            Replaceable => "replaceable",
+            Unbreakable => "unbreakable",
+            Soil => "soil",
+            Fluid => "fluid",
+        }
+    }
+}
+
+impl Display for BlockTag {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "#{}", self.name())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseBlockTagError(String);
+
+impl Display for ParseBlockTagError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "unknown block tag `{}`", self.0)
+    }
+}
+
+impl std::error::Error for ParseBlockTagError {}
+
+impl FromStr for BlockTag {
+    type Err = ParseBlockTagError;
+
+    /// Parse a tag from its name, with or without the leading `#`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let name = s.strip_prefix('#').unwrap_or(s);
+        BlockTag::ALL
+            .into_iter()
+            .find(|tag| tag.name() == name)
+            .ok_or_else(|| ParseBlockTagError(s.to_owned()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_round_trip() {
+        for tag in BlockTag::ALL {
+            assert_eq!(tag.to_string().parse(), Ok(tag));
+            assert_eq!(tag.name().parse(), Ok(tag));
+        }
+        assert!("#logs".parse::<BlockTag>().is_err());
+    }
+}
@@ -0,0 +1,165 @@
+//! Face-to-face visibility of subchunks, for cave-aware occlusion culling.
+//!
+//! Two faces of a subchunk are connected if one can walk from one to the other through
+//! non-opaque blocks within the subchunk. A renderer looking into a subchunk through one face can
+//! only see out of the faces connected to it, so subchunks behind sealed-off faces can be culled.
+
+use itertools::iproduct;
+
+use crate::chunk::SubChunk;
+
+/// One of the six faces of a subchunk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Face {
+    NegX,
+    PosX,
+    NegY,
+    PosY,
+    NegZ,
+    PosZ,
+}
+
+impl Face {
+    pub const ALL: [Face; 6] = [
+        Face::NegX,
+        Face::PosX,
+        Face::NegY,
+        Face::PosY,
+        Face::NegZ,
+        Face::PosZ,
+    ];
+
+    fn bit(self) -> u8 {
+        1 << self as u8
+    }
+
+    /// Get the faces a subchunk-local position lies on.
+    fn of_position((x, y, z): (usize, usize, usize)) -> u8 {
+        let mut faces = 0;
+        let mut mark = |is_on: bool, face: Face| {
+            if is_on {
+                faces |= face.bit();
+            }
+        };
+        mark(x == 0, Face::NegX);
+        mark(x == 15, Face::PosX);
+        mark(y == 0, Face::NegY);
+        mark(y == 15, Face::PosY);
+        mark(z == 0, Face::NegZ);
+        mark(z == 15, Face::PosZ);
+        faces
+    }
+}
+
+/// The connectivity between the faces of a subchunk, as a symmetric 6x6 bit matrix.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Visibility {
+    rows: [u8; 6],
+}
+
+impl Visibility {
+    /// Visibility where every face sees every other face, e.g. for unknown subchunks.
+    pub const ALL: Self = Self {
+        rows: [0b111111; 6],
+    };
+
+    /// Compute the visibility of a subchunk by flood-filling its non-opaque blocks.
+    pub fn compute(subchunk: &SubChunk) -> Self {
+        let index = |(x, y, z): (usize, usize, usize)| y * 16 * 16 + z * 16 + x;
+
+        let mut visited = [false; 16 * 16 * 16];
+        let mut stack = vec![];
+        let mut visibility = Self::default();
+
+        for (x, y, z) in iproduct!(0..16, 0..16, 0..16) {
+            if visited[index((x, y, z))] || subchunk.get((x, y, z)).is_opaque() {
+                continue;
+            }
+
+            // Flood-fill one connected region of non-opaque blocks, collecting the faces it touches
+            let mut faces = 0;
+            visited[index((x, y, z))] = true;
+            stack.push((x, y, z));
+            while let Some(pos) = stack.pop() {
+                faces |= Face::of_position(pos);
+                for next in neighbors(pos) {
+                    if visited[index(next)] || subchunk.get(next).is_opaque() {
+                        continue;
+                    }
+                    visited[index(next)] = true;
+                    stack.push(next);
+                }
+            }
+
+            for face in Face::ALL {
+                if faces & face.bit() != 0 {
+                    visibility.rows[face as usize] |= faces;
+                }
+            }
+        }
+
+        visibility
+    }
+
+    /// Whether one can see through the subchunk from face `a` to face `b`.
+    pub fn is_connected(&self, a: Face, b: Face) -> bool {
+        self.rows[a as usize] & b.bit() != 0
+    }
+}
+
+fn neighbors((x, y, z): (usize, usize, usize)) -> impl Iterator<Item = (usize, usize, usize)> {
+    const DELTAS: [(i32, i32, i32); 6] = [
+        (-1, 0, 0),
+        (1, 0, 0),
+        (0, -1, 0),
+        (0, 1, 0),
+        (0, 0, -1),
+        (0, 0, 1),
+    ];
+    DELTAS.into_iter().filter_map(move |(dx, dy, dz)| {
+        let in_range = |v: i32| (0..16).contains(&v).then_some(v as usize);
+        Some((
+            in_range(x as i32 + dx)?,
+            in_range(y as i32 + dy)?,
+            in_range(z as i32 + dz)?,
+        ))
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::chunk::{Block, Chunk};
+
+    #[test]
+    fn test_empty_subchunk_is_fully_connected() {
+        let chunk = Chunk::default();
+        assert_eq!(Visibility::compute(chunk.subchunk(0)), Visibility::ALL);
+    }
+
+    #[test]
+    fn test_solid_subchunk_is_disconnected() {
+        let mut chunk = Chunk::default();
+        for (x, y, z) in iproduct!(0..16, 0..16, 0..16) {
+            chunk.set((x, y, z), Block::Grass);
+        }
+        assert_eq!(
+            Visibility::compute(chunk.subchunk(0)),
+            Visibility::default()
+        );
+    }
+
+    #[test]
+    fn test_wall_separates_faces() {
+        // A wall at x = 8 splits the subchunk into two halves
+        let mut chunk = Chunk::default();
+        for (y, z) in iproduct!(0..16, 0..16) {
+            chunk.set((8, y, z), Block::Grass);
+        }
+        let visibility = Visibility::compute(chunk.subchunk(0));
+        assert!(visibility.is_connected(Face::NegX, Face::PosY));
+        assert!(visibility.is_connected(Face::PosX, Face::NegZ));
+        assert!(visibility.is_connected(Face::PosY, Face::NegY));
+        assert!(!visibility.is_connected(Face::NegX, Face::PosX));
+    }
+}
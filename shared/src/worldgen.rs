@@ -0,0 +1,255 @@
+//! World generation.
+//!
+//! A [`WorldGenerator`] produces a chunk from nothing but its chunk coordinates, so chunks can be
+//! generated in any order and the result never depends on which chunks were generated before.
+
+use itertools::iproduct;
+use noise::{NoiseFn, OpenSimplex};
+
+use crate::chunk::{Block, Chunk};
+use crate::structure::Structure;
+use crate::tag::BlockTag;
+
+/// Terrain below this `y` is flooded with water.
+pub const WATER_LEVEL: usize = 32;
+
+/// Number of noise octaves summed for the terrain height.
+const OCTAVES: usize = 4;
+/// Horizontal scale of the first terrain octave, in blocks.
+const TERRAIN_SCALE: f64 = 64.0;
+/// Horizontal scale of the biome noise, in blocks.
+const BIOME_SCALE: f64 = 256.0;
+/// Number of soil blocks between the surface block and the stone below.
+const SOIL_DEPTH: usize = 3;
+/// How far a tree reaches from its trunk, i.e. how far into the neighboring chunks to look for
+/// trees that overlap the chunk being generated.
+const TREE_RADIUS: i64 = 2;
+
+const TREE: &str = include_str!("../structures/tree.txt");
+
+pub trait WorldGenerator: Send + Sync {
+    /// Generate the chunk at chunk coordinates `(cx, cz)`.
+    fn generate(&self, chunk_coords: (i64, i64)) -> Chunk;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Biome {
+    Plains,
+    Hills,
+    Desert,
+}
+
+impl Biome {
+    /// The surface block and the soil block below it.
+    fn surface_blocks(self) -> (Block, Block) {
+        match self {
+            Biome::Plains | Biome::Hills => (Block::Grass, Block::Dirt),
+            Biome::Desert => (Block::Sand, Block::Sand),
+        }
+    }
+
+    /// Chance of a tree growing on a column, in 1 / 1000.
+    fn tree_chance(self) -> u64 {
+        match self {
+            Biome::Plains => 4,
+            Biome::Hills => 12,
+            Biome::Desert => 0,
+        }
+    }
+}
+
+/// The default generator: octaves of simplex noise shaped by biomes, flooded up to
+/// [`WATER_LEVEL`], with trees on top.
+pub struct TerrainGenerator {
+    seed: u32,
+    terrain_noise: OpenSimplex,
+    biome_noise: OpenSimplex,
+    tree: Structure,
+}
+
+impl TerrainGenerator {
+    pub fn new(seed: u32) -> Self {
+        Self {
+            seed,
+            terrain_noise: OpenSimplex::new(seed),
+            biome_noise: OpenSimplex::new(seed.wrapping_add(1)),
+            tree: Structure::parse(TREE).expect("Built-in tree structure is invalid"),
+        }
+    }
+
+    /// Get the biome of the column at world coordinates `(x, z)`.
+    pub fn biome(&self, column: (i64, i64)) -> Biome {
+        let value = self.biome_value(column);
+        if value < -0.2 {
+            Biome::Desert
+        } else if value > 0.2 {
+            Biome::Hills
+        } else {
+            Biome::Plains
+        }
+    }
+
+    /// Get the terrain height of the column at world coordinates `(x, z)`, i.e. the `y` of the
+    /// lowest block above ground.
+    pub fn height(&self, (x, z): (i64, i64)) -> usize {
+        // Each octave has double the frequency and half the amplitude of the previous one, and is
+        // shifted so that the octaves don't line up at the origin
+        let mut value = 0.0;
+        let mut amplitude = 1.0;
+        let mut frequency = 1.0 / TERRAIN_SCALE;
+        for octave in 0..OCTAVES {
+            let shift = octave as f64 * 31.7;
+            value += amplitude
+                * self
+                    .terrain_noise
+                    .get([x as f64 * frequency + shift, z as f64 * frequency + shift]);
+            amplitude /= 2.0;
+            frequency *= 2.0;
+        }
+
+        let (offset, scale) = terrain_shape(self.biome_value((x, z)));
+        (WATER_LEVEL as f64 + offset + value * scale).clamp(1.0, 255.0) as usize
+    }
+
+    fn biome_value(&self, (x, z): (i64, i64)) -> f64 {
+        self.biome_noise
+            .get([x as f64 / BIOME_SCALE, z as f64 / BIOME_SCALE])
+    }
+
+    fn surface_blocks(&self, column: (i64, i64), height: usize) -> (Block, Block) {
+        if height <= WATER_LEVEL {
+            (Block::Sand, Block::Sand)
+        } else {
+            self.biome(column).surface_blocks()
+        }
+    }
+
+    /// Get the `y` to place a tree at on the column, if there is one.
+    fn tree_at(&self, column: (i64, i64)) -> Option<usize> {
+        let chance = self.biome(column).tree_chance();
+        if column_hash(self.seed, column) % 1000 >= chance {
+            return None;
+        }
+        let height = self.height(column);
+        let (surface, _) = self.surface_blocks(column, height);
+        surface.has_tag(BlockTag::Soil).then_some(height)
+    }
+}
+
+impl WorldGenerator for TerrainGenerator {
+    fn generate(&self, (cx, cz): (i64, i64)) -> Chunk {
+        let mut chunk = Chunk::default();
+
+        for (lx, lz) in iproduct!(0..16, 0..16) {
+            let column = (cx * 16 + lx as i64, cz * 16 + lz as i64);
+            let height = self.height(column);
+            let (surface, soil) = self.surface_blocks(column, height);
+
+            for y in 0..height.max(WATER_LEVEL) {
+                let block = if y >= height {
+                    Block::Water
+                } else if y + 1 == height {
+                    surface
+                } else if y + 1 + SOIL_DEPTH >= height {
+                    soil
+                } else {
+                    Block::Stone
+                };
+                chunk.set((lx, y, lz), block);
+            }
+        }
+
+        // Trees growing in neighboring chunks may reach into this one
+        for (x, z) in iproduct!(
+            cx * 16 - TREE_RADIUS..(cx + 1) * 16 + TREE_RADIUS,
+            cz * 16 - TREE_RADIUS..(cz + 1) * 16 + TREE_RADIUS
+        ) {
+            if let Some(y) = self.tree_at((x, z)) {
+                self.tree
+                    .place_in_chunk(&mut chunk, (cx, cz), (x, y as i64, z));
+            }
+        }
+
+        chunk
+    }
+}
+
+/// Get the height offset above [`WATER_LEVEL`] and the height scale of the terrain for a biome
+/// noise value. The shape is interpolated between biomes so that there are no cliffs at borders.
+fn terrain_shape(biome_value: f64) -> (f64, f64) {
+    const DESERT: (f64, f64) = (4.0, 3.0);
+    const PLAINS: (f64, f64) = (3.0, 8.0);
+    const HILLS: (f64, f64) = (10.0, 24.0);
+
+    let lerp =
+        |a: (f64, f64), b: (f64, f64), t: f64| (a.0 + (b.0 - a.0) * t, a.1 + (b.1 - a.1) * t);
+    let value = biome_value.clamp(-1.0, 1.0);
+    if value < 0.0 {
+        lerp(PLAINS, DESERT, -value)
+    } else {
+        lerp(PLAINS, HILLS, value)
+    }
+}
+
+/// Hash a column into a pseudo-random number, for decisions that should look random but be the
+/// same every time the column is generated.
+fn column_hash(seed: u32, (x, z): (i64, i64)) -> u64 {
+    let mut h = (seed as u64)
+        ^ (x as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15)
+        ^ (z as u64).wrapping_mul(0xc2b2_ae3d_27d4_eb4f);
+    // The splitmix64 finalizer
+    h = (h ^ (h >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    h = (h ^ (h >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    h ^ (h >> 31)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn same_blocks(a: &Chunk, b: &Chunk) -> bool {
+        iproduct!(0..16, 0..256, 0..16).all(|pos| a.get(pos) == b.get(pos))
+    }
+
+    #[test]
+    fn test_generate_deterministic() {
+        let chunk = TerrainGenerator::new(42).generate((3, -2));
+        assert!(same_blocks(
+            &chunk,
+            &TerrainGenerator::new(42).generate((3, -2))
+        ));
+        assert!(!same_blocks(
+            &chunk,
+            &TerrainGenerator::new(43).generate((3, -2))
+        ));
+    }
+
+    #[test]
+    fn test_generate_layers() {
+        let generator = TerrainGenerator::new(0);
+        let chunk = generator.generate((0, 0));
+        for (lx, lz) in iproduct!(0..16, 0..16) {
+            let height = generator.height((lx as i64, lz as i64));
+            assert_eq!(chunk.get((lx, 0, lz)), Block::Stone);
+            if height < WATER_LEVEL {
+                assert_eq!(chunk.get((lx, WATER_LEVEL - 1, lz)), Block::Water);
+                assert_eq!(chunk.get((lx, WATER_LEVEL, lz)), Block::Empty);
+            }
+            // Leaves of trees on lower neighboring columns may cut into the surface
+            let surface = chunk.get((lx, height - 1, lz));
+            assert!([Block::Grass, Block::Sand, Block::Leaves].contains(&surface));
+        }
+    }
+
+    #[test]
+    fn test_trees_cross_chunk_borders() {
+        let generator = TerrainGenerator::new(0);
+        // Find a tree next to a chunk border, and check its leaves in the neighboring chunk
+        let (x, z, y) = iproduct!(0..64 * 16, 0..16)
+            .map(|(x, z)| (x * 16 + 15, z))
+            .find_map(|(x, z)| generator.tree_at((x, z)).map(|y| (x, z, y)))
+            .expect("No tree along chunk borders");
+        let chunk = generator.generate((x.div_euclid(16) + 1, 0));
+        assert_eq!(chunk.get((0, y + 3, z as usize)), Block::Leaves);
+    }
+}